@@ -1,7 +1,8 @@
 use std::rc::Rc;
 use tempfile::NamedTempFile;
 use xdrfile::*;
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::io::{Seek, SeekFrom};
 use std::time::Duration;
 
 /// generate a temporary test trajectory of given length
@@ -15,6 +16,7 @@ fn gen_test_traj(num_atoms: usize, num_frames: usize) -> Result<NamedTempFile> {
         time: 1.0,
         box_vector: [[1.0, 2.0, 3.0], [2.0, 1.0, 3.0], [3.0, 2.0, 1.0]],
         coords: vec![[1.0, 1.1, 1.2]; num_atoms],
+        ..Default::default()
     };
 
     for _ in 0..num_frames {
@@ -70,6 +72,83 @@ fn bench_iterate_traj(c: &mut Criterion) {
     }));
 }
 
-criterion_group!(benches, bench_iterate_traj);
+// Decode the first frame of a trajectory over and over again
+fn decode_first_frame<T: Trajectory + Seek>(traj: &mut T, frame: &mut Frame) -> Result<()> {
+    traj.seek(SeekFrom::Start(0))
+        .expect("Could not seek to start of trajectory");
+    traj.read(frame)
+}
+
+fn bench_decode_frame(c: &mut Criterion) {
+    let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc").unwrap();
+    let mut trr = TRRTrajectory::open_read("tests/1l2y.trr").unwrap();
+    let num_atoms = xtc.get_num_atoms().unwrap();
+    let mut frame = Frame::with_len(num_atoms);
+
+    let mut group = c.benchmark_group("decode_frame");
+    group.throughput(Throughput::Elements(num_atoms as u64));
+    group.bench_function("decode_xtc_frame", |b| {
+        b.iter(|| decode_first_frame(black_box(&mut xtc), black_box(&mut frame)).unwrap())
+    });
+    group.bench_function("decode_trr_frame", |b| {
+        b.iter(|| decode_first_frame(black_box(&mut trr), black_box(&mut frame)).unwrap())
+    });
+}
+
+fn bench_iterate_fixture(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate_fixture");
+    group.throughput(Throughput::Elements(38));
+    group.bench_function("iterate_1l2y_xtc", |b| {
+        b.iter(|| {
+            let traj = XTCTrajectory::open_read(black_box("tests/1l2y.xtc")).unwrap();
+            assert_eq!(traj.into_iter().count(), 38);
+        })
+    });
+}
+
+// Compare the scalar coordinate transforms on Frame with the SIMD versions
+fn bench_transforms(c: &mut Criterion) {
+    let num_atoms = 100_000;
+    let mut frame = Frame::with_len(num_atoms);
+    for (i, x) in frame.coords.iter_mut().enumerate() {
+        *x = [i as f32 * 0.1, 1.0, -(i as f32)];
+    }
+    let offset = [0.1, 0.2, 0.3];
+    // Scaling by exactly one keeps the coordinates from over- or underflowing
+    let factor = 1.0;
+    let rotation = [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+
+    let mut group = c.benchmark_group("transforms");
+    group.throughput(Throughput::Elements(num_atoms as u64));
+    group.bench_function("translate_scalar", |b| {
+        b.iter(|| frame.translate(black_box(offset)))
+    });
+    group.bench_function("scale_scalar", |b| {
+        b.iter(|| frame.scale(black_box(factor)))
+    });
+    group.bench_function("rotate_scalar", |b| {
+        b.iter(|| frame.rotate(black_box(rotation)))
+    });
+    #[cfg(feature = "simd")]
+    {
+        group.bench_function("translate_simd", |b| {
+            b.iter(|| xdrfile::simd::translate(&mut frame.coords, black_box(offset)))
+        });
+        group.bench_function("scale_simd", |b| {
+            b.iter(|| xdrfile::simd::scale(&mut frame.coords, black_box(factor)))
+        });
+        group.bench_function("rotate_simd", |b| {
+            b.iter(|| xdrfile::simd::rotate(&mut frame.coords, black_box(rotation)))
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_iterate_traj,
+    bench_decode_frame,
+    bench_iterate_fixture,
+    bench_transforms
+);
 criterion_main!(benches);
 
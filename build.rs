@@ -5,6 +5,7 @@ use std::io::Result;
 
 fn main() -> Result<()> {
     // This builds gromacs' xdrfile library
+    println!("cargo:rerun-if-changed=external/xdrfile");
     let source_files = fs::read_dir("external/xdrfile/src")?
         .map(|r| r.map(|f| f.path()))
         .collect::<Result<Vec<_>>>()?;
@@ -258,7 +258,7 @@ static int do_htrn(XDRFILE *xd,mybool bRead,t_trnheader *sh,
             {
                 for(i=0; (i<sh->natoms); i++)
                     for(j=0; (j<DIM); j++)
-                        if (NULL != x)
+                        if (NULL != v)
                         {
                             dx[i*DIM+j] = v[i][j];
                         }
@@ -281,7 +281,7 @@ static int do_htrn(XDRFILE *xd,mybool bRead,t_trnheader *sh,
             {
                 for(i=0; (i<sh->natoms); i++)
                     for(j=0; (j<DIM); j++)
-                        if (NULL != x)
+                        if (NULL != f)
                         {
                             dx[i*DIM+j] = f[i][j];
                         }
@@ -384,7 +384,7 @@ static int do_htrn(XDRFILE *xd,mybool bRead,t_trnheader *sh,
             {
                 for(i=0; (i<sh->natoms); i++)
                     for(j=0; (j<DIM); j++)
-                        if (NULL != x)
+                        if (NULL != v)
                         {
                             fx[i*DIM+j] = v[i][j];
                         }
@@ -405,7 +405,7 @@ static int do_htrn(XDRFILE *xd,mybool bRead,t_trnheader *sh,
             {
                 for(i=0; (i<sh->natoms); i++)
                     for(j=0; (j<DIM); j++)
-                        if (NULL != x)
+                        if (NULL != f)
                         {
                             fx[i*DIM+j] = f[i][j];
                         }
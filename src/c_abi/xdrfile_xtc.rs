@@ -3,13 +3,13 @@ use super::xdrfile::*;
 extern "C" {
     pub fn read_xtc_natoms(
         fn_: *const ::std::os::raw::c_char,
-        natoms: *const ::std::os::raw::c_int,
+        natoms: *mut ::std::os::raw::c_int,
     ) -> ::std::os::raw::c_int;
 }
 extern "C" {
     pub fn read_xtc_nframes(
         fn_: *const ::std::os::raw::c_char,
-        nframes: *const ::std::os::raw::c_ulong,
+        nframes: *mut ::std::os::raw::c_ulong,
     ) -> ::std::os::raw::c_int;
 }
 extern "C" {
@@ -0,0 +1,353 @@
+//! Cross-checking of decoded frames against the C reference decoder
+use crate::c_abi::xdrfile::{self, Matrix, Rvec, XDRFILE};
+use crate::c_abi::{xdr_seek, xdrfile_trr, xdrfile_xtc};
+use crate::*;
+use std::os::raw::c_int;
+
+/// Check that `Trajectory::read` agrees with the C library on every frame of
+/// the trajectory at `path`
+///
+/// The file is read twice side by side: once through the trajectory types of
+/// this crate, and once with the plain `read_xtc` or `read_trr` functions of
+/// the bundled libxdrfile, which serve as the reference decoder. Step, time,
+/// box, coordinates, and for TRR lambda, velocities and forces must match
+/// exactly, as must which of the TRR blocks are present. This is meant for
+/// diagnosing files that seem to be decoded wrongly, not for routine use, as
+/// it reads everything twice. The format is detected from the start of the
+/// file, see `Format::detect`.
+///
+/// Returns `Error::CrossCheckMismatch` for the first value that differs, or
+/// if one decoder reaches the end of the file before the other. Errors from
+/// either decoder are returned as they are.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// debug_cross_check("tests/1l2y.xtc")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn debug_cross_check(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let format = Format::detect(path)?;
+    let mut traj = format.open(path, FileMode::Read)?;
+    let num_atoms = traj.get_num_atoms()?;
+    let mut reference = Reference::open(path, format, num_atoms)?;
+    let mut expected_frame = Frame::with_len(num_atoms);
+    let mut frame = Frame::with_len(num_atoms);
+    let mut index = 0;
+    loop {
+        let expected = match reference.read(&mut expected_frame)? {
+            true => Some(&expected_frame),
+            false => None,
+        };
+        let found = match traj.read(&mut frame) {
+            Ok(()) => Some(&frame),
+            Err(e) if e.is_eof() => None,
+            Err(e) => return Err(e),
+        };
+        match (expected, found) {
+            (None, None) => return Ok(()),
+            (Some(expected), Some(found)) => compare(index, expected, found)?,
+            (expected, _) => {
+                let describe = |more: bool| if more { "another frame" } else { "end of file" };
+                return Err(Error::CrossCheckMismatch {
+                    frame: index,
+                    field: "number of frames",
+                    reference: describe(expected.is_some()).to_string(),
+                    found: describe(expected.is_none()).to_string(),
+                });
+            }
+        }
+        index += 1;
+    }
+}
+
+/// A trajectory file opened directly with the C library
+struct Reference {
+    xdrfile: *mut XDRFILE,
+    format: Format,
+    num_atoms: usize,
+}
+
+impl Reference {
+    fn open(path: &Path, format: Format, num_atoms: usize) -> Result<Self> {
+        let path_p = path_to_cstring(path)?;
+        let mode_p = FileMode::Read.to_cstr().as_ptr();
+        let xdrfile = unsafe { xdrfile::xdrfile_open(path_p.as_ptr(), mode_p) };
+        if xdrfile.is_null() {
+            return Err((path, FileMode::Read).into());
+        }
+        Ok(Reference {
+            xdrfile,
+            format,
+            num_atoms,
+        })
+    }
+
+    /// Read the next frame into `frame`, returning false at the end of the file
+    ///
+    /// As for `TRRTrajectory`, TRR blocks that a frame does not have are
+    /// cleared, or set to None for velocities and forces, and a missing box
+    /// is zeroed. The buffers of `frame` are reused from one frame to the next.
+    fn read(&mut self, frame: &mut Frame) -> Result<bool> {
+        let task = ErrorTask::Read;
+        let num_atoms: c_int = to(self.num_atoms, task, "num_atoms")?;
+        let (has_coords, has_velocities, has_forces) = match self.format {
+            Format::Xtc => (true, false, false),
+            Format::Trr => match self.peek_trr_header() {
+                Some(h) => (h.x_size != 0, h.v_size != 0, h.f_size != 0),
+                None => (true, true, true),
+            },
+        };
+        for (values, present) in [
+            (&mut frame.velocities, has_velocities),
+            (&mut frame.forces, has_forces),
+        ] {
+            match values {
+                Some(values) if present => values.resize(self.num_atoms, [0.0; 3]),
+                None if present => *values = Some(vec![[0.0; 3]; self.num_atoms]),
+                _ => *values = None,
+            }
+        }
+        frame.coords.resize(self.num_atoms, [0.0; 3]);
+        frame.box_vector = [[0.0; 3]; 3];
+        let mut step: c_int = 0;
+        let box_p = &mut frame.box_vector as *mut Matrix;
+        let x_p = frame.coords.as_mut_ptr() as *mut Rvec;
+        let rvecs_p = |values: &mut Option<Vec<[f32; 3]>>| match values {
+            Some(values) => values.as_mut_ptr() as *mut Rvec,
+            None => std::ptr::null_mut(),
+        };
+        let code = match self.format {
+            Format::Xtc => {
+                let mut precision = 0.0;
+                unsafe {
+                    xdrfile_xtc::read_xtc(
+                        self.xdrfile,
+                        num_atoms,
+                        &mut step,
+                        &mut frame.time,
+                        box_p,
+                        x_p,
+                        &mut precision,
+                    )
+                }
+            }
+            Format::Trr => unsafe {
+                xdrfile_trr::read_trr(
+                    self.xdrfile,
+                    num_atoms,
+                    &mut step,
+                    &mut frame.time,
+                    &mut frame.lambda,
+                    box_p,
+                    x_p,
+                    rvecs_p(&mut frame.velocities),
+                    rvecs_p(&mut frame.forces),
+                )
+            },
+        };
+        if !has_coords {
+            frame.coords.clear();
+        }
+        match check_code(code, task) {
+            None => {
+                frame.step = to(step, task, "step")?;
+                Ok(true)
+            }
+            Some(e) if e.is_eof() => Ok(false),
+            Some(e) => Err(e),
+        }
+    }
+
+    /// Header of the next TRR frame, rewinding to its start afterwards, or
+    /// None if it cannot be read, in which case `read_trr` reports the error
+    fn peek_trr_header(&mut self) -> Option<xdrfile_trr::t_trnheader> {
+        let mut header = xdrfile_trr::t_trnheader::default();
+        unsafe {
+            let start = xdr_seek::xdr_tell(self.xdrfile);
+            let code = xdrfile_trr::do_trnheader(self.xdrfile, 1, &mut header);
+            if xdr_seek::xdr_seek(self.xdrfile, start, 0) != 0 {
+                return None;
+            }
+            check_code(code, ErrorTask::Read).map_or(Some(header), |_| None)
+        }
+    }
+}
+
+impl Drop for Reference {
+    fn drop(&mut self) {
+        unsafe {
+            xdrfile::xdrfile_close(self.xdrfile);
+        }
+    }
+}
+
+/// Fail with `Error::CrossCheckMismatch` for the first value of `found` that
+/// differs from the reference decoder's `expected`
+fn compare(index: usize, expected: &Frame, found: &Frame) -> Result<()> {
+    let mismatch = |field, reference: String, found: String| {
+        Err(Error::CrossCheckMismatch {
+            frame: index,
+            field,
+            reference,
+            found,
+        })
+    };
+    // Bitwise, so that NaNs compare equal and signed zeros do not
+    let same = |a: &[f32], b: &[f32]| {
+        a.iter()
+            .map(|x| x.to_bits())
+            .eq(b.iter().map(|x| x.to_bits()))
+    };
+
+    if expected.step != found.step {
+        return mismatch("step", expected.step.to_string(), found.step.to_string());
+    }
+    if !same(&[expected.time], &[found.time]) {
+        return mismatch("time", expected.time.to_string(), found.time.to_string());
+    }
+    if !same(&[expected.lambda], &[found.lambda]) {
+        return mismatch(
+            "lambda",
+            expected.lambda.to_string(),
+            found.lambda.to_string(),
+        );
+    }
+    if !same(
+        expected.box_vector.as_flattened(),
+        found.box_vector.as_flattened(),
+    ) {
+        return mismatch(
+            "box",
+            format!("{:?}", expected.box_vector),
+            format!("{:?}", found.box_vector),
+        );
+    }
+
+    let per_atom = [
+        ("coordinates", Some(&expected.coords), Some(&found.coords)),
+        (
+            "velocities",
+            expected.velocities.as_ref(),
+            found.velocities.as_ref(),
+        ),
+        ("forces", expected.forces.as_ref(), found.forces.as_ref()),
+    ];
+    for (field, expected, found) in per_atom {
+        let (expected, found) = match (expected, found) {
+            (Some(expected), Some(found)) => (expected, found),
+            (None, None) => continue,
+            (expected, found) => {
+                let describe = |present: bool| if present { "present" } else { "absent" };
+                return mismatch(
+                    field,
+                    describe(expected.is_some()).to_string(),
+                    describe(found.is_some()).to_string(),
+                );
+            }
+        };
+        if found.len() != expected.len() {
+            return mismatch(
+                field,
+                format!("{} atoms", expected.len()),
+                format!("{} atoms", found.len()),
+            );
+        }
+        let atom = (0..expected.len()).find(|&i| !same(&expected[i], &found[i]));
+        if let Some(atom) = atom {
+            return mismatch(
+                field,
+                format!("{:?} for atom {}", expected[atom], atom),
+                format!("{:?} for atom {}", found[atom], atom),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_debug_cross_check() -> Result<(), Box<dyn std::error::Error>> {
+        debug_cross_check("tests/1l2y.xtc")?;
+        debug_cross_check("tests/1l2y.trr")?;
+
+        // Velocities and forces, and a missing box
+        let tempfile = NamedTempFile::new()?;
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_blocks(TrrBlocks::X | TrrBlocks::V | TrrBlocks::F);
+        for step in 0..3 {
+            let frame = Frame {
+                step,
+                time: step as f32 * 0.5,
+                coords: vec![[1.0, -2.0, 3.5]; 4],
+                velocities: Some(vec![[0.25, 0.5, -1.0]; 4]),
+                forces: Some(vec![[-8.0, 0.0, 16.0]; 4]),
+                ..Default::default()
+            };
+            trr.write(&frame)?;
+        }
+        trr.flush()?;
+        debug_cross_check(tempfile.path())?;
+
+        // Coordinates only, and zero velocities that must not be mistaken for
+        // absent ones
+        for blocks in [TrrBlocks::X, TrrBlocks::BOX | TrrBlocks::X | TrrBlocks::V] {
+            let tempfile = NamedTempFile::new()?;
+            let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+            trr.set_blocks(blocks);
+            for step in 0..3 {
+                let frame = Frame {
+                    step,
+                    coords: vec![[1.0, -2.0, 3.5]; 4],
+                    velocities: Some(vec![[0.0; 3]; 4]),
+                    ..Default::default()
+                };
+                trr.write(&frame)?;
+            }
+            trr.flush()?;
+            debug_cross_check(tempfile.path())?;
+        }
+
+        // A difference is reported with the field and the first atom
+        let mut expected = Frame::with_len(2);
+        let mut found = expected.clone();
+        found.coords[1][2] = 1.0;
+        assert!(compare(0, &expected, &found).is_err());
+        assert_eq!(
+            compare(4, &expected, &found),
+            Err(Error::CrossCheckMismatch {
+                frame: 4,
+                field: "coordinates",
+                reference: "[0.0, 0.0, 0.0] for atom 1".to_string(),
+                found: "[0.0, 0.0, 1.0] for atom 1".to_string(),
+            })
+        );
+        // Zero velocities are not the same as none
+        expected.velocities = Some(vec![[0.0; 3]; 2]);
+        found.coords = expected.coords.clone();
+        assert_eq!(
+            compare(0, &expected, &found),
+            Err(Error::CrossCheckMismatch {
+                frame: 0,
+                field: "velocities",
+                reference: "present".to_string(),
+                found: "absent".to_string(),
+            })
+        );
+        found.velocities = expected.velocities.clone();
+        compare(0, &expected, &found)?;
+
+        assert!(matches!(
+            debug_cross_check("Cargo.toml"),
+            Err(Error::UnknownFormat { .. })
+        ));
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use crate::c_abi;
 use crate::FileMode;
+use crate::Format;
 use crate::Frame;
+use crate::XtcVersion;
 use std::error::Error as StdError;
 use std::path::{Path, PathBuf};
 
@@ -9,6 +11,12 @@ use std::path::{Path, PathBuf};
 pub enum Error {
     /// An error code from the C API
     CApiError { code: ErrorCode, task: ErrorTask },
+    /// An error code from the C API, caused by the OS error `errno`
+    OsError {
+        code: ErrorCode,
+        task: ErrorTask,
+        errno: i32,
+    },
     /// Passed in a frame of the wrong size
     WrongSizeFrame { expected: usize, found: usize },
     /// C API failed to open a file (No return code provided)
@@ -24,12 +32,97 @@ pub enum Error {
         value: String,
         target: &'static str,
     },
+    /// The operation is not supported by this trajectory format
+    Unsupported { operation: &'static str },
+    /// An atom range was reversed or exceeded the number of atoms
+    InvalidAtomRange {
+        start: usize,
+        end: usize,
+        num_atoms: usize,
+    },
+    /// Data required for an operation was not present in the frame
+    MissingData { name: &'static str },
+    /// A per-atom argument or buffer did not match the number of atoms
+    WrongLength {
+        name: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// An argument was outside the range of values it may take
+    InvalidArgument { name: &'static str, value: String },
+    /// The file has a valid header but reports zero atoms, which usually means it is corrupt
+    ZeroAtoms { path: PathBuf },
+    /// A frame number was not smaller than the number of frames in the trajectory
+    FrameOutOfRange { index: usize, num_frames: usize },
+    /// No frame of the trajectory has the time requested, or one close enough
+    /// to it; `range` holds the earliest and the latest time of any frame, or
+    /// None if the trajectory has no frames
+    TimeOutOfRange {
+        time: f32,
+        range: Option<(f32, f32)>,
+    },
+    /// The file is positioned somewhere other than the start of a frame
+    NotAtFrameStart { position: u64 },
+    /// The format of a file could not be determined from its extension
+    UnknownFormat { path: PathBuf },
+    /// A file does not have the format required
+    FormatMismatch {
+        path: PathBuf,
+        expected: Format,
+        found: Format,
+    },
+    /// A trajectory does not continue in time from the one before it
+    Discontinuity {
+        path: PathBuf,
+        previous_time: f32,
+        time: f32,
+    },
+    /// The box vectors are linearly dependent, so the box has no volume
+    SingularBox,
+    /// A coordinate is too large to be compressed with the XTC precision
+    CoordinateOutOfRange {
+        atom: usize,
+        value: f32,
+        precision: f32,
+    },
+    /// Reading a frame directly with the C library gave a different value,
+    /// see `debug_cross_check`
+    CrossCheckMismatch {
+        frame: usize,
+        field: &'static str,
+        reference: String,
+        found: String,
+    },
+    /// The file system does not have the space required for a write, see
+    /// `check_disk_space`
+    InsufficientSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+    /// Buffered frames could not be written out because the disk or quota is
+    /// full, as reported by the OS error `errno`
+    DiskFull { path: PathBuf, errno: i32 },
+    /// A frame read in strict mode failed one of its checks
+    NonStandardFrame { step: usize, reason: String },
+    /// An XTC frame is in a revision of the format that cannot be read
+    UnsupportedXtcVersion { version: XtcVersion },
+    /// Frame number `frame` has a different number of atoms than the first
+    /// frame of the file
+    ///
+    /// `frame` is None if the number of the frame is not known, which happens
+    /// after moving to it with `io::Seek` before the trajectory is indexed.
+    VariableAtomCount {
+        frame: Option<usize>,
+        expected: usize,
+        got: usize,
+    },
 }
 
 impl Error {
     /// Get the error code returned by the C API, if any
     pub fn code(&self) -> Option<ErrorCode> {
-        if let Error::CApiError { code, .. } = self {
+        if let Error::CApiError { code, .. } | Error::OsError { code, .. } = self {
             Some(*code)
         } else if let Some(e) = self.source() {
             e.downcast_ref::<Self>().and_then(Self::code)
@@ -40,8 +133,10 @@ impl Error {
 
     /// Get the task being attempted when the C API returned an error, if any
     pub fn task(&self) -> Option<ErrorTask> {
-        if let Error::CApiError { task, .. } = self {
+        if let Error::CApiError { task, .. } | Error::OsError { task, .. } = self {
             Some(*task)
+        } else if let Error::DiskFull { .. } = self {
+            Some(ErrorTask::Flush)
         } else if let Some(e) = self.source() {
             e.downcast_ref::<Self>().and_then(Self::task)
         } else {
@@ -49,9 +144,41 @@ impl Error {
         }
     }
 
+    /// Get the OS error that caused the C API to fail, if it is known
+    pub fn os_error(&self) -> Option<std::io::Error> {
+        if let Error::OsError { errno, .. } | Error::DiskFull { errno, .. } = self {
+            Some(std::io::Error::from_raw_os_error(*errno))
+        } else if let Some(e) = self.source() {
+            e.downcast_ref::<Self>().and_then(Self::os_error)
+        } else {
+            None
+        }
+    }
+
     /// True if the error is an end of file error, false otherwise
     pub fn is_eof(&self) -> bool {
-        self.code().map_or(false, |e| e.is_eof())
+        self.code().is_some_and(|e| e.is_eof())
+    }
+
+    /// True if a signal interrupted the call that failed (`EINTR`)
+    pub fn is_interrupted(&self) -> bool {
+        self.os_error()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::Interrupted)
+    }
+
+    /// True if the error may be transient, so that retrying could succeed
+    ///
+    /// Only errors caused by the OS are considered transient: interrupted
+    /// calls, temporarily unavailable resources, timeouts and I/O errors, as
+    /// seen on network filesystems. End of file and format errors are not.
+    pub fn is_retriable(&self) -> bool {
+        self.os_error().is_some_and(|e| {
+            use std::io::ErrorKind;
+            matches!(
+                e.kind(),
+                ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+            ) || e.raw_os_error() == Some(libc::EIO)
+        })
     }
 }
 
@@ -78,6 +205,20 @@ impl From<(ErrorCode, ErrorTask)> for Error {
     }
 }
 
+/// An error of the standard library, such as from `std::fs`, while doing `task`
+///
+/// Errors without an OS error code are reported as `EIO`.
+impl From<(std::io::Error, ErrorTask)> for Error {
+    fn from(value: (std::io::Error, ErrorTask)) -> Self {
+        let (e, task) = value;
+        Error::OsError {
+            code: ErrorCode::ExdrNr,
+            task,
+            errno: e.raw_os_error().unwrap_or(libc::EIO),
+        }
+    }
+}
+
 impl From<(&Path, FileMode)> for Error {
     fn from(value: (&Path, FileMode)) -> Self {
         let (path, mode) = value;
@@ -107,6 +248,12 @@ impl std::fmt::Display for Error {
                 task = task,
                 code = code
             ),
+            Error::OsError { task, errno, .. } => write!(
+                f,
+                "Error while {task}: {error}",
+                task = task,
+                error = std::io::Error::from_raw_os_error(*errno)
+            ),
             Error::WrongSizeFrame { expected, found } => write!(
                 f,
                 "Expected frame of size {:?}, found {:?}",
@@ -132,6 +279,135 @@ impl std::fmt::Display for Error {
                 value = value,
                 target = target
             ),
+            Error::Unsupported { operation } => write!(
+                f,
+                "{} is not supported for this trajectory format",
+                operation
+            ),
+            Error::InvalidAtomRange {
+                start,
+                end,
+                num_atoms,
+            } => write!(
+                f,
+                "Invalid atom range {}..{} for frame with {} atoms",
+                start, end, num_atoms
+            ),
+            Error::MissingData { name } => write!(f, "Frame does not contain {}", name),
+            Error::WrongLength {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Expected {} of length {}, found {}",
+                name, expected, found
+            ),
+            Error::InvalidArgument { name, value } => {
+                write!(f, "Invalid value {} for argument {}", value, name)
+            }
+            Error::ZeroAtoms { path } => {
+                write!(f, "Trajectory file at {:?} reports zero atoms", path)
+            }
+            Error::FrameOutOfRange { index, num_frames } => write!(
+                f,
+                "Frame {} is out of range for trajectory with {} frames",
+                index, num_frames
+            ),
+            Error::TimeOutOfRange { time, range } => match range {
+                Some((first, last)) => write!(
+                    f,
+                    "Time {} is outside the times {} to {} of the trajectory",
+                    time, first, last
+                ),
+                None => write!(f, "Time {} is out of range for an empty trajectory", time),
+            },
+            Error::NotAtFrameStart { position } => {
+                write!(f, "Position {} is not at the start of a frame", position)
+            }
+            Error::UnknownFormat { path } => {
+                write!(f, "Could not determine trajectory format of {:?}", path)
+            }
+            Error::FormatMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Expected {:?} to be in {:?} format, found {:?}",
+                path, expected, found
+            ),
+            Error::Discontinuity {
+                path,
+                previous_time,
+                time,
+            } => write!(
+                f,
+                "Trajectory {:?} starts at time {}, which does not follow time {}",
+                path, time, previous_time
+            ),
+            Error::SingularBox => write!(f, "Box is singular and has no volume"),
+            Error::CoordinateOutOfRange {
+                atom,
+                value,
+                precision,
+            } => write!(
+                f,
+                "Coordinate {} of atom {} is too large to compress with precision {}",
+                value, atom, precision
+            ),
+            Error::CrossCheckMismatch {
+                frame,
+                field,
+                reference,
+                found,
+            } => write!(
+                f,
+                "Frame {} has {} {}, but the C library reads {}",
+                frame, field, found, reference
+            ),
+            Error::InsufficientSpace {
+                path,
+                required,
+                available,
+            } => write!(
+                f,
+                "Writing {} bytes to {:?} needs more than the {} bytes available",
+                required, path, available
+            ),
+            Error::DiskFull { path, errno } => write!(
+                f,
+                "Could not flush trajectory {:?}: {}",
+                path,
+                std::io::Error::from_raw_os_error(*errno)
+            ),
+            Error::NonStandardFrame { step, reason } => {
+                write!(f, "Frame at step {} is not standard: {}", step, reason)
+            }
+            Error::UnsupportedXtcVersion { version } => write!(
+                f,
+                "XTC format revision {:?} (magic number {}) is not supported",
+                version,
+                version.magic()
+            ),
+            Error::VariableAtomCount {
+                frame: Some(frame),
+                expected,
+                got,
+            } => write!(
+                f,
+                "Frame {} has {} atoms, but the first frame has {}",
+                frame, got, expected
+            ),
+            Error::VariableAtomCount {
+                frame: None,
+                expected,
+                got,
+            } => write!(
+                f,
+                "A frame has {} atoms, but the first frame has {}",
+                got, expected
+            ),
         }
     }
 }
@@ -149,6 +425,16 @@ pub enum ErrorTask {
     Flush,
     /// A seek operation was being run on a file
     Seek,
+    /// The current position in a file was being queried
+    Tell,
+    /// The frames of a file were being indexed
+    BuildIndex,
+    /// The raw bytes of a file were being hashed
+    Digest,
+    /// A file was being closed
+    Close,
+    /// The free space for a file was being queried
+    QuerySpace,
 }
 
 impl std::fmt::Display for ErrorTask {
@@ -159,6 +445,11 @@ impl std::fmt::Display for ErrorTask {
             ErrorTask::Write => write!(f, "writing trajectory"),
             ErrorTask::Flush => write!(f, "flushing trajectory"),
             ErrorTask::Seek => write!(f, "seeking in trajectory"),
+            ErrorTask::Tell => write!(f, "querying position in trajectory"),
+            ErrorTask::BuildIndex => write!(f, "indexing trajectory"),
+            ErrorTask::Digest => write!(f, "hashing trajectory"),
+            ErrorTask::Close => write!(f, "closing trajectory"),
+            ErrorTask::QuerySpace => write!(f, "querying free disk space for trajectory"),
         }
     }
 }
@@ -244,6 +535,30 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_retriable() {
+        let os_error = |errno| Error::OsError {
+            code: ErrorCode::ExdrFloat,
+            task: ErrorTask::Read,
+            errno,
+        };
+        assert!(os_error(libc::EINTR).is_retriable());
+        assert!(os_error(libc::EIO).is_retriable());
+        assert!(os_error(libc::EAGAIN).is_retriable());
+        assert!(!os_error(libc::ENOSPC).is_retriable());
+        assert!(!os_error(libc::ENOENT).is_retriable());
+
+        let eof = Error::from((ErrorCode::ExdrEndOfFile, ErrorTask::Read));
+        assert!(!eof.is_retriable());
+        let magic = Error::from((ErrorCode::ExdrMagic, ErrorTask::Read));
+        assert!(!magic.is_retriable());
+        assert!(Error::CouldNotCheckNAtoms(Box::new(os_error(libc::EIO))).is_retriable());
+
+        assert!(os_error(libc::EINTR).is_interrupted());
+        assert!(!os_error(libc::EIO).is_interrupted());
+        assert!(!eof.is_interrupted());
+    }
+
     #[test]
     fn test_is_eof() {
         let error = Error::CApiError {
@@ -1,6 +1,12 @@
+use crate::{Error, ErrorTask, Result};
+use std::io::Write;
 use std::ops::{Index, IndexMut};
 
 /// A frame represents a single step in a trajectory.
+///
+/// The velocities, forces, occupancies, B-factors and atom and residue ids a
+/// frame has are kept aligned with its coordinates: the methods that add,
+/// remove, reorder or resize atoms apply the same change to all of them.
 #[derive(Clone, Debug)]
 pub struct Frame {
     /// Trajectory step
@@ -14,6 +20,73 @@ pub struct Frame {
 
     /// 3D coordinates for N atoms where N is num_atoms
     pub coords: Vec<[f32; 3]>,
+
+    /// Velocities for N atoms, if present in the trajectory (TRR only)
+    pub velocities: Option<Vec<[f32; 3]>>,
+
+    /// Forces for N atoms, if present in the trajectory (TRR only)
+    pub forces: Option<Vec<[f32; 3]>>,
+
+    /// Free energy coupling parameter lambda (TRR only)
+    ///
+    /// The TRR header has room for a single lambda value. GROMACS writes its
+    /// scalar lambda here even when free energy calculations use separate
+    /// lambda components (coulomb, van der Waals, bonded and so on); those are
+    /// not stored in trajectory files and must be read from the energy file.
+    pub lambda: f32,
+
+    /// Occupancy of each of the N atoms, for writing structure files
+    ///
+    /// XTC and TRR do not store occupancies, so this is never read from or
+    /// written to a trajectory, and reading into a frame leaves it untouched.
+    /// It is kept aligned with the coordinates when atoms are filtered, added,
+    /// removed or the frame is resized, so that it survives conversion
+    /// pipelines. Atoms added without a value get an occupancy of 1.
+    pub occupancies: Option<Vec<f32>>,
+
+    /// B-factor (temperature factor) of each of the N atoms
+    ///
+    /// Like `occupancies`, this is not stored in trajectories. Atoms added
+    /// without a value get a B-factor of 0.
+    pub b_factors: Option<Vec<f32>>,
+
+    /// Topology index of each of the N atoms, such as its serial number
+    ///
+    /// Trajectories do not store atom or residue indices either, so these are
+    /// only ever filled in by the user, for example from a topology file, to
+    /// keep track of which atoms remain after taking subsets for selections
+    /// or conversion to structure files. They are kept aligned with the
+    /// coordinates like `occupancies`. Atoms added without a value get 0.
+    pub atom_ids: Option<Vec<i32>>,
+
+    /// Index of the residue each of the N atoms belongs to, see `atom_ids`
+    pub residue_ids: Option<Vec<i32>>,
+}
+
+/// Point to move to the origin or the box center, for `Frame::center`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CenterMode {
+    /// Move the geometric center of all atoms to the origin
+    Origin,
+    /// Move the geometric center of all atoms to the center of the box
+    BoxCenter,
+    /// Move the atom with this index to the origin
+    Atom(usize),
+}
+
+/// Shape of a set of atoms, from the eigenvalues of its gyration tensor
+///
+/// See `Frame::shape_descriptors` for the definitions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeDescriptors {
+    /// Radius of gyration, in the units of the coordinates
+    pub radius_of_gyration: f32,
+    /// Deviation from spherical symmetry, in squared units of the coordinates
+    pub asphericity: f32,
+    /// Deviation from cylindrical symmetry, in squared units of the coordinates
+    pub acylindricity: f32,
+    /// Relative shape anisotropy, from 0 for a sphere to 1 for a line
+    pub relative_shape_anisotropy: f32,
 }
 
 impl Default for Frame {
@@ -23,6 +96,13 @@ impl Default for Frame {
             time: 0.0,
             box_vector: [[0.0; 3]; 3],
             coords: Vec::with_capacity(0),
+            velocities: None,
+            forces: None,
+            lambda: 0.0,
+            occupancies: None,
+            b_factors: None,
+            atom_ids: None,
+            residue_ids: None,
         }
     }
 }
@@ -41,15 +121,76 @@ impl Frame {
         }
     }
 
+    /// Creates a frame from separate x, y and z coordinate arrays
+    ///
+    /// This is the inverse of `to_soa`. All three arrays must have the same
+    /// length.
+    pub fn from_soa(x: &[f32], y: &[f32], z: &[f32]) -> Result<Frame> {
+        for (name, other) in [("y", y), ("z", z)].iter() {
+            if other.len() != x.len() {
+                return Err(Error::WrongLength {
+                    name,
+                    expected: x.len(),
+                    found: other.len(),
+                });
+            }
+        }
+        Ok(Frame {
+            coords: x
+                .iter()
+                .zip(y)
+                .zip(z)
+                .map(|((&x, &y), &z)| [x, y, z])
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Split the coordinates into separate x, y and z arrays
+    /// (structure-of-arrays layout)
+    pub fn to_soa(&self) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        let mut x = Vec::with_capacity(self.len());
+        let mut y = Vec::with_capacity(self.len());
+        let mut z = Vec::with_capacity(self.len());
+        for xyz in self.coords.iter() {
+            x.push(xyz[0]);
+            y.push(xyz[1]);
+            z.push(xyz[2]);
+        }
+        (x, y, z)
+    }
+
+    /// The coordinates widened to double precision
+    ///
+    /// This only changes the type, for double precision code downstream: the
+    /// values are exactly the single precision ones, with no precision
+    /// recovered from the file.
+    pub fn coords_f64(&self) -> Vec<[f64; 3]> {
+        self.coords.iter().map(|x| x.map(f64::from)).collect()
+    }
+
+    /// The box vectors widened to double precision, see `coords_f64`
+    pub fn box_vector_f64(&self) -> [[f64; 3]; 3] {
+        self.box_vector.map(|row| row.map(f64::from))
+    }
+
     /// Filters the frame by removing all atoms not matching the given indeces.
     pub fn filter_coords(self: &mut Frame, indices: &[usize]) {
-        self.coords = self
-            .coords
-            .iter()
-            .enumerate()
-            .filter(|(i, _)| indices.contains(i))
-            .map(|(_, elem)| *elem)
-            .collect();
+        fn filter<T: Copy>(values: &[T], indices: &[usize]) -> Vec<T> {
+            values
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| indices.contains(i))
+                .map(|(_, elem)| *elem)
+                .collect()
+        }
+        self.coords = filter(&self.coords, indices);
+        self.velocities = self.velocities.as_ref().map(|v| filter(v, indices));
+        self.forces = self.forces.as_ref().map(|f| filter(f, indices));
+        self.occupancies = self.occupancies.as_ref().map(|o| filter(o, indices));
+        self.b_factors = self.b_factors.as_ref().map(|b| filter(b, indices));
+        self.atom_ids = self.atom_ids.as_ref().map(|a| filter(a, indices));
+        self.residue_ids = self.residue_ids.as_ref().map(|r| filter(r, indices));
     }
 
     /// Length of the frame (number of atoms)
@@ -64,7 +205,1227 @@ impl Frame {
 
     /// Resize the frame to have exactly `num_atoms` atoms, filling coords with zeros if necessary
     pub fn resize(&mut self, num_atoms: usize) {
-        self.coords.resize(num_atoms, [0.0; 3])
+        self.coords.resize(num_atoms, [0.0; 3]);
+        for values in self.velocities.iter_mut().chain(self.forces.iter_mut()) {
+            values.resize(num_atoms, [0.0; 3]);
+        }
+        for (values, default) in self.atom_scalars_mut() {
+            values.resize(num_atoms, default);
+        }
+        for ids in self.atom_ids_mut() {
+            ids.resize(num_atoms, 0);
+        }
+    }
+
+    /// Occupancies and B-factors the frame has, with the value for new atoms
+    fn atom_scalars_mut(&mut self) -> impl Iterator<Item = (&mut Vec<f32>, f32)> {
+        let occupancies = self.occupancies.iter_mut().map(|o| (o, 1.0));
+        let b_factors = self.b_factors.iter_mut().map(|b| (b, 0.0));
+        occupancies.chain(b_factors)
+    }
+
+    /// Atom and residue ids the frame has
+    fn atom_ids_mut(&mut self) -> impl Iterator<Item = &mut Vec<i32>> {
+        self.atom_ids.iter_mut().chain(self.residue_ids.iter_mut())
+    }
+
+    /// Add an atom to the end of the frame
+    ///
+    /// If the frame has velocities (or forces) but none is given for the new
+    /// atom, it gets zero velocity (or force). If one is given but the frame
+    /// has none yet, the existing atoms get zeros so that all per-atom vectors
+    /// stay the same length. Occupancies, B-factors and ids get the default
+    /// value given on their fields.
+    pub fn append_atom(
+        &mut self,
+        coord: [f32; 3],
+        velocity: Option<[f32; 3]>,
+        force: Option<[f32; 3]>,
+    ) {
+        let num_atoms = self.len();
+        for (values, value) in [(&mut self.velocities, velocity), (&mut self.forces, force)] {
+            match (values, value) {
+                (Some(values), value) => values.push(value.unwrap_or([0.0; 3])),
+                (values @ None, Some(value)) => {
+                    let mut new = vec![[0.0; 3]; num_atoms];
+                    new.push(value);
+                    *values = Some(new);
+                }
+                (None, None) => {}
+            }
+        }
+        for (values, default) in self.atom_scalars_mut() {
+            values.push(default);
+        }
+        for ids in self.atom_ids_mut() {
+            ids.push(0);
+        }
+        self.coords.push(coord);
+    }
+
+    /// Remove the atom at `index`
+    ///
+    /// Later atoms are shifted down by one. Returns `Error::InvalidAtomRange`
+    /// if `index` is out of range.
+    pub fn remove_atom(&mut self, index: usize) -> Result<()> {
+        if index >= self.len() {
+            return Err(Error::InvalidAtomRange {
+                start: index,
+                end: index + 1,
+                num_atoms: self.len(),
+            });
+        }
+        self.coords.remove(index);
+        for values in self.velocities.iter_mut().chain(self.forces.iter_mut()) {
+            if index < values.len() {
+                values.remove(index);
+            }
+        }
+        for (values, _) in self.atom_scalars_mut() {
+            if index < values.len() {
+                values.remove(index);
+            }
+        }
+        for ids in self.atom_ids_mut() {
+            if index < ids.len() {
+                ids.remove(index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep only the atoms whose index satisfies `keep`, in their original order
+    ///
+    /// Unlike calling `remove_atom` repeatedly, this takes a single pass over
+    /// the atoms, however many are removed.
+    pub fn retain_atoms(&mut self, keep: impl Fn(usize) -> bool) {
+        fn retain<T>(values: &mut Vec<T>, mask: &[bool]) {
+            let mut i = 0;
+            values.retain(|_| {
+                i += 1;
+                mask.get(i - 1).copied().unwrap_or(true)
+            });
+        }
+        let mask: Vec<bool> = (0..self.len()).map(keep).collect();
+        retain(&mut self.coords, &mask);
+        for values in self.velocities.iter_mut().chain(self.forces.iter_mut()) {
+            retain(values, &mask);
+        }
+        for (values, _) in self.atom_scalars_mut() {
+            retain(values, &mask);
+        }
+        for ids in self.atom_ids_mut() {
+            retain(ids, &mask);
+        }
+    }
+
+    /// Reorder the atoms so that atom `i` becomes the atom that was at
+    /// `permutation[i]`
+    ///
+    /// `permutation` must contain every index from 0 to the number of atoms
+    /// exactly once: otherwise this returns `Error::WrongLength`,
+    /// `Error::InvalidAtomRange` for an index that is out of range, or
+    /// `Error::InvalidArgument` for a repeated index, and the frame is left
+    /// unchanged.
+    pub fn reorder(&mut self, permutation: &[usize]) -> Result<()> {
+        fn gather<T: Copy>(values: &mut Vec<T>, permutation: &[usize]) {
+            if values.len() == permutation.len() {
+                *values = permutation.iter().map(|&i| values[i]).collect();
+            }
+        }
+        let num_atoms = self.len();
+        if permutation.len() != num_atoms {
+            return Err(Error::WrongLength {
+                name: "permutation",
+                expected: num_atoms,
+                found: permutation.len(),
+            });
+        }
+        let mut seen = vec![false; num_atoms];
+        for &i in permutation {
+            match seen.get_mut(i) {
+                None => {
+                    return Err(Error::InvalidAtomRange {
+                        start: i,
+                        end: i + 1,
+                        num_atoms,
+                    })
+                }
+                Some(true) => {
+                    return Err(Error::InvalidArgument {
+                        name: "permutation",
+                        value: format!("index {} repeated", i),
+                    })
+                }
+                Some(seen) => *seen = true,
+            }
+        }
+
+        gather(&mut self.coords, permutation);
+        for values in self.velocities.iter_mut().chain(self.forces.iter_mut()) {
+            gather(values, permutation);
+        }
+        for (values, _) in self.atom_scalars_mut() {
+            gather(values, permutation);
+        }
+        for ids in self.atom_ids_mut() {
+            gather(ids, permutation);
+        }
+        Ok(())
+    }
+
+    /// Translate all atoms by `offset`
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        for x in self.coords.iter_mut() {
+            x[0] += offset[0];
+            x[1] += offset[1];
+            x[2] += offset[2];
+        }
+    }
+
+    /// Translate all atoms to center them according to `mode`
+    ///
+    /// The geometric center is the center of mass with all atoms weighted
+    /// equally. Fails if `mode` is `CenterMode::Atom` with an index beyond the
+    /// number of atoms.
+    pub fn center(&mut self, mode: CenterMode) -> Result<()> {
+        let center = match mode {
+            CenterMode::Origin => self.center_of_mass(None)?,
+            CenterMode::BoxCenter => {
+                let com = self.center_of_mass(None)?;
+                let b = &self.box_vector;
+                // The box center is half the sum of the box vectors
+                [0, 1, 2].map(|k| com[k] - 0.5 * (b[0][k] + b[1][k] + b[2][k]))
+            }
+            CenterMode::Atom(index) => *self.coords.get(index).ok_or(Error::InvalidAtomRange {
+                start: index,
+                end: index + 1,
+                num_atoms: self.len(),
+            })?,
+        };
+        self.translate(center.map(|x| -x));
+        Ok(())
+    }
+
+    /// Wrap all atoms into the periodic cell centered on `center`
+    ///
+    /// Every atom is moved by whole box vectors to its periodic image closest
+    /// to `center`, so that a molecule of interest around that point stays in
+    /// one piece and the rest of the system is arranged around it, as for
+    /// visualization after `center`. As for the other periodic operations,
+    /// the box is assumed to be lower-triangular, so the resulting cell is the
+    /// triclinic box shifted to be centered on `center`, and dimensions with a
+    /// box length of zero are left untouched.
+    pub fn wrap_around(&mut self, center: [f32; 3]) {
+        for i in 0..self.len() {
+            let x = self.coords[i];
+            let d = self.minimum_image([x[0] - center[0], x[1] - center[1], x[2] - center[2]]);
+            self.coords[i] = [center[0] + d[0], center[1] + d[1], center[2] + d[2]];
+        }
+    }
+
+    /// Scale all coordinates and the box by `factor`, e.g. to convert units
+    pub fn scale(&mut self, factor: f32) {
+        for x in self.coords.iter_mut() {
+            x[0] *= factor;
+            x[1] *= factor;
+            x[2] *= factor;
+        }
+        for v in self.box_vector.iter_mut() {
+            v[0] *= factor;
+            v[1] *= factor;
+            v[2] *= factor;
+        }
+    }
+
+    /// Rotate all atoms about the origin by the rotation matrix `rotation`
+    ///
+    /// Each coordinate `x` is replaced by the matrix product `rotation · x`.
+    /// The box is left untouched, as rotating it would generally break the
+    /// lower-triangular form GROMACS requires.
+    pub fn rotate(&mut self, rotation: [[f32; 3]; 3]) {
+        let r = rotation;
+        for x in self.coords.iter_mut() {
+            *x = [
+                r[0][0] * x[0] + r[0][1] * x[1] + r[0][2] * x[2],
+                r[1][0] * x[0] + r[1][1] * x[1] + r[1][2] * x[2],
+                r[2][0] * x[0] + r[2][1] * x[1] + r[2][2] * x[2],
+            ];
+        }
+    }
+
+    /// Center of mass of the frame's atoms
+    ///
+    /// All atoms are weighted equally if `masses` is `None`. Coordinates are
+    /// used as they are, without making molecules whole across the periodic
+    /// boundary.
+    pub fn center_of_mass(&self, masses: Option<&[f32]>) -> Result<[f32; 3]> {
+        Ok(self.center_of_mass_f64(masses)?.map(|x| x as f32))
+    }
+
+    /// Center of mass of the frame's atoms, in double precision
+    ///
+    /// The sums are accumulated in double precision either way; this skips
+    /// the rounding of the result to single precision done by `center_of_mass`.
+    pub fn center_of_mass_f64(&self, masses: Option<&[f32]>) -> Result<[f64; 3]> {
+        if let Some(masses) = masses {
+            if masses.len() != self.len() {
+                return Err(Error::WrongLength {
+                    name: "masses",
+                    expected: self.len(),
+                    found: masses.len(),
+                });
+            }
+        }
+
+        let mut sum = [0.0_f64; 3];
+        let mut total = 0.0_f64;
+        for (i, x) in self.coords.iter().enumerate() {
+            let m = masses.map_or(1.0, |m| f64::from(m[i]));
+            for (s, xi) in sum.iter_mut().zip(x) {
+                *s += m * f64::from(*xi);
+            }
+            total += m;
+        }
+        if total == 0.0 {
+            return Ok([0.0; 3]);
+        }
+        Ok(sum.map(|s| s / total))
+    }
+
+    /// Moment of inertia tensor of the frame's atoms about their center of mass
+    ///
+    /// All atoms have unit mass if `masses` is `None`. As for
+    /// `center_of_mass`, molecules are not made whole across the periodic
+    /// boundary first.
+    pub fn inertia_tensor(&self, masses: Option<&[f32]>) -> Result<[[f32; 3]; 3]> {
+        let tensor = self.inertia_tensor_f64(masses)?;
+        Ok(tensor.map(|row| row.map(|x| x as f32)))
+    }
+
+    /// Principal moments of inertia and the corresponding principal axes
+    ///
+    /// Returns the eigenvalues of `inertia_tensor` in ascending order, and the
+    /// unit eigenvectors as rows in the same order. `axes[0]` has the smallest
+    /// moment, so it is the long axis of an elongated molecule. The sign of
+    /// each axis is arbitrary.
+    ///
+    /// ```rust
+    /// use xdrfile::Frame;
+    ///
+    /// # fn main() -> xdrfile::Result<()> {
+    /// let mut frame = Frame::with_len(3);
+    /// frame[0] = [0.0, 0.0, -1.0];
+    /// frame[2] = [0.0, 0.0, 1.0];
+    /// let (moments, axes) = frame.principal_axes(None)?;
+    /// assert!(moments[0].abs() < 1e-6);
+    /// assert!((axes[0][2].abs() - 1.0).abs() < 1e-6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn principal_axes(&self, masses: Option<&[f32]>) -> Result<([f32; 3], [[f32; 3]; 3])> {
+        let (values, vectors) = symmetric_eigen(self.inertia_tensor_f64(masses)?);
+        let mut order = [0, 1, 2];
+        order.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+        let moments = order.map(|i| values[i] as f32);
+        // The eigenvectors are the columns of `vectors`
+        let axes = order.map(|i| [vectors[0][i], vectors[1][i], vectors[2][i]].map(|x| x as f32));
+        Ok((moments, axes))
+    }
+
+    /// Moment of inertia tensor in double precision, see `inertia_tensor`
+    pub fn inertia_tensor_f64(&self, masses: Option<&[f32]>) -> Result<[[f64; 3]; 3]> {
+        let com = self.center_of_mass_f64(masses)?;
+        let mut tensor = [[0.0_f64; 3]; 3];
+        for (i, x) in self.coords.iter().enumerate() {
+            let m = masses.map_or(1.0, |m| f64::from(m[i]));
+            let r = [0, 1, 2].map(|k| f64::from(x[k]) - com[k]);
+            let r2 = r[0] * r[0] + r[1] * r[1] + r[2] * r[2];
+            for (j, row) in tensor.iter_mut().enumerate() {
+                for (k, t) in row.iter_mut().enumerate() {
+                    let delta = if j == k { r2 } else { 0.0 };
+                    *t += m * (delta - r[j] * r[k]);
+                }
+            }
+        }
+        Ok(tensor)
+    }
+
+    /// Gyration tensor of the frame's atoms about their center of mass
+    ///
+    /// This is `S = sum(m * r r^T) / sum(m)`, with `r` the position of each
+    /// atom relative to the center of mass; its trace is the squared radius
+    /// of gyration. All atoms have unit mass if `masses` is `None`. As for
+    /// `center_of_mass`, molecules are not made whole across the periodic
+    /// boundary first.
+    pub fn gyration_tensor(&self, masses: Option<&[f32]>) -> Result<[[f32; 3]; 3]> {
+        let tensor = self.gyration_tensor_f64(masses)?;
+        Ok(tensor.map(|row| row.map(|x| x as f32)))
+    }
+
+    /// Radius of gyration and shape descriptors of the frame's atoms
+    ///
+    /// With the eigenvalues `l1 <= l2 <= l3` of `gyration_tensor`:
+    ///
+    /// - the radius of gyration is `Rg = sqrt(l1 + l2 + l3)`
+    /// - the asphericity is `b = l3 - (l1 + l2) / 2`, which is zero if the
+    ///   distribution of atoms is spherically symmetric
+    /// - the acylindricity is `c = l2 - l1`, which is zero if it is
+    ///   cylindrically symmetric
+    /// - the relative shape anisotropy is `k2 = (b^2 + 3 c^2 / 4) / Rg^4`,
+    ///   from 0 for a spherically symmetric distribution to 1 for atoms on
+    ///   a line, or 0 if all atoms are at the same position
+    ///
+    /// ```rust
+    /// use xdrfile::Frame;
+    ///
+    /// # fn main() -> xdrfile::Result<()> {
+    /// let mut frame = Frame::with_len(2);
+    /// frame[0] = [-1.0, 0.0, 0.0];
+    /// frame[1] = [1.0, 0.0, 0.0];
+    /// let shape = frame.shape_descriptors(None)?;
+    /// assert_eq!(shape.radius_of_gyration, 1.0);
+    /// assert_eq!(shape.relative_shape_anisotropy, 1.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shape_descriptors(&self, masses: Option<&[f32]>) -> Result<ShapeDescriptors> {
+        let (mut l, _) = symmetric_eigen(self.gyration_tensor_f64(masses)?);
+        l.sort_by(f64::total_cmp);
+        let rg2 = l[0] + l[1] + l[2];
+        let b = l[2] - 0.5 * (l[0] + l[1]);
+        let c = l[1] - l[0];
+        let k2 = if rg2 > 0.0 {
+            (b * b + 0.75 * c * c) / (rg2 * rg2)
+        } else {
+            0.0
+        };
+        Ok(ShapeDescriptors {
+            radius_of_gyration: rg2.max(0.0).sqrt() as f32,
+            asphericity: b as f32,
+            acylindricity: c as f32,
+            relative_shape_anisotropy: k2 as f32,
+        })
+    }
+
+    /// Gyration tensor in double precision, see `gyration_tensor`
+    pub fn gyration_tensor_f64(&self, masses: Option<&[f32]>) -> Result<[[f64; 3]; 3]> {
+        let com = self.center_of_mass_f64(masses)?;
+        let mut tensor = [[0.0_f64; 3]; 3];
+        let mut total = 0.0_f64;
+        for (i, x) in self.coords.iter().enumerate() {
+            let m = masses.map_or(1.0, |m| f64::from(m[i]));
+            let r = [0, 1, 2].map(|k| f64::from(x[k]) - com[k]);
+            for (j, row) in tensor.iter_mut().enumerate() {
+                for (k, t) in row.iter_mut().enumerate() {
+                    *t += m * r[j] * r[k];
+                }
+            }
+            total += m;
+        }
+        if total == 0.0 {
+            return Ok([[0.0; 3]; 3]);
+        }
+        Ok(tensor.map(|row| row.map(|t| t / total)))
+    }
+
+    /// Kinetic energy of the frame's atoms, `0.5 * sum(m * v^2)`
+    ///
+    /// With GROMACS units, masses in atomic mass units and velocities in
+    /// nm/ps, this is in kJ/mol. Returns `Error::MissingData` if the frame has
+    /// no velocities, and `Error::WrongLength` if `masses` does not have one
+    /// entry per atom.
+    pub fn kinetic_energy(&self, masses: &[f32]) -> Result<f32> {
+        let velocities = self
+            .velocities
+            .as_ref()
+            .ok_or(Error::MissingData { name: "velocities" })?;
+        if masses.len() != velocities.len() {
+            return Err(Error::WrongLength {
+                name: "masses",
+                expected: velocities.len(),
+                found: masses.len(),
+            });
+        }
+        let twice: f64 = velocities
+            .iter()
+            .zip(masses)
+            .map(|(v, &m)| {
+                let v = v.map(f64::from);
+                f64::from(m) * (v[0] * v[0] + v[1] * v[1] + v[2] * v[2])
+            })
+            .sum();
+        Ok((0.5 * twice) as f32)
+    }
+
+    /// Temperature in kelvin from the kinetic energy, by equipartition
+    ///
+    /// This is `2 * kinetic_energy / (dof * k_B)`, with the Boltzmann constant
+    /// in kJ/mol/K to match GROMACS units, see `kinetic_energy`. `dof` is the
+    /// number of degrees of freedom: `3 * N` for N free atoms, less 3 if the
+    /// center of mass motion is removed and 1 for each constraint. Returns
+    /// `Error::InvalidArgument` if `dof` is not positive, and the errors of
+    /// `kinetic_energy`.
+    pub fn temperature(&self, masses: &[f32], dof: f32) -> Result<f32> {
+        /// Boltzmann constant in kJ/mol/K
+        const BOLTZMANN: f64 = 0.008_314_462_618;
+        if !(dof > 0.0 && dof.is_finite()) {
+            return Err(Error::InvalidArgument {
+                name: "dof",
+                value: dof.to_string(),
+            });
+        }
+        let energy = f64::from(self.kinetic_energy(masses)?);
+        Ok((2.0 * energy / (f64::from(dof) * BOLTZMANN)) as f32)
+    }
+
+    /// Box matrix for a cell given by its edge lengths and angles in degrees
+    ///
+    /// `lengths` are `[a, b, c]` and `angles` are `[alpha, beta, gamma]`, where
+    /// alpha is the angle between b and c, beta between a and c, and gamma
+    /// between a and b, as used in crystallography. The result follows the
+    /// GROMACS convention of a lower-triangular matrix: a lies along x and b
+    /// in the xy plane. Angles that do not describe a valid cell give NaN
+    /// entries. This is the inverse of `box_lengths` and `box_angles`.
+    pub fn box_matrix_from_lengths_angles(lengths: [f32; 3], angles: [f32; 3]) -> [[f32; 3]; 3] {
+        // cos(90 degrees) is not exactly zero in floating point; keep rectangular boxes exact
+        let cos = |degrees: f32| {
+            if degrees == 90.0 {
+                0.0
+            } else {
+                f64::from(degrees).to_radians().cos()
+            }
+        };
+        let [a, b, c] = [
+            f64::from(lengths[0]),
+            f64::from(lengths[1]),
+            f64::from(lengths[2]),
+        ];
+        let (cos_alpha, cos_beta, cos_gamma) = (cos(angles[0]), cos(angles[1]), cos(angles[2]));
+        let sin_gamma = (1.0 - cos_gamma * cos_gamma).sqrt();
+
+        let cx = c * cos_beta;
+        let cy = c * (cos_alpha - cos_beta * cos_gamma) / sin_gamma;
+        let cz = (c * c - cx * cx - cy * cy).sqrt();
+        [
+            [a as f32, 0.0, 0.0],
+            [(b * cos_gamma) as f32, (b * sin_gamma) as f32, 0.0],
+            [cx as f32, cy as f32, cz as f32],
+        ]
+    }
+
+    /// Determinant of the box matrix, whose rows are the box vectors
+    ///
+    /// This is the volume of the box for a right-handed set of box vectors,
+    /// which includes all GROMACS boxes.
+    pub fn box_determinant(&self) -> f32 {
+        determinant(&self.box_vector.map(|row| row.map(f64::from))) as f32
+    }
+
+    /// Inverse of the box matrix, whose rows are the box vectors
+    ///
+    /// Fails with `Error::SingularBox` rather than producing infinities if the
+    /// box has no volume, as for a frame without a box.
+    pub fn box_inverse(&self) -> Result<[[f32; 3]; 3]> {
+        let m = self.box_vector.map(|row| row.map(f64::from));
+        let det = determinant(&m);
+        // Relative to the box size, so tiny but valid boxes are still accepted
+        let scale: f64 = m
+            .iter()
+            .map(|v| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt())
+            .product();
+        if det.is_nan() || det.abs() <= f64::from(f32::EPSILON) * scale {
+            return Err(Error::SingularBox);
+        }
+        // The inverse is the transposed matrix of cofactors over the determinant
+        let mut inverse = [[0.0; 3]; 3];
+        for (i, row) in inverse.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+                let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+                let cofactor = m[j1][i1] * m[j2][i2] - m[j1][i2] * m[j2][i1];
+                *x = (cofactor / det) as f32;
+            }
+        }
+        Ok(inverse)
+    }
+
+    /// Coordinates in units of the box vectors
+    ///
+    /// Each Cartesian coordinate `x` is the sum `s[0] a + s[1] b + s[2] c` of
+    /// the box vectors weighted by its fractional coordinates `s`, so atoms
+    /// inside the box have fractional coordinates between 0 and 1. The full
+    /// triclinic box is used. Fails with `Error::SingularBox` if the box has no
+    /// volume.
+    pub fn to_fractional(&self) -> Result<Vec<[f32; 3]>> {
+        let inverse = self.box_inverse()?.map(|row| row.map(f64::from));
+        Ok(self
+            .coords
+            .iter()
+            .map(|x| mul_row_vector(x, &inverse))
+            .collect())
+    }
+
+    /// Set the coordinates from fractional coordinates, the inverse of
+    /// `to_fractional`
+    ///
+    /// `fractional` must have one entry per atom. The box is not required to
+    /// have a volume.
+    pub fn from_fractional(&mut self, fractional: &[[f32; 3]]) -> Result<()> {
+        if fractional.len() != self.len() {
+            return Err(Error::WrongLength {
+                name: "fractional coordinates",
+                expected: self.len(),
+                found: fractional.len(),
+            });
+        }
+        let m = self.box_vector.map(|row| row.map(f64::from));
+        for (x, s) in self.coords.iter_mut().zip(fractional) {
+            *x = mul_row_vector(s, &m);
+        }
+        Ok(())
+    }
+
+    /// Whether the coordinates look like they have been wrapped into the box
+    ///
+    /// This is a heuristic, not a guarantee: it checks that every atom lies
+    /// inside the full triclinic box, allowing for a tolerance of 0.1% of
+    /// each box vector so that rounding in compressed coordinates does not
+    /// count as escaping. Unwrapped coordinates can still all happen to lie
+    /// inside the box, especially early in a simulation, and some programs
+    /// wrap into other cells, such as one centered on the origin. A frame
+    /// whose box has no volume is never reported as wrapped.
+    pub fn appears_wrapped(&self) -> bool {
+        const TOLERANCE: f32 = 1e-3;
+        match self.to_fractional() {
+            Ok(fractional) => fractional
+                .iter()
+                .flatten()
+                .all(|&s| (-TOLERANCE..=1.0 + TOLERANCE).contains(&s)),
+            Err(_) => false,
+        }
+    }
+
+    /// Lengths of the three box vectors, `[a, b, c]`
+    pub fn box_lengths(&self) -> [f32; 3] {
+        let norm = |v: &[f32; 3]| {
+            let v = v.map(f64::from);
+            (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt() as f32
+        };
+        [
+            norm(&self.box_vector[0]),
+            norm(&self.box_vector[1]),
+            norm(&self.box_vector[2]),
+        ]
+    }
+
+    /// Angles between the box vectors in degrees, `[alpha, beta, gamma]`
+    ///
+    /// alpha is the angle between b and c, beta between a and c, and gamma
+    /// between a and b. Angles involving a zero-length box vector are NaN.
+    pub fn box_angles(&self) -> [f32; 3] {
+        let angle = |u: &[f32; 3], v: &[f32; 3]| {
+            let (u, v) = (u.map(f64::from), v.map(f64::from));
+            let dot = u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+            let norms = (u[0] * u[0] + u[1] * u[1] + u[2] * u[2]).sqrt()
+                * (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            (dot / norms).clamp(-1.0, 1.0).acos().to_degrees() as f32
+        };
+        let [a, b, c] = &self.box_vector;
+        [angle(b, c), angle(a, c), angle(a, b)]
+    }
+
+    /// Whether the box follows the conventions GROMACS requires
+    ///
+    /// GROMACS tools reject boxes that are not in reduced lower-triangular
+    /// form: a must lie along x and b in the xy plane, with positive diagonal
+    /// entries, and the off-diagonal entries may be at most half the diagonal
+    /// entry above them (`|b_x|, |c_x| <= a_x / 2` and `|c_y| <= b_y / 2`), with
+    /// the same 0.1% margin GROMACS allows. A box of all zeros, as written for
+    /// systems without periodic boundaries, is also valid. See
+    /// `canonicalize_box` to bring other boxes into this form.
+    pub fn is_valid_gromacs_box(&self) -> bool {
+        const MARGIN: f32 = 1.001;
+        let m = &self.box_vector;
+        if m.iter().flatten().all(|&x| x == 0.0) {
+            return true;
+        }
+        m[0][1] == 0.0
+            && m[0][2] == 0.0
+            && m[1][2] == 0.0
+            && m[0][0] > 0.0
+            && m[1][1] > 0.0
+            && m[2][2] > 0.0
+            && m[2][2].is_finite()
+            && m[1][0].abs() <= MARGIN * 0.5 * m[0][0]
+            && m[2][0].abs() <= MARGIN * 0.5 * m[0][0]
+            && m[2][1].abs() <= MARGIN * 0.5 * m[1][1]
+    }
+
+    /// Bring the box into the form GROMACS requires, see
+    /// `is_valid_gromacs_box`
+    ///
+    /// A box that is not lower-triangular is first rotated so that a lies
+    /// along x and b in the xy plane, and the coordinates, velocities and
+    /// forces are rotated along with it. The box vectors are then reduced by
+    /// adding or subtracting whole earlier box vectors, which describes the
+    /// same periodic lattice, so the coordinates need no change; atoms are not
+    /// wrapped into the new box. A box of all zeros is left as it is.
+    ///
+    /// Fails with `Error::SingularBox` if the box has no volume, and with
+    /// `Error::InvalidArgument` if the box vectors are left-handed, as that
+    /// cannot be fixed by a rotation. The frame is unchanged in both cases.
+    pub fn canonicalize_box(&mut self) -> Result<()> {
+        if self.box_vector.iter().flatten().all(|&x| x == 0.0) {
+            return Ok(());
+        }
+        self.box_inverse()?;
+        let mut m = self.box_vector.map(|row| row.map(f64::from));
+        if determinant(&m) < 0.0 {
+            return Err(Error::InvalidArgument {
+                name: "box_vector",
+                value: format!("{:?} (left-handed)", self.box_vector),
+            });
+        }
+
+        let dot = |u: &[f64; 3], v: &[f64; 3]| u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+        let triangular = m[0][1] == 0.0 && m[0][2] == 0.0 && m[1][2] == 0.0;
+        if !triangular || m[0][0] < 0.0 || m[1][1] < 0.0 {
+            // Rows of the rotation are the new axes, by Gram-Schmidt on a and b
+            let e1 = m[0].map(|x| x / dot(&m[0], &m[0]).sqrt());
+            let along = dot(&m[1], &e1);
+            let b = [0, 1, 2].map(|k| m[1][k] - along * e1[k]);
+            let e2 = b.map(|x| x / dot(&b, &b).sqrt());
+            let e3 = [
+                e1[1] * e2[2] - e1[2] * e2[1],
+                e1[2] * e2[0] - e1[0] * e2[2],
+                e1[0] * e2[1] - e1[1] * e2[0],
+            ];
+            let rotation = [e1, e2, e3];
+            let rotate = |x: &[f64; 3]| rotation.map(|e| dot(&e, x));
+            m = m.map(|v| rotate(&v));
+            // Exactly triangular, rather than up to rounding
+            m[0][1] = 0.0;
+            m[0][2] = 0.0;
+            m[1][2] = 0.0;
+            let vectors = Some(&mut self.coords)
+                .into_iter()
+                .chain(self.velocities.as_mut())
+                .chain(self.forces.as_mut());
+            for values in vectors {
+                for x in values.iter_mut() {
+                    *x = rotate(&x.map(f64::from)).map(|x| x as f32);
+                }
+            }
+        }
+
+        // Reduce c by b, then c and b by a; entries of exactly half are kept
+        let shifts = |x: f64, diagonal: f64| {
+            if x.abs() > 0.5 * diagonal {
+                (x / diagonal).round()
+            } else {
+                0.0
+            }
+        };
+        let n = shifts(m[2][1], m[1][1]);
+        m[2] = [0, 1, 2].map(|k| m[2][k] - n * m[1][k]);
+        for i in [2, 1] {
+            let n = shifts(m[i][0], m[0][0]);
+            m[i] = [0, 1, 2].map(|k| m[i][k] - n * m[0][k]);
+        }
+        self.box_vector = m.map(|row| row.map(|x| x as f32));
+        Ok(())
+    }
+
+    /// Write the frame as a PDB file
+    ///
+    /// Each atom is written as an `ATOM` record, preceded by a `CRYST1` record
+    /// for the box unless the box is all zeros, and followed by `END`.
+    /// Coordinates and box lengths are converted from nanometers to the
+    /// Ångström PDB uses. `atom_names` and `residue_names` give one name per
+    /// atom, of at most 4 and 3 characters; without them, atoms are named `X`
+    /// and residues `UNK`. Serial and residue numbers are taken from the
+    /// frame's `atom_ids` and `residue_ids` if it has them; otherwise atoms
+    /// are numbered from 1 and, as trajectories do not record residues, all
+    /// written as residue 1. Occupancies and B-factors are taken from the
+    /// frame if it has them, and default to 1 and 0.
+    ///
+    /// Returns `Error::WrongLength` if a list of names does not have one entry
+    /// per atom, and `Error::InvalidArgument` if a name or coordinate does not
+    /// fit its PDB column.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut frame = Frame::with_len(trj.get_num_atoms()?);
+    /// trj.read(&mut frame)?;
+    ///
+    /// let mut pdb = Vec::new();
+    /// frame.to_pdb(&mut pdb, None, None)?;
+    /// let pdb = String::from_utf8(pdb).unwrap();
+    /// assert_eq!(pdb.lines().filter(|l| l.starts_with("ATOM  ")).count(), 304);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_pdb(
+        &self,
+        w: &mut impl Write,
+        atom_names: Option<&[String]>,
+        residue_names: Option<&[String]>,
+    ) -> Result<()> {
+        for (name, names) in [("atom names", atom_names), ("residue names", residue_names)] {
+            if let Some(names) = names {
+                if names.len() != self.len() {
+                    return Err(Error::WrongLength {
+                        name,
+                        expected: self.len(),
+                        found: names.len(),
+                    });
+                }
+            }
+        }
+        let check_name = |name: &'static str, value: &str, width: usize| {
+            if value.is_empty() || value.chars().count() > width || !value.is_ascii() {
+                return Err(Error::InvalidArgument {
+                    name,
+                    value: value.to_string(),
+                });
+            }
+            Ok(())
+        };
+        // Values that fit the 8.3 coordinate columns, including the minus sign
+        let coordinate_range = -999.999..=9999.999;
+
+        let mut pdb = String::new();
+        if self.box_vector != [[0.0; 3]; 3] {
+            let [a, b, c] = self.box_lengths();
+            let [alpha, beta, gamma] = self.box_angles();
+            pdb.push_str(&format!(
+                "CRYST1{:9.3}{:9.3}{:9.3}{:7.2}{:7.2}{:7.2} P 1           1\n",
+                a * 10.0,
+                b * 10.0,
+                c * 10.0,
+                alpha,
+                beta,
+                gamma
+            ));
+        }
+        for (i, x) in self.coords.iter().enumerate() {
+            let atom_name = atom_names.map_or("X", |names| &names[i]);
+            let residue_name = residue_names.map_or("UNK", |names| &names[i]);
+            check_name("atom_names", atom_name, 4)?;
+            check_name("residue_names", residue_name, 3)?;
+            let x = x.map(|x| x * 10.0);
+            if let Some(value) = x.iter().find(|x| !coordinate_range.contains(*x)) {
+                return Err(Error::InvalidArgument {
+                    name: "coords",
+                    value: format!("{} for atom {}", value, i),
+                });
+            }
+            // Names shorter than four characters start in the second column
+            let atom_name = if atom_name.len() < 4 {
+                format!(" {:<3}", atom_name)
+            } else {
+                atom_name.to_string()
+            };
+            let occupancy = self.occupancies.as_ref().and_then(|o| o.get(i));
+            let b_factor = self.b_factors.as_ref().and_then(|b| b.get(i));
+            let serial = self.atom_ids.as_ref().and_then(|a| a.get(i));
+            let residue = self.residue_ids.as_ref().and_then(|r| r.get(i));
+            // Serial and residue numbers wrap around to fit their five and four columns
+            pdb.push_str(&format!(
+                "ATOM  {:5} {} {:>3} {:1}{:4}    {:8.3}{:8.3}{:8.3}{:6.2}{:6.2}\n",
+                serial.map_or((i + 1) % 100_000, |&s| s.rem_euclid(100_000) as usize),
+                atom_name,
+                residue_name,
+                'A',
+                residue.map_or(1, |&r| r.rem_euclid(10_000)),
+                x[0],
+                x[1],
+                x[2],
+                occupancy.unwrap_or(&1.0),
+                b_factor.unwrap_or(&0.0)
+            ));
+        }
+        pdb.push_str("END\n");
+
+        w.write_all(pdb.as_bytes())
+            .map_err(|e| Error::from((e, ErrorTask::Write)))
+    }
+
+    /// Shift a displacement vector to its shortest periodic image
+    ///
+    /// Assumes the GROMACS box convention, where the box vectors form a
+    /// lower-triangular matrix. Box dimensions of zero are treated as
+    /// non-periodic.
+    pub(crate) fn minimum_image(&self, mut d: [f32; 3]) -> [f32; 3] {
+        for m in (0..3).rev() {
+            let length = self.box_vector[m][m];
+            if length > 0.0 {
+                let shift = (d[m] / length).round();
+                if shift != 0.0 {
+                    for (di, bi) in d.iter_mut().zip(&self.box_vector[m]) {
+                        *di -= shift * bi;
+                    }
+                }
+            }
+        }
+        d
+    }
+
+    /// Shift a position into the primary unit cell
+    ///
+    /// Like `minimum_image`, this assumes a lower-triangular box and leaves
+    /// dimensions with a box length of zero untouched.
+    pub(crate) fn wrap_position(&self, mut x: [f32; 3]) -> [f32; 3] {
+        for m in (0..3).rev() {
+            let length = self.box_vector[m][m];
+            if length > 0.0 {
+                let shift = (x[m] / length).floor();
+                if shift != 0.0 {
+                    for (xi, bi) in x.iter_mut().zip(&self.box_vector[m]) {
+                        *xi -= shift * bi;
+                    }
+                }
+            }
+        }
+        x
+    }
+
+    /// Full matrix of distances between all pairs of atoms
+    ///
+    /// The matrix is returned in row-major order, so the distance between
+    /// atoms `i` and `j` is at index `i * self.len() + j`. It is symmetric
+    /// with zeros on the diagonal. With `pbc`, distances are measured between
+    /// nearest periodic images, which requires the frame to have a box.
+    ///
+    /// Memory grows with the square of the number of atoms (4 GB for 32768
+    /// atoms), so this is only suitable for small systems or selections.
+    pub fn distance_matrix(&self, pbc: bool) -> Result<Vec<f32>> {
+        if pbc && self.box_vector == [[0.0; 3]; 3] {
+            return Err(Error::MissingData { name: "a box" });
+        }
+
+        let n = self.len();
+        let mut matrix = vec![0.0; n * n];
+        for (i, xi) in self.coords.iter().enumerate() {
+            for (j, xj) in self.coords.iter().enumerate().skip(i + 1) {
+                let mut d = [xj[0] - xi[0], xj[1] - xi[1], xj[2] - xi[2]];
+                if pbc {
+                    d = self.minimum_image(d);
+                }
+                let distance = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+                matrix[i * n + j] = distance;
+                matrix[j * n + i] = distance;
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Indices of the atoms whose coordinate along `axis` lies in `[min, max]`
+    ///
+    /// Coordinates are wrapped into the primary unit cell before being
+    /// compared, so `min` and `max` should lie within the box. The resulting
+    /// indices can be passed to `filter_coords` to extract the slab.
+    pub fn atoms_in_slab(&self, axis: usize, min: f32, max: f32) -> Result<Vec<usize>> {
+        if axis >= 3 {
+            return Err(Error::InvalidArgument {
+                name: "axis",
+                value: axis.to_string(),
+            });
+        }
+        Ok(self
+            .coords
+            .iter()
+            .enumerate()
+            .filter(|(_, &x)| {
+                let value = self.wrap_position(x)[axis];
+                min <= value && value <= max
+            })
+            .map(|(i, _)| i)
+            .collect())
+    }
+
+    /// Smallest bounding box of the atoms `indices` over their periodic images
+    ///
+    /// Each atom may be replaced by any of its periodic images, and the images
+    /// are chosen so that the group is as compact as possible along each box
+    /// vector: a cluster split across the boundary gives a box around the
+    /// cluster rather than one spanning the whole cell. Returns the minimum
+    /// and maximum corners of the axis-aligned bounding box of those images.
+    /// Along periodic dimensions the minimum corner lies inside the unit cell,
+    /// while the box may extend past it.
+    ///
+    /// Like `minimum_image`, this assumes a lower-triangular box and treats
+    /// dimensions with a box length of zero as non-periodic. Returns
+    /// `Error::InvalidAtomRange` if an index is out of range, and
+    /// `Error::InvalidArgument` if there are no indices.
+    pub fn pbc_bounding_box(&self, indices: &[usize]) -> Result<([f32; 3], [f32; 3])> {
+        if indices.is_empty() {
+            return Err(Error::InvalidArgument {
+                name: "indices",
+                value: "[]".to_string(),
+            });
+        }
+        let mut images = Vec::with_capacity(indices.len());
+        for &i in indices {
+            let x = self.coords.get(i).ok_or(Error::InvalidAtomRange {
+                start: i,
+                end: i + 1,
+                num_atoms: self.len(),
+            })?;
+            images.push(x.map(f64::from));
+        }
+
+        // Shifting along box vector m only changes coordinates up to m, so
+        // settle the last dimension first
+        for m in (0..3).rev() {
+            let length = f64::from(self.box_vector[m][m]);
+            if length <= 0.0 {
+                continue;
+            }
+            let mut wrapped: Vec<f64> = images.iter().map(|x| x[m].rem_euclid(length)).collect();
+            wrapped.sort_by(|a, b| a.total_cmp(b));
+            // The group spans the circle except for the largest gap between
+            // neighbouring atoms, so it starts at the atom after that gap
+            let mut start = wrapped[0];
+            let mut largest_gap = wrapped[0] + length - wrapped[wrapped.len() - 1];
+            for pair in wrapped.windows(2) {
+                if pair[1] - pair[0] > largest_gap {
+                    largest_gap = pair[1] - pair[0];
+                    start = pair[1];
+                }
+            }
+            for x in images.iter_mut() {
+                let shift = ((x[m] - start) / length).floor();
+                if shift != 0.0 {
+                    for (xi, bi) in x.iter_mut().zip(&self.box_vector[m]) {
+                        *xi -= shift * f64::from(*bi);
+                    }
+                }
+            }
+        }
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for x in &images {
+            for d in 0..3 {
+                min[d] = min[d].min(x[d]);
+                max[d] = max[d].max(x[d]);
+            }
+        }
+        Ok((min.map(|x| x as f32), max.map(|x| x as f32)))
+    }
+
+    /// Quantized change in the coordinates since the frame `prev`
+    ///
+    /// Experimental: this is a building block for compact caches of frames
+    /// that change little, not a replacement for XTC. Each coordinate's change
+    /// from `prev` is multiplied by `precision` and rounded to an `i16`, giving
+    /// three values per atom in the order of `coords`. The rounding error is
+    /// at most `0.5 / precision`, but deltas are limited to
+    /// `i16::MAX / precision`: about 32.8 nm at a precision of 1000, or 3.3 nm
+    /// at 10000. A larger change fails with `Error::CoordinateOutOfRange`,
+    /// reporting the atom and the change. Only coordinates are encoded.
+    ///
+    /// To stop rounding errors accumulating over a chain of frames, encode
+    /// each frame against the decoded previous frame rather than the original.
+    /// Returns `Error::WrongSizeFrame` if `prev` has a different number of
+    /// atoms, and `Error::InvalidArgument` unless `precision` is positive.
+    pub fn delta_encode(&self, prev: &Frame, precision: f32) -> Result<Vec<i16>> {
+        check_delta_precision(precision)?;
+        if prev.len() != self.len() {
+            return Err((prev, self.len()).into());
+        }
+        let mut deltas = Vec::with_capacity(3 * self.len());
+        for (atom, (x, p)) in self.coords.iter().zip(&prev.coords).enumerate() {
+            for k in 0..3 {
+                let delta = f64::from(x[k]) - f64::from(p[k]);
+                let scaled = (delta * f64::from(precision)).round();
+                if scaled.is_nan() || scaled.abs() > f64::from(i16::MAX) {
+                    return Err(Error::CoordinateOutOfRange {
+                        atom,
+                        value: delta as f32,
+                        precision,
+                    });
+                }
+                deltas.push(scaled as i16);
+            }
+        }
+        Ok(deltas)
+    }
+
+    /// Coordinates of the frame that `deltas` encode relative to this one
+    ///
+    /// This reverses `delta_encode`, which must have used the same
+    /// `precision`, up to its rounding error. Returns `Error::WrongLength` if
+    /// `deltas` does not have three values per atom.
+    pub fn delta_decode(&self, deltas: &[i16], precision: f32) -> Result<Vec<[f32; 3]>> {
+        check_delta_precision(precision)?;
+        if deltas.len() != 3 * self.len() {
+            return Err(Error::WrongLength {
+                name: "deltas",
+                expected: 3 * self.len(),
+                found: deltas.len(),
+            });
+        }
+        let scale = 1.0 / f64::from(precision);
+        Ok(self
+            .coords
+            .iter()
+            .zip(deltas.chunks_exact(3))
+            .map(|(p, d)| [0, 1, 2].map(|k| (f64::from(p[k]) + f64::from(d[k]) * scale) as f32))
+            .collect())
+    }
+
+    /// Linearly interpolate between frames `a` and `b`
+    ///
+    /// `t` must be in `[0, 1]`; 0 gives `a` and 1 gives `b`. Coordinates move
+    /// along the shortest periodic image of each atom's displacement (using the
+    /// box of `a`), so atoms that crossed the box boundary between the two
+    /// frames do not travel across the box. Time, step, box and lambda are
+    /// interpolated as well, with the step rounded to the nearest integer.
+    /// Velocities and forces are interpolated directly where both frames have
+    /// them, and are None otherwise. Occupancies, B-factors and ids are taken
+    /// from `a`.
+    pub fn interpolate(a: &Frame, b: &Frame, t: f32) -> Result<Frame> {
+        if !(0.0..=1.0).contains(&t) {
+            return Err(Error::InvalidArgument {
+                name: "t",
+                value: t.to_string(),
+            });
+        }
+        if a.len() != b.len() {
+            return Err((b, a.len()).into());
+        }
+
+        let lerp = |x: f32, y: f32| x + t * (y - x);
+        let coords = a
+            .coords
+            .iter()
+            .zip(&b.coords)
+            .map(|(xa, xb)| {
+                let d = a.minimum_image([xb[0] - xa[0], xb[1] - xa[1], xb[2] - xa[2]]);
+                [xa[0] + t * d[0], xa[1] + t * d[1], xa[2] + t * d[2]]
+            })
+            .collect();
+        let mut box_vector = [[0.0; 3]; 3];
+        for (i, row) in box_vector.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = lerp(a.box_vector[i][j], b.box_vector[i][j]);
+            }
+        }
+        let step = a.step as f64 + f64::from(t) * (b.step as f64 - a.step as f64);
+        let lerp_rvecs = |va: &Option<Vec<[f32; 3]>>, vb: &Option<Vec<[f32; 3]>>| match (va, vb) {
+            (Some(va), Some(vb)) if va.len() == vb.len() => Some(
+                va.iter()
+                    .zip(vb)
+                    .map(|(xa, xb)| [0, 1, 2].map(|k| lerp(xa[k], xb[k])))
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        Ok(Frame {
+            step: step.round() as usize,
+            time: lerp(a.time, b.time),
+            box_vector,
+            coords,
+            lambda: lerp(a.lambda, b.lambda),
+            velocities: lerp_rvecs(&a.velocities, &b.velocities),
+            forces: lerp_rvecs(&a.forces, &b.forces),
+            occupancies: a.occupancies.clone(),
+            b_factors: a.b_factors.clone(),
+            atom_ids: a.atom_ids.clone(),
+            residue_ids: a.residue_ids.clone(),
+        })
+    }
+}
+
+/// Returns `Error::InvalidArgument` unless `precision` is positive and finite
+fn check_delta_precision(precision: f32) -> Result<()> {
+    if precision > 0.0 && precision.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument {
+            name: "precision",
+            value: precision.to_string(),
+        })
+    }
+}
+
+/// Product `v · m` of a row vector and a 3x3 matrix
+fn mul_row_vector(v: &[f32; 3], m: &[[f64; 3]; 3]) -> [f32; 3] {
+    let v = v.map(f64::from);
+    [0, 1, 2].map(|j| (v[0] * m[0][j] + v[1] * m[1][j] + v[2] * m[2][j]) as f32)
+}
+
+/// Determinant of a 3x3 matrix
+fn determinant(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Eigenvalues and eigenvectors of a symmetric 3x3 matrix by the Jacobi method
+///
+/// Returns the eigenvalues in no particular order, and a matrix whose columns
+/// are the corresponding unit eigenvectors.
+fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    fn mul(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+        let mut c = [[0.0; 3]; 3];
+        for (i, row) in c.iter_mut().enumerate() {
+            for (j, c) in row.iter_mut().enumerate() {
+                *c = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        c
+    }
+    let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    let mut vectors = identity;
+    // Jacobi converges quadratically, so a handful of sweeps is plenty
+    for _ in 0..50 {
+        let off_diagonal = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        let diagonal = a[0][0] * a[0][0] + a[1][1] * a[1][1] + a[2][2] * a[2][2];
+        if off_diagonal <= f64::EPSILON * f64::EPSILON * diagonal {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            // Rotate in the pq plane by the angle that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            let mut rotation = identity;
+            rotation[p][p] = c;
+            rotation[q][q] = c;
+            rotation[p][q] = s;
+            rotation[q][p] = -s;
+            let mut transposed = rotation;
+            transposed[p][q] = -s;
+            transposed[q][p] = s;
+            a = mul(&transposed, &mul(&a, &rotation));
+            vectors = mul(&vectors, &rotation);
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], vectors)
+}
+
+impl AsRef<[[f32; 3]]> for Frame {
+    fn as_ref(&self) -> &[[f32; 3]] {
+        &self.coords
+    }
+}
+
+impl AsMut<[[f32; 3]]> for Frame {
+    fn as_mut(&mut self) -> &mut [[f32; 3]] {
+        &mut self.coords
     }
 }
 
@@ -107,6 +1468,121 @@ mod tests {
         assert!(frame_new.coords[1] == frame[2]);
     }
 
+    #[test]
+    fn test_append_remove_atom() -> Result<()> {
+        let mut frame = Frame::new();
+        frame.append_atom([1.0; 3], None, None);
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame.velocities, None);
+
+        frame.append_atom([2.0; 3], Some([0.2; 3]), None);
+        frame.append_atom([3.0; 3], None, Some([30.0; 3]));
+        assert_eq!(frame.coords, vec![[1.0; 3], [2.0; 3], [3.0; 3]]);
+        assert_eq!(frame.velocities, Some(vec![[0.0; 3], [0.2; 3], [0.0; 3]]));
+        assert_eq!(frame.forces, Some(vec![[0.0; 3], [0.0; 3], [30.0; 3]]));
+
+        frame.remove_atom(1)?;
+        assert_eq!(frame.len(), 2);
+        assert_eq!(frame.coords, vec![[1.0; 3], [3.0; 3]]);
+        assert_eq!(frame.velocities, Some(vec![[0.0; 3], [0.0; 3]]));
+        assert_eq!(frame.forces, Some(vec![[0.0; 3], [30.0; 3]]));
+
+        let result = frame.remove_atom(2);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidAtomRange { num_atoms: 2, .. })
+        ));
+        assert_eq!(frame.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_box_from_lengths_angles() {
+        let rectangular = Frame::box_matrix_from_lengths_angles([1.0, 2.0, 3.0], [90.0; 3]);
+        assert_eq!(
+            rectangular,
+            [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 3.0]]
+        );
+
+        let lengths = [5.0, 6.0, 7.0];
+        let angles = [70.0, 80.0, 100.0];
+        let frame = Frame {
+            box_vector: Frame::box_matrix_from_lengths_angles(lengths, angles),
+            ..Default::default()
+        };
+        assert_eq!(frame.box_vector[0][1], 0.0);
+        assert_eq!(frame.box_vector[0][2], 0.0);
+        assert_eq!(frame.box_vector[1][2], 0.0);
+        for (found, expected) in frame.box_lengths().iter().zip(&lengths) {
+            assert_approx_eq!(found, expected, 1e-5);
+        }
+        for (found, expected) in frame.box_angles().iter().zip(&angles) {
+            assert_approx_eq!(found, expected, 1e-4);
+        }
+
+        // The triangle inequality for angles does not hold
+        let invalid = Frame::box_matrix_from_lengths_angles([1.0; 3], [10.0, 10.0, 90.0]);
+        assert!(invalid[2][2].is_nan());
+    }
+
+    #[test]
+    fn test_distance_matrix() -> Result<()> {
+        let mut frame = Frame {
+            box_vector: [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]],
+            coords: vec![[0.0, 0.0, 0.0], [3.0, 4.0, 0.0], [9.0, 0.0, 0.0]],
+            ..Default::default()
+        };
+
+        let matrix = frame.distance_matrix(false)?;
+        let d12 = (6.0_f32 * 6.0 + 4.0 * 4.0).sqrt();
+        let expected = [0.0, 5.0, 9.0, 5.0, 0.0, d12, 9.0, d12, 0.0];
+        for (found, expected) in matrix.iter().zip(&expected) {
+            assert_approx_eq!(found, expected);
+        }
+
+        // Through the boundary, atom 2 is next to atom 0 and closer to atom 1
+        let matrix = frame.distance_matrix(true)?;
+        let d12 = (4.0_f32 * 4.0 + 4.0 * 4.0).sqrt();
+        let expected = [0.0, 5.0, 1.0, 5.0, 0.0, d12, 1.0, d12, 0.0];
+        for (found, expected) in matrix.iter().zip(&expected) {
+            assert_approx_eq!(found, expected);
+        }
+
+        frame.box_vector = [[0.0; 3]; 3];
+        assert!(matches!(
+            frame.distance_matrix(true),
+            Err(Error::MissingData { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_soa_round_trip() -> Result<()> {
+        let mut frame = Frame::with_len(3);
+        frame[0] = [1.0, 2.0, 3.0];
+        frame[1] = [4.0, 5.0, 6.0];
+        frame[2] = [7.0, 8.0, 9.0];
+
+        let (x, y, z) = frame.to_soa();
+        assert_eq!(x, vec![1.0, 4.0, 7.0]);
+        assert_eq!(y, vec![2.0, 5.0, 8.0]);
+        assert_eq!(z, vec![3.0, 6.0, 9.0]);
+
+        let round_trip = Frame::from_soa(&x, &y, &z)?;
+        assert_eq!(round_trip.coords, frame.coords);
+
+        let result = Frame::from_soa(&x, &y, &z[..2]);
+        assert!(matches!(
+            result,
+            Err(Error::WrongLength {
+                name: "z",
+                expected: 3,
+                found: 2
+            })
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_frame_len() {
         let frame = Frame::with_len(10);
@@ -119,7 +1595,8 @@ mod tests {
             step: 0,
             time: 0.0,
             box_vector: [[0.0; 3]; 3],
-            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3]]
+            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3]],
+            ..Default::default()
         };
 
         frame.filter_coords(&[1]);
@@ -128,6 +1605,833 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_atom_scalars() -> Result<()> {
+        let mut frame = Frame {
+            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3], [3.0; 3]],
+            occupancies: Some(vec![0.25, 0.5, 0.75, 1.0]),
+            b_factors: Some(vec![10.0, 20.0, 30.0, 40.0]),
+            ..Default::default()
+        };
+
+        // The scalars follow their atoms through a subset
+        frame.filter_coords(&[1, 3]);
+        assert_eq!(frame.occupancies, Some(vec![0.5, 1.0]));
+        assert_eq!(frame.b_factors, Some(vec![20.0, 40.0]));
+
+        frame.append_atom([4.0; 3], None, None);
+        assert_eq!(frame.occupancies, Some(vec![0.5, 1.0, 1.0]));
+        assert_eq!(frame.b_factors, Some(vec![20.0, 40.0, 0.0]));
+        frame.remove_atom(0)?;
+        assert_eq!(frame.occupancies, Some(vec![1.0, 1.0]));
+        assert_eq!(frame.b_factors, Some(vec![40.0, 0.0]));
+        frame.resize(1);
+        assert_eq!(frame.occupancies, Some(vec![1.0]));
+        assert_eq!(frame.b_factors, Some(vec![40.0]));
+
+        // Reading a trajectory into the frame keeps them
+        use crate::{Trajectory, XTCTrajectory};
+        let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        frame.resize(trj.get_num_atoms()?);
+        trj.read(&mut frame)?;
+        assert_eq!(frame.b_factors.as_ref().map(|b| b.len()), Some(304));
+        assert_eq!(frame.b_factors.as_ref().map(|b| b[0]), Some(40.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_atom_ids() -> Result<()> {
+        let mut frame = Frame {
+            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3], [3.0; 3]],
+            atom_ids: Some(vec![101, 102, 103, 104]),
+            residue_ids: Some(vec![1, 1, 2, 2]),
+            ..Default::default()
+        };
+
+        // The ids follow their atoms through a subset
+        frame.filter_coords(&[1, 2, 3]);
+        assert_eq!(frame.coords, vec![[1.0; 3], [2.0; 3], [3.0; 3]]);
+        assert_eq!(frame.atom_ids, Some(vec![102, 103, 104]));
+        assert_eq!(frame.residue_ids, Some(vec![1, 2, 2]));
+        frame.retain_atoms(|i| i != 1);
+        assert_eq!(frame.atom_ids, Some(vec![102, 104]));
+        assert_eq!(frame.residue_ids, Some(vec![1, 2]));
+        frame.reorder(&[1, 0])?;
+        assert_eq!(frame.atom_ids, Some(vec![104, 102]));
+        assert_eq!(frame.coords, vec![[3.0; 3], [1.0; 3]]);
+
+        frame.append_atom([4.0; 3], None, None);
+        assert_eq!(frame.atom_ids, Some(vec![104, 102, 0]));
+        frame.remove_atom(0)?;
+        assert_eq!(frame.residue_ids, Some(vec![1, 0]));
+        frame.resize(3);
+        assert_eq!(frame.atom_ids, Some(vec![102, 0, 0]));
+        assert_eq!(frame.residue_ids, Some(vec![1, 0, 0]));
+
+        // They number the atoms and residues of PDB files
+        let mut pdb = Vec::new();
+        frame.to_pdb(&mut pdb, None, None)?;
+        let pdb = String::from_utf8(pdb).unwrap();
+        assert!(pdb.starts_with("ATOM    102  X   UNK A   1"), "{}", pdb);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_pdb() -> Result<()> {
+        let frame = Frame {
+            box_vector: Frame::box_matrix_from_lengths_angles([5.0, 6.0, 7.0], [90.0, 90.0, 120.0]),
+            coords: vec![[0.1, 0.2, 0.3], [-1.0, 2.5, 10.0]],
+            b_factors: Some(vec![12.5, 99.0]),
+            ..Default::default()
+        };
+        let atom_names = ["CA".to_string(), "HD21".to_string()];
+        let residue_names = ["ALA".to_string(), "ASN".to_string()];
+        let mut pdb = Vec::new();
+        frame.to_pdb(&mut pdb, Some(&atom_names), Some(&residue_names))?;
+        let pdb = String::from_utf8(pdb).unwrap();
+        let lines: Vec<_> = pdb.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "CRYST1   50.000   60.000   70.000  90.00  90.00 120.00 P 1           1"
+        );
+        assert_eq!(lines[3], "END");
+
+        // Fields by their PDB columns
+        let field = |line: &str, start: usize, end: usize| line[start - 1..end].to_string();
+        let atom = lines[2];
+        assert_eq!(field(atom, 1, 6), "ATOM  ");
+        assert_eq!(field(atom, 7, 11).trim(), "2");
+        assert_eq!(field(atom, 13, 16), "HD21");
+        assert_eq!(field(atom, 18, 20), "ASN");
+        assert_eq!(field(atom, 23, 26).trim(), "1");
+        assert_eq!(field(atom, 31, 38).trim(), "-10.000");
+        assert_eq!(field(atom, 39, 46).trim(), "25.000");
+        assert_eq!(field(atom, 47, 54).trim(), "100.000");
+        assert_eq!(field(atom, 55, 60).trim(), "1.00");
+        assert_eq!(field(atom, 61, 66).trim(), "99.00");
+        assert_eq!(field(lines[1], 13, 16), " CA ");
+
+        // Generic names, and no box
+        let frame = Frame::with_len(1);
+        let mut pdb = Vec::new();
+        frame.to_pdb(&mut pdb, None, None)?;
+        let pdb = String::from_utf8(pdb).unwrap();
+        assert!(pdb.starts_with("ATOM      1  X   UNK A   1       0.000"));
+
+        let names = ["LONGNAME".to_string(), "CA".to_string()];
+        let result = Frame::with_len(2).to_pdb(&mut Vec::new(), Some(&names), None);
+        assert!(matches!(result, Err(Error::InvalidArgument { .. })));
+        let result = Frame::with_len(3).to_pdb(&mut Vec::new(), Some(&names), None);
+        assert!(matches!(result, Err(Error::WrongLength { .. })));
+        let far = Frame {
+            coords: vec![[1000.0, 0.0, 0.0]],
+            ..Default::default()
+        };
+        let result = far.to_pdb(&mut Vec::new(), None, None);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidArgument { name: "coords", .. })
+        ));
+
+        // Negative values need a column for the sign
+        let far = Frame {
+            coords: vec![[0.0, -500.0, 0.0]],
+            ..Default::default()
+        };
+        let result = far.to_pdb(&mut Vec::new(), None, None);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidArgument { name: "coords", .. })
+        ));
+        let near = Frame {
+            coords: vec![[0.0, -99.9, 0.0]],
+            ..Default::default()
+        };
+        let mut pdb = Vec::new();
+        near.to_pdb(&mut pdb, None, None)?;
+        let line = String::from_utf8(pdb).unwrap();
+        assert_eq!(&line[38..46], "-999.000");
+        Ok(())
+    }
+
+    #[test]
+    fn test_retain_atoms() {
+        let mut frame = Frame::with_len(0);
+        for i in 0..7 {
+            let x = i as f32;
+            frame.append_atom([x; 3], Some([-x; 3]), None);
+        }
+        frame.b_factors = Some((0..7).map(|i| i as f32 * 10.0).collect());
+
+        frame.retain_atoms(|i| i % 2 == 0);
+        assert_eq!(frame.len(), 4);
+        assert_eq!(frame.coords, vec![[0.0; 3], [2.0; 3], [4.0; 3], [6.0; 3]]);
+        assert_eq!(
+            frame.velocities,
+            Some(vec![[0.0; 3], [-2.0; 3], [-4.0; 3], [-6.0; 3]])
+        );
+        assert_eq!(frame.forces, None);
+        assert_eq!(frame.b_factors, Some(vec![0.0, 20.0, 40.0, 60.0]));
+
+        frame.retain_atoms(|_| false);
+        assert_eq!(frame.len(), 0);
+        assert_eq!(frame.velocities, Some(vec![]));
+    }
+
+    #[test]
+    fn test_reorder() -> Result<()> {
+        let mut frame = Frame::with_len(0);
+        for i in 0..4 {
+            let x = i as f32;
+            frame.append_atom([x; 3], Some([-x; 3]), None);
+        }
+        frame.occupancies = Some(vec![0.1, 0.2, 0.3, 0.4]);
+
+        frame.reorder(&[3, 2, 1, 0])?;
+        assert_eq!(frame.coords, vec![[3.0; 3], [2.0; 3], [1.0; 3], [0.0; 3]]);
+        assert_eq!(
+            frame.velocities,
+            Some(vec![[-3.0; 3], [-2.0; 3], [-1.0; 3], [0.0; 3]])
+        );
+        assert_eq!(frame.occupancies, Some(vec![0.4, 0.3, 0.2, 0.1]));
+
+        // Not a reversal, so the direction of the permutation matters
+        frame.reorder(&[1, 2, 3, 0])?;
+        assert_eq!(frame.coords, vec![[2.0; 3], [1.0; 3], [0.0; 3], [3.0; 3]]);
+
+        let before = frame.coords.clone();
+        assert!(matches!(
+            frame.reorder(&[0, 1, 2]),
+            Err(Error::WrongLength { found: 3, .. })
+        ));
+        assert!(matches!(
+            frame.reorder(&[0, 1, 2, 4]),
+            Err(Error::InvalidAtomRange { start: 4, .. })
+        ));
+        assert!(matches!(
+            frame.reorder(&[0, 1, 1, 2]),
+            Err(Error::InvalidArgument { .. })
+        ));
+        assert_eq!(frame.coords, before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pbc_bounding_box() -> Result<()> {
+        let frame = Frame {
+            box_vector: [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 0.0]],
+            coords: vec![
+                [9.5, 4.0, 20.0],
+                [0.5, 5.0, -3.0],
+                [9.8, 4.5, 1.0],
+                [5.0, 5.0, 5.0],
+            ],
+            ..Default::default()
+        };
+
+        // The first three atoms straddle the boundary along x only
+        let (min, max) = frame.pbc_bounding_box(&[0, 1, 2])?;
+        let (expected_min, expected_max) = ([9.5, 4.0, -3.0], [10.5, 5.0, 20.0]);
+        for d in 0..3 {
+            assert_approx_eq!(min[d], expected_min[d], 1e-5);
+            assert_approx_eq!(max[d], expected_max[d], 1e-5);
+        }
+
+        // Atoms further into the cell fill the smaller gap instead
+        let (min, max) = frame.pbc_bounding_box(&[0, 1, 3])?;
+        assert_approx_eq!(max[0] - min[0], 5.5, 1e-5);
+
+        let (min, max) = frame.pbc_bounding_box(&[3])?;
+        assert_eq!((min, max), ([5.0; 3], [5.0; 3]));
+
+        assert!(matches!(
+            frame.pbc_bounding_box(&[4]),
+            Err(Error::InvalidAtomRange { start: 4, .. })
+        ));
+        assert!(matches!(
+            frame.pbc_bounding_box(&[]),
+            Err(Error::InvalidArgument { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_delta_encode() -> Result<()> {
+        let mut prev = Frame::with_len(3);
+        prev[0] = [1.0, 2.0, 3.0];
+        prev[1] = [-4.5, 0.25, 7.0];
+        prev[2] = [0.0, 0.0, 0.0];
+        let mut next = prev.clone();
+        next[0] = [1.0123, 1.9876, 3.0];
+        next[1] = [-4.4, 0.3, 6.95];
+        next[2] = [3.2, -3.2, 0.0001];
+
+        let deltas = next.delta_encode(&prev, 10000.0)?;
+        assert_eq!(deltas.len(), 9);
+        assert_eq!(&deltas[..3], &[123, -124, 0]);
+        let decoded = prev.delta_decode(&deltas, 10000.0)?;
+        for (x, expected) in decoded.iter().zip(&next.coords) {
+            for k in 0..3 {
+                assert_approx_eq!(x[k], expected[k], 0.5e-4 + 1e-6);
+            }
+        }
+
+        // 4 nm does not fit at this precision, but does at a coarser one
+        next[2] = [4.0, 0.0, 0.0];
+        assert!(matches!(
+            next.delta_encode(&prev, 10000.0),
+            Err(Error::CoordinateOutOfRange { atom: 2, .. })
+        ));
+        next.delta_encode(&prev, 1000.0)?;
+
+        assert!(matches!(
+            Frame::with_len(2).delta_encode(&prev, 1000.0),
+            Err(Error::WrongSizeFrame {
+                expected: 2,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            prev.delta_decode(&deltas[..6], 10000.0),
+            Err(Error::WrongLength { .. })
+        ));
+        assert!(matches!(
+            next.delta_encode(&prev, 0.0),
+            Err(Error::InvalidArgument { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_midpoint() -> Result<()> {
+        let a = Frame {
+            step: 0,
+            time: 0.0,
+            box_vector: [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]],
+            coords: vec![[1.0, 2.0, 3.0], [4.0, 4.0, 4.0]],
+            ..Default::default()
+        };
+        let b = Frame {
+            step: 10,
+            time: 2.0,
+            box_vector: [[12.0, 0.0, 0.0], [0.0, 12.0, 0.0], [0.0, 0.0, 12.0]],
+            coords: vec![[3.0, 2.0, 1.0], [5.0, 5.0, 5.0]],
+            ..Default::default()
+        };
+
+        let mid = Frame::interpolate(&a, &b, 0.5)?;
+        assert_eq!(mid.step, 5);
+        assert_approx_eq!(mid.time, 1.0);
+        assert_approx_eq!(mid.box_vector[0][0], 11.0);
+        assert_approx_eq!(mid.box_vector[1][0], 0.0);
+        for i in 0..3 {
+            assert_approx_eq!(mid[0][i], 2.0);
+            assert_approx_eq!(mid[1][i], 4.5);
+        }
+        assert!(mid.velocities.is_none() && mid.forces.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_velocities() -> Result<()> {
+        let a = Frame {
+            velocities: Some(vec![[1.0, 0.0, -2.0]]),
+            forces: Some(vec![[10.0, 20.0, 30.0]]),
+            ..Frame::with_len(1)
+        };
+        let b = Frame {
+            velocities: Some(vec![[3.0, 4.0, -6.0]]),
+            ..Frame::with_len(1)
+        };
+
+        let frame = Frame::interpolate(&a, &b, 0.25)?;
+        let velocities = frame.velocities.expect("Both frames have velocities");
+        assert_approx_eq!(velocities[0][0], 1.5);
+        assert_approx_eq!(velocities[0][1], 1.0);
+        assert_approx_eq!(velocities[0][2], -3.0);
+        // Only one frame has forces
+        assert!(frame.forces.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_pbc() -> Result<()> {
+        let mut a = Frame::with_len(1);
+        a.box_vector = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]];
+        a[0] = [9.5, 5.0, 5.0];
+        let mut b = a.clone();
+        b[0] = [0.5, 5.0, 5.0];
+
+        // The atom crossed the boundary, so it should not pass the box center
+        let mid = Frame::interpolate(&a, &b, 0.5)?;
+        assert_approx_eq!(mid[0][0], 10.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        let a = Frame::with_len(2);
+        let b = Frame::with_len(3);
+        assert!(matches!(
+            Frame::interpolate(&a, &b, 0.5),
+            Err(Error::WrongSizeFrame { .. })
+        ));
+        assert!(matches!(
+            Frame::interpolate(&a, &a, 1.5),
+            Err(Error::InvalidArgument { name: "t", .. })
+        ));
+    }
+
+    #[test]
+    fn test_center_of_mass() -> Result<()> {
+        let mut frame = Frame::with_len(2);
+        frame[0] = [0.0, 0.0, 0.0];
+        frame[1] = [3.0, 6.0, 9.0];
+
+        let com = frame.center_of_mass(None)?;
+        assert_eq!(com, [1.5, 3.0, 4.5]);
+        let com = frame.center_of_mass(Some(&[2.0, 1.0]))?;
+        assert_approx_eq!(com[0], 1.0);
+        assert_approx_eq!(com[1], 2.0);
+        assert_approx_eq!(com[2], 3.0);
+
+        assert!(matches!(
+            frame.center_of_mass(Some(&[1.0])),
+            Err(Error::WrongLength {
+                name: "masses",
+                expected: 2,
+                found: 1
+            })
+        ));
+
+        frame.translate([-1.0, -2.0, -3.0]);
+        assert_eq!(frame[1], [2.0, 4.0, 6.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_coords_f64() -> Result<()> {
+        let mut frame = Frame::with_len(3);
+        frame[0] = [0.1, -2.7, 1e-7];
+        frame[1] = [1234.567, 0.3, -0.0];
+        frame[2] = [1.0 / 3.0, 7.0, 3.4e8];
+        frame.box_vector = [[2.5, 0.0, 0.0], [0.0, 0.1, 0.0], [0.0, 0.0, 1.7]];
+
+        let wide = frame.coords_f64();
+        assert_eq!(wide.len(), frame.len());
+        for (w, x) in wide.iter().zip(&frame.coords) {
+            assert_eq!(w.map(|c| c as f32), *x);
+            assert_eq!(*w, x.map(f64::from));
+        }
+        assert_eq!(
+            frame.box_vector_f64()[1][1],
+            f64::from(frame.box_vector[1][1])
+        );
+
+        let com = frame.center_of_mass_f64(None)?;
+        assert_eq!(com.map(|c| c as f32), frame.center_of_mass(None)?);
+        let tensor = frame.inertia_tensor_f64(None)?;
+        assert_eq!(
+            tensor.map(|row| row.map(|t| t as f32)),
+            frame.inertia_tensor(None)?
+        );
+
+        // The center halfway between adjacent floats is not rounded first
+        let mut frame = Frame::with_len(2);
+        let next = f32::from_bits(10000.0f32.to_bits() + 1);
+        frame[0] = [10000.0, 0.0, 0.0];
+        frame[1] = [next, 0.0, 0.0];
+        let half = (f64::from(next) - 10000.0) / 2.0;
+        assert_eq!(frame.inertia_tensor_f64(None)?[1][1], 2.0 * half * half);
+        assert_eq!(frame.gyration_tensor_f64(None)?[0][0], half * half);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kinetic_energy() -> Result<()> {
+        let mut frame = Frame::with_len(2);
+        assert_eq!(
+            frame.kinetic_energy(&[1.0, 1.0]),
+            Err(Error::MissingData { name: "velocities" })
+        );
+        frame.velocities = Some(vec![[1.0, 0.0, 0.0], [0.0, -2.0, 2.0]]);
+        let masses = [2.0, 16.0];
+        // 0.5 * (2 * 1 + 16 * 8)
+        assert_approx_eq!(frame.kinetic_energy(&masses)?, 65.0);
+        assert!(matches!(
+            frame.kinetic_energy(&[1.0]),
+            Err(Error::WrongLength {
+                name: "masses",
+                expected: 2,
+                found: 1
+            })
+        ));
+
+        // 2 * 65 / (6 * 0.008314462618)
+        let temperature = frame.temperature(&masses, 6.0)?;
+        assert!((temperature - 2605.901).abs() < 1e-2, "{}", temperature);
+        assert!(matches!(
+            frame.temperature(&masses, 0.0),
+            Err(Error::InvalidArgument { name: "dof", .. })
+        ));
+        assert!(frame.temperature(&masses, f32::NAN).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_inertia_tensor() -> Result<()> {
+        let mut frame = Frame::with_len(2);
+        frame[0] = [1.0, 5.0, 5.0];
+        frame[1] = [3.0, 5.0, 5.0];
+        let tensor = frame.inertia_tensor(None)?;
+        assert_eq!(tensor, [[0.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]]);
+
+        // Heavier atoms pull the center of mass towards them
+        let tensor = frame.inertia_tensor(Some(&[3.0, 1.0]))?;
+        assert_approx_eq!(tensor[1][1], 3.0);
+        assert!(frame.inertia_tensor(Some(&[1.0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gyration_tensor() -> Result<()> {
+        // Atoms on a line along x have all of their spread in one direction
+        let mut frame = Frame::with_len(5);
+        for i in 0..5 {
+            frame[i] = [i as f32, 1.0, 2.0];
+        }
+        let tensor = frame.gyration_tensor(None)?;
+        assert_eq!(tensor, [[2.0, 0.0, 0.0], [0.0; 3], [0.0; 3]]);
+        let shape = frame.shape_descriptors(None)?;
+        assert_approx_eq!(shape.radius_of_gyration, 2.0_f32.sqrt());
+        assert_approx_eq!(shape.asphericity, 2.0);
+        assert_approx_eq!(shape.acylindricity, 0.0);
+        assert_approx_eq!(shape.relative_shape_anisotropy, 1.0);
+
+        // The corners of a cube are spread equally in every direction
+        let mut frame = Frame::with_len(8);
+        for i in 0..8 {
+            frame[i] = [0, 1, 2].map(|k| ((i >> k) & 1) as f32 * 2.0 - 1.0);
+        }
+        frame.rotate([[0.6, -0.8, 0.0], [0.8, 0.6, 0.0], [0.0, 0.0, 1.0]]);
+        let shape = frame.shape_descriptors(None)?;
+        assert_approx_eq!(shape.radius_of_gyration, 3.0_f32.sqrt());
+        assert_approx_eq!(shape.asphericity, 0.0);
+        assert_approx_eq!(shape.acylindricity, 0.0);
+        assert_approx_eq!(shape.relative_shape_anisotropy, 0.0);
+
+        // A flat square, the bottom face, is in between
+        let masses = [1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+        let shape = frame.shape_descriptors(Some(&masses))?;
+        assert_approx_eq!(shape.radius_of_gyration, 2.0_f32.sqrt());
+        assert_approx_eq!(shape.asphericity, 0.5);
+        assert_approx_eq!(shape.acylindricity, 1.0);
+        assert_approx_eq!(shape.relative_shape_anisotropy, 0.25);
+
+        assert!(frame.gyration_tensor(Some(&[1.0])).is_err());
+        let shape = Frame::with_len(3).shape_descriptors(None)?;
+        assert_eq!(shape.relative_shape_anisotropy, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_principal_axes() -> Result<()> {
+        // Atoms on a line along (1, 2, 2) / 3, with one slightly off it
+        let mut frame = Frame::with_len(5);
+        for i in 0..5 {
+            let t = i as f32 - 2.0;
+            frame[i] = [1.0 + t, 2.0 + 2.0 * t, -1.0 + 2.0 * t];
+        }
+        frame[2][0] += 0.1;
+        let masses = [1.0, 2.0, 3.0, 2.0, 1.0];
+        let (moments, axes) = frame.principal_axes(Some(&masses))?;
+
+        assert!(moments[0] <= moments[1] && moments[1] <= moments[2]);
+        assert!(moments[0] < 0.1);
+        let along = axes[0][0] / 3.0 + axes[0][1] * 2.0 / 3.0 + axes[0][2] * 2.0 / 3.0;
+        assert_approx_eq!(along.abs(), 1.0, 1e-3);
+
+        // The axes are orthonormal eigenvectors of the tensor
+        let tensor = frame.inertia_tensor(Some(&masses))?;
+        for (i, axis) in axes.iter().enumerate() {
+            for j in 0..3 {
+                let product: f32 = (0..3).map(|k| tensor[j][k] * axis[k]).sum();
+                assert_approx_eq!(product, moments[i] * axis[j], 1e-3);
+            }
+            for (k, other) in axes.iter().enumerate() {
+                let dot: f32 = (0..3).map(|j| axis[j] * other[j]).sum();
+                assert_approx_eq!(dot, if i == k { 1.0 } else { 0.0 }, 1e-5);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_center() -> Result<()> {
+        let mut frame = Frame::with_len(2);
+        frame.box_vector = [[2.0, 0.0, 0.0], [0.0, 4.0, 0.0], [1.0, 0.0, 6.0]];
+        frame[0] = [1.0, 1.0, 1.0];
+        frame[1] = [3.0, 5.0, 7.0];
+
+        frame.center(CenterMode::Origin)?;
+        assert_eq!(frame.coords, vec![[-1.0, -2.0, -3.0], [1.0, 2.0, 3.0]]);
+        frame.center(CenterMode::BoxCenter)?;
+        assert_eq!(frame.coords, vec![[0.5, 0.0, 0.0], [2.5, 4.0, 6.0]]);
+        frame.center(CenterMode::Atom(1))?;
+        assert_eq!(frame.coords, vec![[-2.0, -4.0, -6.0], [0.0, 0.0, 0.0]]);
+
+        assert!(matches!(
+            frame.center(CenterMode::Atom(2)),
+            Err(Error::InvalidAtomRange { start: 2, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_box_inverse() -> Result<()> {
+        let mut frame = Frame::new();
+        assert_eq!(frame.box_determinant(), 0.0);
+        assert!(matches!(frame.box_inverse(), Err(Error::SingularBox)));
+
+        // Two parallel box vectors
+        frame.box_vector = [[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 3.0]];
+        assert!(matches!(frame.box_inverse(), Err(Error::SingularBox)));
+
+        frame.box_vector =
+            Frame::box_matrix_from_lengths_angles([3.0, 4.0, 5.0], [60.0, 70.0, 80.0]);
+        let volume = frame.box_determinant();
+        assert!(volume > 0.0 && volume < 60.0);
+        let inverse = frame.box_inverse()?;
+        let m = frame.box_vector;
+        for i in 0..3 {
+            for j in 0..3 {
+                let identity = if i == j { 1.0 } else { 0.0 };
+                let left: f32 = (0..3).map(|k| inverse[i][k] * m[k][j]).sum();
+                let right: f32 = (0..3).map(|k| m[i][k] * inverse[k][j]).sum();
+                assert_approx_eq!(left, identity, 1e-5);
+                assert_approx_eq!(right, identity, 1e-5);
+            }
+        }
+
+        frame.box_vector = [[2.0, 0.0, 0.0], [0.0, 4.0, 0.0], [0.0, 0.0, 5.0]];
+        assert_eq!(frame.box_determinant(), 40.0);
+        assert_eq!(
+            frame.box_inverse()?,
+            [[0.5, 0.0, 0.0], [0.0, 0.25, 0.0], [0.0, 0.0, 0.2]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_gromacs_box() -> Result<()> {
+        let mut frame = Frame::with_len(2);
+        assert!(frame.is_valid_gromacs_box());
+        frame.box_vector = [[4.0, 0.0, 0.0], [2.0, 3.0, 0.0], [-1.0, 1.5, 5.0]];
+        assert!(frame.is_valid_gromacs_box());
+        frame.canonicalize_box()?;
+        assert_eq!(
+            frame.box_vector,
+            [[4.0, 0.0, 0.0], [2.0, 3.0, 0.0], [-1.0, 1.5, 5.0]]
+        );
+
+        // Too tilted: the same lattice, reduced
+        frame.box_vector = [[4.0, 0.0, 0.0], [5.0, 3.0, 0.0], [9.0, 7.0, 5.0]];
+        assert!(!frame.is_valid_gromacs_box());
+        frame.canonicalize_box()?;
+        assert_eq!(
+            frame.box_vector,
+            [[4.0, 0.0, 0.0], [1.0, 3.0, 0.0], [-1.0, 1.0, 5.0]]
+        );
+        assert!(frame.is_valid_gromacs_box());
+
+        // A rotated cube, with atoms rotated along
+        frame.box_vector = [[0.0, 2.0, 0.0], [-2.0, 0.0, 0.0], [0.0, 0.0, 2.0]];
+        frame[1] = [0.0, 1.0, 0.5];
+        frame.velocities = Some(vec![[0.0, 0.0, 1.0], [-3.0, 0.0, 0.0]]);
+        assert!(!frame.is_valid_gromacs_box());
+        frame.canonicalize_box()?;
+        for (found, expected) in frame
+            .box_vector
+            .iter()
+            .flatten()
+            .zip(&[2.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0])
+        {
+            assert_approx_eq!(found, expected);
+        }
+        assert!(frame.is_valid_gromacs_box());
+        assert_approx_eq!(frame[1][0], 1.0);
+        assert_approx_eq!(frame[1][1], 0.0);
+        assert_approx_eq!(frame[1][2], 0.5);
+        let velocities = frame.velocities.as_ref().expect("Velocities are kept");
+        assert_approx_eq!(velocities[1][0], 0.0);
+        assert_approx_eq!(velocities[1][1], 3.0);
+
+        // Left-handed and flat boxes cannot be fixed
+        let left = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]];
+        frame.box_vector = left;
+        assert!(!frame.is_valid_gromacs_box());
+        assert!(matches!(
+            frame.canonicalize_box(),
+            Err(Error::InvalidArgument {
+                name: "box_vector",
+                ..
+            })
+        ));
+        assert_eq!(frame.box_vector, left);
+        frame.box_vector = [[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(frame.canonicalize_box(), Err(Error::SingularBox));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrap_around() {
+        let mut frame = Frame::with_len(4);
+        frame.box_vector = [[4.0, 0.0, 0.0], [1.0, 4.0, 0.0], [0.0, 0.0, 0.0]];
+        frame[0] = [10.5, 0.0, 7.0];
+        frame[1] = [-3.0, 9.0, -2.0];
+        frame[2] = [1.5, 1.5, 0.0];
+        frame[3] = [-1.0, -1.0, 0.0];
+        let center = [1.0, 1.0, 0.0];
+        frame.wrap_around(center);
+
+        // Whole box vectors away from the original, and within half a box
+        assert_eq!(frame[0], [2.5, 0.0, 7.0]);
+        assert_eq!(frame[1], [3.0, 1.0, -2.0]);
+        assert_eq!(frame[2], [1.5, 1.5, 0.0]);
+        assert_eq!(frame[3], [0.0, 3.0, 0.0]);
+        for x in frame.coords.iter() {
+            let fractional_y = (x[1] - center[1]) / 4.0;
+            let fractional_x = (x[0] - center[0] - fractional_y) / 4.0;
+            assert!(fractional_x.abs() <= 0.5 && fractional_y.abs() <= 0.5);
+        }
+
+        // Without a box, nothing moves
+        let mut frame = Frame::with_len(1);
+        frame[0] = [100.0, -50.0, 3.0];
+        frame.wrap_around([0.0; 3]);
+        assert_eq!(frame[0], [100.0, -50.0, 3.0]);
+    }
+
+    #[test]
+    fn test_fractional() -> Result<()> {
+        let mut frame = Frame::with_len(3);
+        assert!(matches!(frame.to_fractional(), Err(Error::SingularBox)));
+
+        frame.box_vector = [[2.0, 0.0, 0.0], [1.0, 2.0, 0.0], [1.0, 1.0, 2.0]];
+        frame[0] = [0.0, 0.0, 0.0];
+        frame[1] = [4.0, 3.0, 2.0];
+        frame[2] = [-1.3, 7.2, 0.4];
+        let fractional = frame.to_fractional()?;
+        assert_eq!(fractional[0], [0.0; 3]);
+        // a + b + c, which a diagonal-only conversion would get wrong
+        assert_eq!(fractional[1], [1.0, 1.0, 1.0]);
+
+        let original = frame.coords.clone();
+        frame.from_fractional(&fractional)?;
+        for (x, expected) in frame.coords.iter().zip(&original) {
+            for k in 0..3 {
+                assert_approx_eq!(x[k], expected[k], 1e-5);
+            }
+        }
+
+        // The same fractional coordinates in a box twice the size
+        frame.scale(2.0);
+        let scaled = frame.to_fractional()?;
+        assert_approx_eq!(scaled[2][1], fractional[2][1], 1e-6);
+        assert!(matches!(
+            frame.from_fractional(&fractional[..2]),
+            Err(Error::WrongLength {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_appears_wrapped() {
+        let mut frame = Frame::with_len(3);
+        assert!(!frame.appears_wrapped());
+
+        frame.box_vector = [[2.0, 0.0, 0.0], [1.0, 2.0, 0.0], [0.0, 0.0, 2.0]];
+        frame[0] = [0.1, 0.1, 0.1];
+        // Inside the triclinic box, though outside the rectangle [0, 2]^3
+        frame[1] = [2.5, 1.5, 1.0];
+        // On the far corner, just past it by rounding
+        frame[2] = [3.0, 2.0, 2.0005];
+        assert!(frame.appears_wrapped());
+
+        frame[2] = [0.5, 0.5, 2.5];
+        assert!(!frame.appears_wrapped());
+        frame[2] = [-0.1, 0.5, 0.5];
+        assert!(!frame.appears_wrapped());
+    }
+
+    #[test]
+    fn test_scale_rotate() {
+        let mut frame = Frame::with_len(2);
+        frame.box_vector = [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.5, 0.0, 3.0]];
+        frame[0] = [1.0, 0.0, 0.0];
+        frame[1] = [1.0, 2.0, 3.0];
+
+        frame.scale(10.0);
+        assert_eq!(frame[1], [10.0, 20.0, 30.0]);
+        assert_eq!(frame.box_vector[2], [5.0, 0.0, 30.0]);
+
+        // 90 degrees about z
+        frame.rotate([[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(frame[0], [0.0, 10.0, 0.0]);
+        assert_eq!(frame[1], [-20.0, 10.0, 30.0]);
+        assert_eq!(frame.box_vector[2], [5.0, 0.0, 30.0]);
+    }
+
+    #[test]
+    fn test_atoms_in_slab() -> Result<()> {
+        let frame = Frame {
+            step: 0,
+            time: 0.0,
+            box_vector: [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]],
+            coords: vec![
+                [1.0, 1.0, 1.0],
+                [1.0, 1.0, 4.5],
+                [5.0, 5.0, 5.5],
+                [1.0, 1.0, 14.8], // wraps to z = 4.8
+                [1.0, 1.0, 7.0],
+            ],
+            ..Default::default()
+        };
+        assert_eq!(frame.atoms_in_slab(2, 4.0, 6.0)?, vec![1, 2, 3]);
+        assert_eq!(frame.atoms_in_slab(0, 4.0, 6.0)?, vec![2]);
+        assert!(matches!(
+            frame.atoms_in_slab(3, 4.0, 6.0),
+            Err(Error::InvalidArgument { name: "axis", .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_ref() {
+        fn sum_x(coords: impl AsRef<[[f32; 3]]>) -> f32 {
+            coords.as_ref().iter().map(|x| x[0]).sum()
+        }
+
+        let mut frame = Frame {
+            step: 0,
+            time: 0.0,
+            box_vector: [[0.0; 3]; 3],
+            coords: vec![[1.0; 3], [2.0; 3], [3.0; 3]],
+            ..Default::default()
+        };
+        assert_approx_eq!(sum_x(&frame), 6.0);
+        assert_approx_eq!(sum_x(frame.clone()), 6.0);
+
+        frame.as_mut()[0] = [4.0; 3];
+        assert_approx_eq!(sum_x(&frame), 9.0);
+        assert_eq!(frame.as_ref().len(), frame.len());
+    }
+
     #[test]
     #[allow(unused_mut)]
     fn test_index() {
@@ -136,7 +2440,8 @@ mod tests {
             step: 0,
             time: 0.0,
             box_vector: [[0.0; 3]; 3],
-            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3]]
+            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3]],
+            ..Default::default()
         };
         for i in 0..frame.len() {
             for j in 0..3 {
@@ -149,7 +2454,8 @@ mod tests {
             step: 0,
             time: 0.0,
             box_vector: [[0.0; 3]; 3],
-            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3]]
+            coords: vec![[0.0; 3], [1.0; 3], [2.0; 3]],
+            ..Default::default()
         };
         for i in 0..frame.len() {
             for j in 0..3 {
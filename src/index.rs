@@ -0,0 +1,183 @@
+//! Byte offsets of the frames in a trajectory file, for random access
+
+/// Position, step and time of a single frame in a trajectory file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    /// Byte offset of the start of the frame's header
+    pub offset: u64,
+    pub step: usize,
+    pub time: f32,
+}
+
+/// Byte offset, step and time of every frame in a trajectory file
+///
+/// XTC frames are compressed to a variable length, so finding a frame
+/// requires reading the headers of all frames before it. An index records
+/// those positions in a single pass, after which any frame can be reached
+/// with one seek.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrajectoryIndex {
+    entries: Vec<IndexEntry>,
+    /// True if the times never decrease from one entry to the next
+    times_sorted: bool,
+}
+
+impl TrajectoryIndex {
+    /// Create an index from its entries, which should be ordered by offset
+    pub fn new(entries: Vec<IndexEntry>) -> Self {
+        let times_sorted = entries.windows(2).all(|w| w[0].time <= w[1].time);
+        TrajectoryIndex {
+            entries,
+            times_sorted,
+        }
+    }
+
+    /// Number of frames in the index
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// True if the index contains no frames
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Byte offset of frame `i`, or None if it is out of range
+    pub fn offset_of(&self, i: usize) -> Option<u64> {
+        self.entries.get(i).map(|e| e.offset)
+    }
+
+    /// Time of frame `i`, or None if it is out of range
+    pub fn time_of(&self, i: usize) -> Option<f32> {
+        self.entries.get(i).map(|e| e.time)
+    }
+
+    /// Step of frame `i`, or None if it is out of range
+    pub fn step_of(&self, i: usize) -> Option<usize> {
+        self.entries.get(i).map(|e| e.step)
+    }
+
+    /// First frame with a time of at least `time`, or None if there is none
+    ///
+    /// This is a binary search, so it assumes that the times never decrease
+    /// through the file, as in almost all trajectories.
+    pub fn first_at_time(&self, time: f32) -> Option<usize> {
+        let k = self.entries.partition_point(|e| e.time < time);
+        Some(k).filter(|&k| k < self.entries.len())
+    }
+
+    /// Frame with the time closest to `time`, or None if the index is empty
+    ///
+    /// Of two frames equally close, the earlier one is returned. If the times
+    /// never decrease through the file, as in almost all trajectories, this
+    /// is a binary search. Otherwise, for example after a restart that
+    /// rewound the clock, every frame is compared instead, which finds the
+    /// closest frame all the same.
+    pub fn nearest_time(&self, time: f32) -> Option<usize> {
+        let distance = |i: usize| (self.entries[i].time - time).abs();
+        if !self.times_sorted {
+            return (0..self.entries.len()).min_by(|&i, &j| distance(i).total_cmp(&distance(j)));
+        }
+        let k = self.entries.partition_point(|e| e.time < time);
+        match (
+            k.checked_sub(1),
+            Some(k).filter(|&k| k < self.entries.len()),
+        ) {
+            (Some(before), Some(after)) if distance(after) < distance(before) => Some(after),
+            (Some(before), _) => Some(before),
+            (None, after) => after,
+        }
+    }
+
+    /// Earliest and latest time of any frame, or None if the index is empty
+    pub fn time_range(&self) -> Option<(f32, f32)> {
+        let mut times = self.entries.iter().map(|e| e.time);
+        let first = times.next()?;
+        Some(times.fold((first, first), |(min, max), t| (min.min(t), max.max(t))))
+    }
+
+    /// All entries of the index, ordered by offset
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_build_index() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let xtc_index = xtc.build_index()?;
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let trr_index = trr.build_index()?;
+
+        assert_eq!(xtc_index.len(), 38);
+        assert_eq!(trr_index.len(), 38);
+        assert_eq!(xtc_index.offset_of(0), Some(0));
+        assert_eq!(trr_index.offset_of(0), Some(0));
+        assert_eq!(xtc_index.offset_of(38), None);
+
+        // Build the index by reading frame by frame to compare
+        let mut frame = Frame::with_len(304);
+        for entry in xtc_index.entries() {
+            assert_eq!(xtc.tell(), entry.offset);
+            xtc.read(&mut frame)?;
+            assert_eq!(frame.step, entry.step);
+            assert_eq!(frame.time, entry.time);
+        }
+        for entry in trr_index.entries() {
+            assert_eq!(trr.tell(), entry.offset);
+            trr.read(&mut frame)?;
+            assert_eq!(frame.step, entry.step);
+            assert_eq!(frame.time, entry.time);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_nearest_time() {
+        let index = |times: &[f32]| {
+            let entries = times.iter().enumerate().map(|(i, &time)| IndexEntry {
+                offset: i as u64 * 100,
+                step: i,
+                time,
+            });
+            TrajectoryIndex::new(entries.collect())
+        };
+        let sorted = index(&[0.0, 1.0, 2.0, 4.0]);
+        assert_eq!(sorted.nearest_time(1.4), Some(1));
+        assert_eq!(sorted.nearest_time(1.6), Some(2));
+        assert_eq!(sorted.nearest_time(3.0), Some(2));
+        assert_eq!(sorted.nearest_time(-5.0), Some(0));
+        assert_eq!(sorted.nearest_time(10.0), Some(3));
+        assert_eq!(sorted.time_range(), Some((0.0, 4.0)));
+
+        // A restart that went back in time
+        let restarted = index(&[0.0, 1.0, 2.0, 1.5, 2.5, 3.5]);
+        assert_eq!(restarted.nearest_time(1.6), Some(3));
+        assert_eq!(restarted.nearest_time(3.2), Some(5));
+        assert_eq!(restarted.time_range(), Some((0.0, 3.5)));
+
+        let empty = index(&[]);
+        assert_eq!(empty.nearest_time(1.0), None);
+        assert_eq!(empty.time_range(), None);
+    }
+
+    #[test]
+    fn test_build_index_keeps_position() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(304);
+        xtc.read(&mut frame)?;
+        let pos = xtc.tell();
+
+        let index = xtc.build_index()?;
+        assert_eq!(xtc.tell(), pos);
+        assert_eq!(index.offset_of(1), Some(pos));
+
+        xtc.read(&mut frame)?;
+        assert_eq!(frame.step, 2);
+        Ok(())
+    }
+}
@@ -1,15 +1,18 @@
 use crate::*;
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::io::{self, Seek, SeekFrom};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-fn into_iter_inner<T: Trajectory>(mut traj: T) -> TrajectoryIterator<T> {
-    let num_atoms = traj.get_num_atoms();
-    let frame = match &num_atoms {
-        Ok(num_atoms) => Frame::with_len(*num_atoms),
-        Err(_) => Frame::new(),
-    };
+/// Wrap a trajectory in an iterator. No I/O happens until the first frame is requested.
+pub(crate) fn into_iter_inner<T: Trajectory>(traj: T) -> TrajectoryIterator<T> {
     TrajectoryIterator {
         trajectory: traj,
-        item: Rc::new(frame),
+        item: Rc::new(Frame::new()),
         has_error: false,
     }
 }
@@ -32,6 +35,90 @@ impl IntoIterator for TRRTrajectory {
     }
 }
 
+/// Iterate over the remaining frames of a borrowed trajectory, see
+/// `Trajectory::frames`
+impl<'a> IntoIterator for &'a mut XTCTrajectory {
+    type Item = Result<Rc<Frame>>;
+    type IntoIter = TrajectoryIterator<&'a mut XTCTrajectory>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        into_iter_inner(self)
+    }
+}
+
+/// Iterate over the remaining frames of a borrowed trajectory, see
+/// `Trajectory::frames`
+impl<'a> IntoIterator for &'a mut TRRTrajectory {
+    type Item = Result<Rc<Frame>>;
+    type IntoIter = TrajectoryIterator<&'a mut TRRTrajectory>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        into_iter_inner(self)
+    }
+}
+
+/// Iterate over a shared trajectory by reopening its file for reading
+///
+/// The original trajectory is not read from, so its position is unchanged and
+/// the same trajectory can be iterated any number of times. Since the file is
+/// opened again by path, frames that a trajectory open for writing has not
+/// flushed yet are not seen.
+impl IntoIterator for &XTCTrajectory {
+    type Item = Result<Rc<Frame>>;
+    type IntoIter = Reopened<XTCTrajectory>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Reopened::new(XTCTrajectory::open_read(&self.handle.path))
+    }
+}
+
+/// Iterate over a shared trajectory by reopening its file for reading
+///
+/// See the implementation for `&XTCTrajectory`.
+impl IntoIterator for &TRRTrajectory {
+    type Item = Result<Rc<Frame>>;
+    type IntoIter = Reopened<TRRTrajectory>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Reopened::new(TRRTrajectory::open_read(&self.handle.path))
+    }
+}
+
+/// Iterator over a reopened copy of a trajectory, created by iterating over a
+/// shared reference to it
+///
+/// If the file could not be reopened, the error is yielded as the only item.
+pub struct Reopened<T> {
+    frames: Option<TrajectoryIterator<T>>,
+    error: Option<Error>,
+}
+
+impl<T: Trajectory> Reopened<T> {
+    fn new(trajectory: Result<T>) -> Self {
+        match trajectory {
+            Ok(trajectory) => Reopened {
+                frames: Some(into_iter_inner(trajectory)),
+                error: None,
+            },
+            Err(e) => Reopened {
+                frames: None,
+                error: Some(e),
+            },
+        }
+    }
+}
+
+impl<T: Trajectory> Iterator for Reopened<T> {
+    type Item = Result<Rc<Frame>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.frames {
+            Some(frames) => frames.next(),
+            None => self.error.take().map(Err),
+        }
+    }
+}
+
 /// Iterator for trajectories.
 /// This iterator yields a Result<Frame, Error> for each frame in the
 /// trajectory file and stops with yielding None once the trajectory is
@@ -57,10 +144,14 @@ impl<T: Trajectory> TrajectoryIterator<T> {
             Some(item) => item,
             None => {
                 // caller kept frame. Create new one
-                self.item = Rc::new(Frame::with_len(num_atoms as usize));
+                self.item = Rc::new(Frame::with_len(num_atoms));
                 Rc::get_mut(&mut self.item).expect("Could not get mutable access to new Rc")
             }
         };
+        // The first frame is only sized once the number of atoms is known
+        if item.len() != num_atoms {
+            item.resize(num_atoms);
+        }
 
         self.trajectory.read(item)?;
         Ok(Rc::clone(&self.item))
@@ -89,7 +180,408 @@ where
     }
 }
 
+impl<T: Trajectory> TrajectoryIterator<T> {
+    /// Pair each frame with the one before it
+    ///
+    /// For N frames, this yields N-1 pairs `(previous, current)`. Because the
+    /// previous frame is kept alive, the iterator can no longer reuse its frame
+    /// buffer and allocates a new frame for every step.
+    pub fn pairwise(self) -> Pairwise<Self> {
+        Pairwise {
+            frames: self,
+            prev: None,
+        }
+    }
+}
+
+impl<T: Trajectory> TrajectoryIterator<T> {
+    /// Pair each frame with up to `n` frames before it, oldest first
+    ///
+    /// The history starts empty, grows by one frame per step until it holds
+    /// `n` frames, and then slides along the trajectory. As with `pairwise`,
+    /// the iterator can no longer reuse its frame buffer, and `n + 1` frames
+    /// are kept in memory at once: budget for `n + 1` times the size of a
+    /// frame.
+    ///
+    /// Use `next_with_history` to borrow the history without copying it, or
+    /// iterate to get it as a `Vec` of shared frames.
+    pub fn with_history(self, n: usize) -> WithHistory<Self> {
+        WithHistory {
+            frames: self,
+            history: VecDeque::with_capacity(n + 1),
+            current: None,
+            n,
+        }
+    }
+}
+
+impl<T: Trajectory> TrajectoryIterator<T> {
+    /// Center each frame according to `mode`, see `Frame::center`
+    ///
+    /// As with `pairwise`, the frame buffer cannot be reused: the shared frame
+    /// is copied before centering, so every step yields a new frame.
+    pub fn centered(self, mode: CenterMode) -> Centered<Self> {
+        Centered { frames: self, mode }
+    }
+}
+
+impl<T: Trajectory> TrajectoryIterator<T> {
+    /// Stop iterating once `token` is set
+    ///
+    /// The token is checked before each frame is read, and the iterator ends,
+    /// yielding `None`, as soon as it is found to be `true`; it then stays
+    /// ended even if the token is reset. This lets another thread, such as a
+    /// user interface, abort a long analysis cleanly. A frame that is already
+    /// being read is not interrupted: cancellation takes effect before the
+    /// next one.
+    pub fn cancellable(self, token: Arc<AtomicBool>) -> Cancellable<Self> {
+        Cancellable {
+            frames: self,
+            token,
+            cancelled: false,
+        }
+    }
+}
+
+impl<T: Trajectory> TrajectoryIterator<T> {
+    /// Apply `transform` to each frame before yielding it
+    ///
+    /// Further transforms can be chained with `transform` on the result, and
+    /// are applied in order. An error from a transform is yielded in place of
+    /// the frame. As with `centered`, the frame buffer cannot be reused: the
+    /// shared frame is copied before it is transformed, so every step yields
+    /// a new frame and the reading buffer is never modified.
+    pub fn transform<F: FrameTransform>(self, transform: F) -> Transformed<Self, F> {
+        Transformed {
+            frames: self,
+            transform,
+        }
+    }
+}
+
+/// Iterator over transformed frames, created by `TrajectoryIterator::transform`
+pub struct Transformed<I, F> {
+    frames: I,
+    transform: F,
+}
+
+impl<I, F> Transformed<I, F> {
+    /// Apply another transform after this one
+    pub fn transform<G: FrameTransform>(self, transform: G) -> Transformed<Self, G> {
+        Transformed {
+            frames: self,
+            transform,
+        }
+    }
+}
+
+impl<I, F> Iterator for Transformed<I, F>
+where
+    I: Iterator<Item = Result<Rc<Frame>>>,
+    F: FrameTransform,
+{
+    type Item = Result<Rc<Frame>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = match self.frames.next()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e)),
+        };
+        // Copies the frame if the underlying iterator still holds it
+        match self.transform.apply(Rc::make_mut(&mut frame)) {
+            Ok(()) => Some(Ok(frame)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Iterator that stops when cancelled, created by
+/// `TrajectoryIterator::cancellable`
+pub struct Cancellable<I> {
+    frames: I,
+    token: Arc<AtomicBool>,
+    cancelled: bool,
+}
+
+impl<I> Cancellable<I> {
+    /// True if the iterator stopped because the token was set
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+impl<I: Iterator> Iterator for Cancellable<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cancelled || self.token.load(Ordering::Relaxed) {
+            self.cancelled = true;
+            return None;
+        }
+        self.frames.next()
+    }
+}
+
+/// Iterator over the frames of a growing file, created by `Trajectory::follow`
+pub struct Follow<T> {
+    frames: TrajectoryIterator<T>,
+    poll_interval: Duration,
+    stop: Arc<AtomicBool>,
+}
+
+impl<T: Trajectory> Follow<T> {
+    pub(crate) fn new(trajectory: T, poll_interval: Duration) -> Self {
+        Follow {
+            frames: into_iter_inner(trajectory),
+            poll_interval,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl<T> Follow<T> {
+    /// Token that stops the iterator once set to `true`
+    ///
+    /// The token is checked before each frame is read and after each poll
+    /// interval spent waiting, so the iterator ends at most one interval
+    /// after it is set, even if no more frames arrive.
+    pub fn stop_token(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+}
+
+impl<T: Trajectory + Seek> Follow<T> {
+    /// The next frame, or None if the file has no complete frame after the
+    /// current position yet
+    fn next_inner(&mut self) -> Result<Option<Rc<Frame>>> {
+        let trajectory = &mut self.frames.trajectory;
+        let start = trajectory.stream_position().map_err(seek_error)?;
+        let len = trajectory.seek(SeekFrom::End(0)).map_err(seek_error)?;
+        trajectory
+            .seek(SeekFrom::Start(start))
+            .map_err(seek_error)?;
+        if start >= len {
+            return Ok(None);
+        }
+        match self.frames.next_inner() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(e) => {
+                // A read that ran into the end of the file as it was before
+                // found a frame that is not fully written yet
+                let trajectory = &mut self.frames.trajectory;
+                let end = trajectory.stream_position().map_err(seek_error)?;
+                trajectory
+                    .seek(SeekFrom::Start(start))
+                    .map_err(seek_error)?;
+                if e.is_eof() || end >= len {
+                    Ok(None)
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+}
+
+impl<T: Trajectory + Seek> Iterator for Follow<T> {
+    type Item = Result<Rc<Frame>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frames.has_error {
+            return None;
+        }
+        while !self.stop.load(Ordering::Relaxed) {
+            match self.next_inner() {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => thread::sleep(self.poll_interval),
+                Err(e) => {
+                    self.frames.has_error = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Convert an error from `io::Seek` on a trajectory back to the crate error
+fn seek_error(e: io::Error) -> Error {
+    match e.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+        Some(e) => e.clone(),
+        None => (e, ErrorTask::Seek).into(),
+    }
+}
+
+/// Iterator over centered frames, created by `TrajectoryIterator::centered`
+pub struct Centered<I> {
+    frames: I,
+    mode: CenterMode,
+}
+
+impl<I> Iterator for Centered<I>
+where
+    I: Iterator<Item = Result<Rc<Frame>>>,
+{
+    type Item = Result<Rc<Frame>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = match self.frames.next()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e)),
+        };
+        // Copies the frame if the underlying iterator still holds it
+        match Rc::make_mut(&mut frame).center(self.mode) {
+            Ok(()) => Some(Ok(frame)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Iterator over pairs of consecutive frames, created by `TrajectoryIterator::pairwise`
+pub struct Pairwise<I> {
+    frames: I,
+    prev: Option<Rc<Frame>>,
+}
+
+impl<I> Iterator for Pairwise<I>
+where
+    I: Iterator<Item = Result<Rc<Frame>>>,
+{
+    type Item = Result<(Rc<Frame>, Rc<Frame>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = match self.frames.next()? {
+                Ok(frame) => frame,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(prev) = self.prev.replace(Rc::clone(&frame)) {
+                return Some(Ok((prev, frame)));
+            }
+        }
+    }
+}
+
+/// A frame and the frames before it, oldest first, see `WithHistory`
+pub type FrameWithHistory<'a> = (Rc<Frame>, &'a [Rc<Frame>]);
+
+/// Iterator over frames and the frames before them, created by
+/// `TrajectoryIterator::with_history`
+pub struct WithHistory<I> {
+    frames: I,
+    history: VecDeque<Rc<Frame>>,
+    current: Option<Rc<Frame>>,
+    n: usize,
+}
+
+impl<I> WithHistory<I>
+where
+    I: Iterator<Item = Result<Rc<Frame>>>,
+{
+    /// Read the next frame, returning it with the frames before it
+    ///
+    /// The history is borrowed from the iterator, oldest frame first, and
+    /// holds at most `n` frames.
+    pub fn next_with_history(&mut self) -> Option<Result<FrameWithHistory<'_>>> {
+        let frame = match self.frames.next()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e)),
+        };
+        if let Some(previous) = self.current.replace(Rc::clone(&frame)) {
+            if self.n > 0 {
+                if self.history.len() == self.n {
+                    self.history.pop_front();
+                }
+                self.history.push_back(previous);
+            }
+        }
+        Some(Ok((frame, &*self.history.make_contiguous())))
+    }
+}
+
+impl<I> Iterator for WithHistory<I>
+where
+    I: Iterator<Item = Result<Rc<Frame>>>,
+{
+    type Item = Result<(Rc<Frame>, Vec<Rc<Frame>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(
+            self.next_with_history()?
+                .map(|(frame, history)| (frame, history.to_vec())),
+        )
+    }
+}
+
+/// Iterator over frames and their frame numbers, created by `indexed_frames`
+/// on the trajectory types
+pub struct IndexedFrames<T> {
+    frames: TrajectoryIterator<T>,
+    next_index: usize,
+}
+
+impl<T> IndexedFrames<T> {
+    pub(crate) fn new(frames: TrajectoryIterator<T>, start: usize) -> Self {
+        IndexedFrames {
+            frames,
+            next_index: start,
+        }
+    }
+}
+
+impl<T: Trajectory> Iterator for IndexedFrames<T> {
+    type Item = (usize, Result<Rc<Frame>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.frames.next()?;
+        let index = self.next_index;
+        self.next_index += 1;
+        Some((index, item))
+    }
+}
+
+/// Sink that writes each frame pushed into it, created by `Trajectory::sink`
+pub struct FrameSink<T> {
+    trajectory: T,
+    num_written: usize,
+}
+
+impl<T: Trajectory> FrameSink<T> {
+    pub(crate) fn new(trajectory: T) -> Self {
+        FrameSink {
+            trajectory,
+            num_written: 0,
+        }
+    }
+
+    /// Write `frame` to the trajectory
+    ///
+    /// This takes a `Frame`, a reference to one, or the `Rc<Frame>` yielded
+    /// by a trajectory iterator.
+    pub fn push(&mut self, frame: impl Borrow<Frame>) -> Result<()> {
+        self.trajectory.write(frame.borrow())?;
+        self.num_written += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far
+    pub fn num_written(&self) -> usize {
+        self.num_written
+    }
+
+    /// Flush the trajectory and return the number of frames written
+    ///
+    /// Dropping the sink instead also flushes when the trajectory is closed,
+    /// but any error is lost.
+    pub fn finish(mut self) -> Result<usize> {
+        self.trajectory.flush()?;
+        Ok(self.num_written)
+    }
+}
+
 #[cfg(test)]
+// The original tests predate this lint
+#[allow(non_fmt_panics)]
 mod tests {
     use super::*;
 
@@ -104,6 +596,246 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn test_lazy_open() -> Result<()> {
+        // Neither opening nor creating the iterator reads the file
+        let traj = XTCTrajectory::open_read("README.md")?;
+        let mut iter = traj.into_iter();
+        let first = iter.next().expect("Iterator should yield the error");
+        assert!(matches!(first, Err(Error::CouldNotCheckNAtoms(_))));
+        assert!(iter.next().is_none());
+
+        // The file may even be filled in after opening
+        let tempfile = tempfile::NamedTempFile::new().expect("Could not create temporary file");
+        let traj = XTCTrajectory::open_read(tempfile.path())?;
+        let iter = traj.into_iter();
+        std::fs::copy("tests/1l2y.xtc", tempfile.path()).expect("Could not copy trajectory");
+        assert_eq!(iter.count(), 38);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_shared_iterator() -> Result<()> {
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(304);
+        traj.read(&mut frame)?;
+        let position = traj.tell();
+
+        let shared = &traj;
+        let first: Result<Vec<_>> = shared
+            .into_iter()
+            .map(|f| f.map(|f| f.coords.clone()))
+            .collect();
+        let second: Result<Vec<_>> = shared
+            .into_iter()
+            .map(|f| f.map(|f| f.coords.clone()))
+            .collect();
+        let (first, second) = (first?, second?);
+        assert_eq!(first.len(), 38);
+        assert_eq!(first, second);
+        assert_eq!(first[0], frame.coords);
+
+        // The original is left where it was
+        assert_eq!(traj.tell(), position);
+        traj.read(&mut frame)?;
+        assert_eq!(frame.step, 2);
+
+        let trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        assert_eq!((&trr).into_iter().count(), 38);
+        assert_eq!((&trr).into_iter().count(), 38);
+
+        // A file that has gone away is reported by the first item
+        let tempfile = tempfile::NamedTempFile::new().expect("Could not create temporary file");
+        let traj = XTCTrajectory::open_read(tempfile.path())?;
+        drop(tempfile);
+        let mut frames = (&traj).into_iter();
+        assert!(matches!(
+            frames.next(),
+            Some(Err(Error::CouldNotOpen { .. }))
+        ));
+        assert!(frames.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_borrowed_iterator() -> Result<()> {
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        assert_eq!(traj.frames().count(), 38);
+
+        // The trajectory is still usable afterwards
+        traj.seek_frame(36)?;
+        let steps: Result<Vec<_>> = (&mut traj).into_iter().map(|f| f.map(|f| f.step)).collect();
+        assert_eq!(steps?, vec![37, 38]);
+
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut count = 0;
+        for frame in &mut trr {
+            assert_eq!(frame?.len(), 304);
+            count += 1;
+        }
+        assert_eq!(count, 38);
+        assert!(trr.at_eof()?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_cancellable() -> Result<()> {
+        let token = Arc::new(AtomicBool::new(false));
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frames = traj.into_iter().cancellable(Arc::clone(&token));
+        let mut steps = vec![];
+        for frame in &mut frames {
+            steps.push(frame?.step);
+            if steps.len() == 3 {
+                token.store(true, Ordering::Relaxed);
+            }
+        }
+        assert_eq!(steps, vec![1, 2, 3]);
+        assert!(frames.is_cancelled());
+
+        // Resetting the token does not resume the iteration
+        token.store(false, Ordering::Relaxed);
+        assert!(frames.next().is_none());
+
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frames = traj.into_iter().cancellable(token);
+        assert_eq!((&mut frames).count(), 38);
+        assert!(!frames.is_cancelled());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_pairwise() -> Result<()> {
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let pairs: Result<Vec<_>> = traj.into_iter().pairwise().collect();
+        let pairs = pairs?;
+        assert_eq!(pairs.len(), 37);
+        for (prev, current) in &pairs {
+            assert_eq!(prev.step + 1, current.step);
+        }
+        assert_eq!(pairs[0].0.step, 1);
+        assert_eq!(pairs[36].1.step, 38);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_with_history() -> Result<()> {
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frames = traj.into_iter().with_history(3);
+        let mut num_frames = 0;
+        while let Some(result) = frames.next_with_history() {
+            let (frame, history) = result?;
+            let steps: Vec<usize> = history.iter().map(|f| f.step).collect();
+            let first = frame.step.saturating_sub(3).max(1);
+            assert_eq!(steps, (first..frame.step).collect::<Vec<_>>());
+            num_frames += 1;
+        }
+        assert_eq!(num_frames, 38);
+
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let lengths: Result<Vec<usize>> = traj
+            .into_iter()
+            .with_history(2)
+            .map(|r| r.map(|(_, history)| history.len()))
+            .take(4)
+            .collect();
+        assert_eq!(lengths?, vec![0, 1, 2, 2]);
+
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frames = traj.into_iter().with_history(0);
+        assert!(frames.all(|r| r.unwrap().1.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_transform() -> Result<()> {
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let frames: Result<Vec<_>> = traj
+            .into_iter()
+            .transform(CenterMode::Atom(0))
+            .transform(WrapAround([0.0; 3]))
+            .transform(Scale(10.0))
+            .collect();
+        let frames = frames?;
+        assert_eq!(frames.len(), 38);
+
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        for (frame, original) in frames.iter().zip(traj) {
+            let mut expected = (*original?).clone();
+            expected.center(CenterMode::Atom(0))?;
+            expected.wrap_around([0.0; 3]);
+            expected.scale(10.0);
+            assert_eq!(frame.coords, expected.coords);
+            assert_eq!(frame[0], [0.0; 3]);
+            // Within half a box of the first atom
+            for s in frame.to_fractional()?.iter().flatten() {
+                assert!(s.abs() <= 0.5 + 1e-4, "{}", s);
+            }
+        }
+
+        // Errors of a transform take the place of the frame
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let fail = |_: &mut Frame| Err(Error::MissingData { name: "test" });
+        let mut frames = traj.into_iter().transform(Wrap).transform(fail);
+        assert!(matches!(
+            frames.next(),
+            Some(Err(Error::MissingData { name: "test" }))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_centered() -> Result<()> {
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let frames: Result<Vec<_>> = traj.into_iter().centered(CenterMode::Atom(7)).collect();
+        let frames = frames?;
+        assert_eq!(frames.len(), 38);
+        for frame in &frames {
+            assert_eq!(frame[7], [0.0; 3]);
+        }
+        // Each frame was centered once, on its own coordinates
+        assert_ne!(frames[0][0], frames[1][0]);
+
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        for frame in traj.into_iter().centered(CenterMode::Origin) {
+            let center = frame?.center_of_mass(None)?;
+            for x in center.iter() {
+                assert!(x.abs() < 1e-4, "{:?}", center);
+            }
+        }
+
+        let traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frames = traj.into_iter().centered(CenterMode::Atom(304));
+        assert!(matches!(
+            frames.next(),
+            Some(Err(Error::InvalidAtomRange { .. }))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_sink() -> Result<()> {
+        let tempfile = tempfile::NamedTempFile::new().expect("Could not create temporary file");
+        let mut sink = TRRTrajectory::open_write(tempfile.path())?.sink();
+        XTCTrajectory::open_read("tests/1l2y.xtc")?
+            .into_iter()
+            .try_for_each(|frame| sink.push(frame?))?;
+        assert_eq!(sink.num_written(), 38);
+        assert_eq!(sink.finish()?, 38);
+
+        let written = TRRTrajectory::open_read(tempfile.path())?.into_iter();
+        let original = XTCTrajectory::open_read("tests/1l2y.xtc")?.into_iter();
+        let mut num_frames = 0;
+        for (written, original) in written.zip(original) {
+            let (written, original) = (written?, original?);
+            assert_eq!(written.step, original.step);
+            assert_eq!(written.coords, original.coords);
+            num_frames += 1;
+        }
+        assert_eq!(num_frames, 38);
+        Ok(())
+    }
+
     #[test]
     pub fn test_trr_trajectory_iterator() -> Result<()> {
         let traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
@@ -114,4 +846,71 @@ mod tests {
         assert!(frames[37].step == 38);
         Ok(())
     }
+
+    #[test]
+    fn test_follow() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        fn follow_growing<T: Trajectory + Seek>(
+            source: &str,
+            open: impl Fn(&Path) -> Result<T>,
+        ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            let bytes = std::fs::read(source)?;
+            let mut reference = open(Path::new(source))?;
+            let mut expected = Vec::new();
+            while let Some(frame) = reference.read_owned()? {
+                expected.push((frame, reference.stream_position()? as usize));
+            }
+            let tempfile = tempfile::NamedTempFile::new()?;
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(tempfile.path())?;
+            let mut frames = open(tempfile.path())?.follow(Duration::from_millis(1));
+
+            // Another thread appends each frame in two pieces, so that the
+            // first ends in the middle of the frame. After the first piece it
+            // waits until the follower has checked that there is no frame yet,
+            // then writes the rest while the follower waits for it.
+            thread::scope(|scope| {
+                let (written, wait_written) = std::sync::mpsc::channel();
+                let (checked, wait_checked) = std::sync::mpsc::channel();
+                let (bytes, expected) = (&bytes, &expected);
+                let writer = scope.spawn(move || -> io::Result<()> {
+                    let mut start = 0;
+                    for (_, end) in expected {
+                        let middle = (start + end) / 2;
+                        io::Write::write_all(&mut file, &bytes[start..middle])?;
+                        io::Write::flush(&mut file)?;
+                        // The follower hangs up if it fails
+                        if written.send(()).is_err() || wait_checked.recv().is_err() {
+                            return Ok(());
+                        }
+                        io::Write::write_all(&mut file, &bytes[middle..*end])?;
+                        io::Write::flush(&mut file)?;
+                        start = *end;
+                    }
+                    Ok(())
+                });
+                for (frame, _) in expected {
+                    wait_written.recv()?;
+                    let partial = frames.next_inner();
+                    checked.send(())?;
+                    assert!(partial?.is_none());
+                    let read = frames.next().expect("Frame should be complete")?;
+                    assert_eq!(read.step, frame.step);
+                    assert_eq!(read.coords, frame.coords);
+                }
+                writer.join().expect("Writer thread panicked")?;
+                assert!(frames.next_inner()?.is_none());
+                Ok(())
+            })
+        }
+
+        follow_growing("tests/1l2y.xtc", |path| XTCTrajectory::open_read(path))?;
+        follow_growing("tests/1l2y.trr", |path| TRRTrajectory::open_read(path))?;
+
+        // Stopped before the first frame
+        let frames = XTCTrajectory::open_read("tests/1l2y.xtc")?.follow(Duration::ZERO);
+        frames.stop_token().store(true, Ordering::Relaxed);
+        assert_eq!(frames.count(), 0);
+        Ok(())
+    }
 }
@@ -55,19 +55,208 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! # Threads
+//! Trajectories are `Send` but not `Sync`: a trajectory can be moved to
+//! another thread, but not used from several threads at once, because every
+//! read, write and seek moves the position of its file handle. To process a
+//! file on several threads, open it once per thread, as `par_map_reduce`
+//! does with the `rayon` feature.
+//! Frames are both `Send` and `Sync`. The iterator of a trajectory yields
+//! `Rc<Frame>` and is therefore neither, so move the trajectory into the
+//! thread before iterating over it.
+//!
+//! ```rust
+//! use xdrfile::*;
+//!
+//! fn main() -> Result<()> {
+//!     let trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+//!     let num_frames = std::thread::spawn(move || trj.into_iter().count())
+//!         .join()
+//!         .expect("Reader thread panicked");
+//!     assert_eq!(num_frames, 38);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Sharing a trajectory between threads does not compile:
+//!
+//! ```rust,compile_fail
+//! use xdrfile::*;
+//!
+//! fn share<T: Sync>(_: &T) {}
+//!
+//! let trj = XTCTrajectory::open_read("tests/1l2y.xtc").unwrap();
+//! share(&trj);
+//! ```
+//!
+//! ```rust,compile_fail
+//! use xdrfile::*;
+//!
+//! fn share<T: Sync>(_: &T) {}
+//!
+//! let trj = TRRTrajectory::open_read("tests/1l2y.trr").unwrap();
+//! share(&trj);
+//! ```
 
 #[cfg(test)]
 #[macro_use]
 extern crate assert_approx_eq;
 extern crate lazy_init;
 
+/// Implement the required and overridable methods of `Trajectory` by calling
+/// them on `$inner`, an expression of the wrapped trajectory in terms of
+/// `$this`, which stands for `self`
+///
+/// Wrappers that change how frames are read pass `except reads` and implement
+/// `read` and `read_atom_range` themselves. Defined before the modules so that
+/// all of them can use it.
+macro_rules! forward_trajectory {
+    (|$this:ident| $inner:expr) => {
+        fn read(&mut self, frame: &mut Frame) -> Result<()> {
+            let $this = self;
+            $inner.read(frame)
+        }
+
+        fn read_atom_range(&mut self, frame: &mut Frame, start: usize, end: usize) -> Result<()> {
+            let $this = self;
+            $inner.read_atom_range(frame, start, end)
+        }
+
+        forward_trajectory!(|$this| $inner, except reads);
+    };
+    (|$this:ident| $inner:expr, except reads) => {
+        fn write(&mut self, frame: &Frame) -> Result<()> {
+            let $this = self;
+            $inner.write(frame)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            let $this = self;
+            $inner.flush()
+        }
+
+        fn get_num_atoms(&mut self) -> Result<usize> {
+            let $this = self;
+            $inner.get_num_atoms()
+        }
+
+        fn peek_step(&mut self) -> Result<Option<usize>> {
+            let $this = self;
+            $inner.peek_step()
+        }
+
+        fn peek_time(&mut self) -> Result<Option<f32>> {
+            let $this = self;
+            $inner.peek_time()
+        }
+
+        fn set_strict(&mut self, strict: bool) {
+            let $this = self;
+            $inner.set_strict(strict)
+        }
+
+        fn set_variable_atom_count(&mut self, allow: bool) {
+            let $this = self;
+            $inner.set_variable_atom_count(allow)
+        }
+
+        fn is_strict(&self) -> bool {
+            let $this = self;
+            $inner.is_strict()
+        }
+
+        fn len(&self) -> Option<usize> {
+            let $this = self;
+            $inner.len()
+        }
+
+        fn num_frames(&mut self) -> Result<usize> {
+            let $this = self;
+            $inner.num_frames()
+        }
+
+        fn seek_by_time(&mut self, time: f32) -> Result<()> {
+            let $this = self;
+            $inner.seek_by_time(time)
+        }
+
+        fn seek_time(&mut self, time: f32) -> Result<()> {
+            let $this = self;
+            $inner.seek_time(time)
+        }
+
+        fn read_nth(&mut self, n: usize) -> Result<Frame> {
+            let $this = self;
+            $inner.read_nth(n)
+        }
+
+        fn read_frame_at(&mut self, index: usize, frame: &mut Frame) -> Result<()> {
+            let $this = self;
+            $inner.read_frame_at(index, frame)
+        }
+
+        fn estimate_write_size(&self, num_frames: usize, frame: &Frame) -> Result<u64> {
+            let $this = self;
+            $inner.estimate_write_size(num_frames, frame)
+        }
+
+        fn at_eof(&mut self) -> Result<bool> {
+            let $this = self;
+            $inner.at_eof()
+        }
+
+        fn write_settings(&mut self) -> Result<WriteSettings> {
+            let $this = self;
+            $inner.write_settings()
+        }
+
+        fn apply_write_settings(&mut self, settings: WriteSettings) {
+            let $this = self;
+            $inner.apply_write_settings(settings)
+        }
+
+        #[cfg(feature = "sha2")]
+        fn digest(&mut self) -> Result<[u8; 32]> {
+            let $this = self;
+            $inner.digest()
+        }
+    };
+}
+
 pub mod c_abi;
+#[cfg(feature = "cross-check")]
+mod cross_check;
 mod errors;
 mod frame;
+mod index;
 mod iterator;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod pool;
+mod retry;
+#[cfg(feature = "simd")]
+pub mod simd;
+#[cfg(any(test, feature = "test-utils"))]
+mod test_utils;
+mod tools;
+mod transform;
+mod unwrap;
+mod writer;
+#[cfg(feature = "cross-check")]
+pub use cross_check::*;
 pub use errors::*;
-pub use frame::Frame;
+pub use frame::{CenterMode, Frame, ShapeDescriptors};
+pub use index::*;
 pub use iterator::*;
+pub use pool::*;
+pub use retry::*;
+#[cfg(any(test, feature = "test-utils"))]
+pub use test_utils::*;
+pub use tools::*;
+pub use transform::*;
+pub use unwrap::*;
+pub use writer::*;
 
 use c_abi::xdr_seek;
 use c_abi::xdrfile;
@@ -75,15 +264,392 @@ use c_abi::xdrfile::XDRFILE;
 use c_abi::xdrfile_trr;
 use c_abi::xdrfile_xtc;
 
+use bitflags::bitflags;
 use lazy_init::Lazy;
 use std::cell::Cell;
 use std::convert::{TryFrom, TryInto};
 use std::ffi::CString;
 use std::io;
 use std::io::SeekFrom;
-use std::os::raw::{c_float, c_int};
+use std::os::raw::{c_double, c_float, c_int};
 use std::path::{Path, PathBuf};
 
+/// Magic number at the start of every XTC frame
+///
+/// Each frame header of an XTC file begins with this big-endian integer
+/// (`MAGIC` in libxdrfile's `xdrfile_xtc.c`), so it can be used to recognise
+/// XTC files and find frame boundaries.
+pub const XTC_MAGIC: i32 = 1995;
+
+/// Revision of the XTC format, identified by the magic number of each frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XtcVersion {
+    /// The original format, with magic number `XTC_MAGIC` (1995). This is
+    /// the only revision libxdrfile reads and writes.
+    V1995,
+    /// The revision GROMACS 2023 introduced for frames with too many atoms
+    /// for the original, with magic number 2023. It stores the size of the
+    /// compressed coordinates in 64 bits, and is not supported.
+    V2023,
+}
+
+impl XtcVersion {
+    /// The revision with the given frame magic number, if it is an XTC one
+    pub fn from_magic(magic: i32) -> Option<XtcVersion> {
+        match magic {
+            XTC_MAGIC => Some(XtcVersion::V1995),
+            2023 => Some(XtcVersion::V2023),
+            _ => None,
+        }
+    }
+
+    /// Magic number at the start of frames of this revision
+    pub fn magic(self) -> i32 {
+        match self {
+            XtcVersion::V1995 => XTC_MAGIC,
+            XtcVersion::V2023 => 2023,
+        }
+    }
+
+    /// True if frames of this revision can be read and written
+    pub fn is_supported(self) -> bool {
+        self == XtcVersion::V1995
+    }
+}
+
+/// Magic number at the start of every TRR frame
+///
+/// Each frame header of a TRR file begins with this big-endian integer
+/// (`GROMACS_MAGIC` in libxdrfile's `xdrfile_trr.c`), followed by the version
+/// string "GMX_trn_file".
+pub const TRR_MAGIC: i32 = 1993;
+
+/// Precision new XTC trajectories compress coordinates with, unless changed
+/// with `XTCTrajectory::set_precision`
+pub const XTC_DEFAULT_PRECISION: f32 = 1000.0;
+
+/// Largest error in a coordinate after writing it to and reading it from an
+/// XTC file with the default precision, in nm
+///
+/// Coordinates are rounded to the nearest multiple of `1 / precision`, so
+/// they change by at most `0.5 / precision`. See `approx_eq_coords` for
+/// comparing coordinates with this tolerance.
+pub const XTC_COORD_TOLERANCE: f32 = 0.5 / XTC_DEFAULT_PRECISION;
+
+/// True if coordinates `a` and `b` agree as well as an XTC round trip with
+/// `precision` guarantees
+///
+/// Both must have the same number of atoms, and each coordinate may differ by
+/// at most `0.5 / precision` (`XTC_COORD_TOLERANCE` for the default
+/// precision), plus the rounding error of an `f32` of its magnitude.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// # let tempfile = tempfile::NamedTempFile::new().unwrap();
+/// let frame = Frame {
+///     coords: (0..20).map(|i| [i as f32 * 0.12345, 1.0 / (i + 1) as f32, -2.5]).collect(),
+///     ..Default::default()
+/// };
+/// let mut trj = XTCTrajectory::open_write(tempfile.path())?;
+/// trj.write(&frame)?;
+/// trj.close()?;
+///
+/// let mut trj = XTCTrajectory::open_read(tempfile.path())?;
+/// let mut read = Frame::with_len(trj.get_num_atoms()?);
+/// trj.read(&mut read)?;
+/// assert_ne!(read.coords, frame.coords);
+/// assert!(approx_eq_coords(&read.coords, &frame.coords, XTC_DEFAULT_PRECISION));
+/// # Ok(())
+/// # }
+/// ```
+pub fn approx_eq_coords(a: &[[f32; 3]], b: &[[f32; 3]], precision: f32) -> bool {
+    let tolerance = 0.5 / precision;
+    a.len() == b.len()
+        && a.iter().flatten().zip(b.iter().flatten()).all(|(x, y)| {
+            let rounding = x.abs().max(y.abs()) * f32::EPSILON;
+            (x - y).abs() <= tolerance + rounding
+        })
+}
+
+/// Lowest XTC precision that keeps the coordinates of `frame` within
+/// `max_error` of their values after a round trip
+///
+/// The coordinates of a frame written with precision `p` are rounded to the
+/// nearest multiple of `1 / p`, an error of at most `0.5 / p`, and lose some
+/// more to `f32` arithmetic in proportion to their magnitude. Lower
+/// precisions give smaller files, so this finds the lowest one that still
+/// meets `max_error` for the largest coordinate of `frame`, to pass to
+/// `XTCTrajectory::set_precision`. Other frames of a trajectory are covered
+/// too if their coordinates are no larger.
+///
+/// Returns `Error::InvalidArgument` unless `max_error` is positive, and also
+/// if it is too small to reach in `f32` for coordinates this large.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// let frame = Frame {
+///     coords: vec![[1.0, 2.0, 3.0]; 20],
+///     ..Default::default()
+/// };
+/// let precision = recommend_precision(&frame, 0.005)?;
+/// assert!((100.0..100.1).contains(&precision));
+/// # Ok(())
+/// # }
+/// ```
+pub fn recommend_precision(frame: &Frame, max_error: f32) -> Result<f32> {
+    let largest = frame
+        .coords
+        .iter()
+        .flatten()
+        .fold(0.0_f64, |m, x| m.max(f64::from(x.abs())));
+    // Rounding x * p to an integer in f32 and dividing it by p again, with
+    // some margin; see `approx_eq_coords`
+    let rounding = 2.0 * largest * f64::from(f32::EPSILON);
+    let budget = f64::from(max_error) - rounding;
+    let precision = (0.5 / budget) as f32;
+    if !(budget > 0.0 && precision.is_finite())
+        || largest > f64::from(XTCTrajectory::max_coordinate(precision))
+    {
+        return Err(Error::InvalidArgument {
+            name: "max_error",
+            value: max_error.to_string(),
+        });
+    }
+    Ok(precision)
+}
+
+/// Number of bytes available to unprivileged users on the file system
+/// holding `path`
+///
+/// `path` may be a file that does not exist yet, in which case the space in
+/// its directory is returned. Only supported on Unix; elsewhere, this returns
+/// `Error::Unsupported`.
+pub fn available_space(path: impl AsRef<Path>) -> Result<u64> {
+    let path = path.as_ref();
+    let existing = match path.parent() {
+        Some(parent) if !path.exists() => {
+            if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            }
+        }
+        _ => path,
+    };
+    statvfs_available(existing)
+}
+
+#[cfg(unix)]
+fn statvfs_available(path: &Path) -> Result<u64> {
+    let path_p = path_to_cstring(path)?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    errno::set_errno(errno::Errno(0));
+    if unsafe { libc::statvfs(path_p.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(Error::OsError {
+            code: ErrorCode::ExdrNr,
+            task: ErrorTask::QuerySpace,
+            errno: errno::errno().0,
+        });
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)] // The field types differ between platforms
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn statvfs_available(_path: &Path) -> Result<u64> {
+    Err(Error::Unsupported {
+        operation: "Querying free disk space",
+    })
+}
+
+/// Check that the file system holding `path` has room for `required` more
+/// bytes, returning the space available
+///
+/// Use `Trajectory::estimate_write_size` for `required` to check before a
+/// long write. Returns `Error::InsufficientSpace` if there is not enough
+/// room; see `available_space` for the other errors.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path = dir.path().join("out.trr");
+/// let trj = TRRTrajectory::open_write(&path)?;
+/// let required = trj.estimate_write_size(1000, &Frame::with_len(304))?;
+/// check_disk_space(&path, required)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn check_disk_space(path: impl AsRef<Path>, required: u64) -> Result<u64> {
+    let path = path.as_ref();
+    let available = available_space(path)?;
+    if available < required {
+        return Err(Error::InsufficientSpace {
+            path: path.to_owned(),
+            required,
+            available,
+        });
+    }
+    Ok(available)
+}
+
+/// Byte order of the numbers in a trajectory file
+///
+/// XDR, and so both XTC and TRR, stores numbers big-endian, but some legacy
+/// tools write little-endian files. Only the header parsing done in Rust,
+/// such as `Format::detect_with_byte_order`, can be told to expect those:
+/// `XTCTrajectory` and `TRRTrajectory` read and write through libxdrfile,
+/// which always assumes standard big-endian XDR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ByteOrder {
+    /// Standard XDR
+    #[default]
+    BigEndian,
+    LittleEndian,
+}
+
+impl ByteOrder {
+    /// Decode a 32-bit integer stored in this byte order
+    pub fn read_i32(self, bytes: [u8; 4]) -> i32 {
+        match self {
+            ByteOrder::BigEndian => i32::from_be_bytes(bytes),
+            ByteOrder::LittleEndian => i32::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// Trajectory file format
+///
+/// Formats display as, and parse from, their usual file extensions.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// let format: Format = "xtc".parse()?;
+/// assert_eq!(format, Format::Xtc);
+/// assert_eq!(Format::Trr.to_string(), "trr");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Compressed coordinates, see `XTCTrajectory`
+    Xtc,
+    /// Full precision coordinates, velocities and forces, see `TRRTrajectory`
+    Trr,
+}
+
+impl Format {
+    /// Usual file extension of the format, without the leading dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Xtc => "xtc",
+            Format::Trr => "trr",
+        }
+    }
+
+    /// Format of a file, from its extension
+    ///
+    /// The extension is matched case-insensitively. Use `detect` for files
+    /// whose extension is missing or misleading.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Format> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str());
+        extension
+            .and_then(|e| e.parse().ok())
+            .ok_or_else(|| Error::UnknownFormat {
+                path: path.to_owned(),
+            })
+    }
+
+    /// Format whose frames start with the magic number `magic`
+    ///
+    /// All revisions of the XTC format count as XTC, see `XtcVersion`.
+    pub fn from_magic(magic: i32) -> Option<Format> {
+        match magic {
+            TRR_MAGIC => Some(Format::Trr),
+            magic => XtcVersion::from_magic(magic).map(|_| Format::Xtc),
+        }
+    }
+
+    /// Format of a file, from the magic number at its start
+    ///
+    /// Returns `Error::UnknownFormat` if the file is too short or does not
+    /// start with a known magic number. The magic number is read as standard
+    /// big-endian XDR, see `detect_with_byte_order` for other files.
+    pub fn detect(path: impl AsRef<Path>) -> Result<Format> {
+        Format::detect_with_byte_order(path, ByteOrder::BigEndian)
+    }
+
+    /// Format of a file, from the magic number at its start read in the given
+    /// byte order
+    ///
+    /// This recognizes files written little-endian in violation of XDR, but
+    /// such files still cannot be opened, see `ByteOrder`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let format = Format::detect_with_byte_order("tests/1l2y.xtc", ByteOrder::BigEndian)?;
+    /// assert_eq!(format, Format::Xtc);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn detect_with_byte_order(path: impl AsRef<Path>, byte_order: ByteOrder) -> Result<Format> {
+        use std::io::Read;
+
+        let path = path.as_ref();
+        let mut file =
+            std::fs::File::open(path).map_err(|_| Error::from((path, FileMode::Read)))?;
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)
+            .ok()
+            .and_then(|()| Format::from_magic(byte_order.read_i32(magic)))
+            .ok_or_else(|| Error::UnknownFormat {
+                path: path.to_owned(),
+            })
+    }
+
+    /// Open a trajectory of this format in the given mode
+    pub fn open(self, path: impl AsRef<Path>, filemode: FileMode) -> Result<Box<dyn Trajectory>> {
+        Ok(match self {
+            Format::Xtc => Box::new(XTCTrajectory::open(path, filemode)?),
+            Format::Trr => Box::new(TRRTrajectory::open(path, filemode)?),
+        })
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    /// Parse a file extension, such as "xtc" or ".TRR"
+    fn from_str(s: &str) -> Result<Self> {
+        let extension = s.strip_prefix('.').unwrap_or(s);
+        match extension.to_ascii_lowercase().as_str() {
+            "xtc" => Ok(Format::Xtc),
+            "trr" => Ok(Format::Trr),
+            _ => Err(Error::InvalidArgument {
+                name: "format",
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
 /// File Mode for accessing trajectories.
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileMode {
@@ -105,6 +671,94 @@ impl FileMode {
     }
 }
 
+impl std::str::FromStr for FileMode {
+    type Err = Error;
+
+    /// Parse an fopen-style mode string: "r", "w" or "a"
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "r" => Ok(FileMode::Read),
+            "w" => Ok(FileMode::Write),
+            "a" => Ok(FileMode::Append),
+            _ => Err(Error::InvalidArgument {
+                name: "mode",
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for FileMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = self
+            .to_cstr()
+            .to_str()
+            .expect("File mode is not valid UTF-8");
+        write!(f, "{}", s)
+    }
+}
+
+/// Options for opening a trajectory, mirroring `std::fs::OpenOptions`
+///
+/// The options resolve to one of the modes supported by libxdrfile. Writing
+/// and appending always create the file if it does not exist, and writing
+/// always truncates it. Opening for reading and writing at the same time is
+/// not supported.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// let mode = OpenOptions::new().append(true).mode()?;
+/// assert_eq!(mode, FileMode::Append);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+impl OpenOptions {
+    /// Create a set of options with every option disabled
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Open the trajectory for reading
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Open the trajectory for writing, replacing any existing content
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Open the trajectory for writing at the end of any existing content
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Resolve the options to a `FileMode`
+    pub fn mode(&self) -> Result<FileMode> {
+        match (self.read, self.write, self.append) {
+            (true, false, false) => Ok(FileMode::Read),
+            (false, true, false) => Ok(FileMode::Write),
+            (false, _, true) => Ok(FileMode::Append),
+            _ => Err(Error::InvalidArgument {
+                name: "options",
+                value: format!("{:?}", self),
+            }),
+        }
+    }
+}
+
 fn path_to_cstring(path: impl AsRef<Path>) -> Result<CString> {
     if let Some(s) = path.as_ref().to_str() {
         CString::new(s).map_err(|e| Error::InvalidOsStr(Some(e)))
@@ -145,14 +799,133 @@ fn check_code(code: impl Into<ErrorCode>, task: ErrorTask) -> Option<Error> {
     }
 }
 
+/// Number of attempts at reading a frame before giving up on signals
+/// interrupting it
+const MAX_INTERRUPTED_ATTEMPTS: usize = 16;
+
+/// Run `read` on `state`, running it again if a signal interrupts it
+///
+/// When a read fails with `EINTR` (see `Error::is_interrupted`), part of the
+/// frame may already have been consumed, so `rewind` is called to return to
+/// its start before the next attempt. After `MAX_INTERRUPTED_ATTEMPTS`, the
+/// error is returned.
+fn retry_interrupted<S, T>(
+    state: &mut S,
+    read: impl FnMut(&mut S) -> Result<T>,
+    rewind: impl FnMut(&mut S) -> Result<()>,
+) -> Result<T> {
+    retry_read(
+        state,
+        MAX_INTERRUPTED_ATTEMPTS,
+        Error::is_interrupted,
+        read,
+        rewind,
+    )
+}
+
+/// Run `read` on `state`, running it again while it fails with an error for
+/// which `retriable` is true, up to `max_attempts` attempts in total
+///
+/// `rewind` is called before every further attempt to return to the start of
+/// the frame. Any other error, or the last one, is returned.
+fn retry_read<S, T>(
+    state: &mut S,
+    max_attempts: usize,
+    retriable: impl Fn(&Error) -> bool,
+    mut read: impl FnMut(&mut S) -> Result<T>,
+    mut rewind: impl FnMut(&mut S) -> Result<()>,
+) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match read(state) {
+            Err(e) if retriable(&e) && attempt < max_attempts => {
+                rewind(state)?;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run a C call that fails in the C standard library, attaching the OS error
+///
+/// `errno` is cleared before `call` runs, so the OS error is only attached
+/// if it was set by the failing call itself.
+fn check_os_call(task: ErrorTask, call: impl FnOnce() -> c_int) -> Result<()> {
+    errno::set_errno(errno::Errno(0));
+    let code = call();
+    let errno = errno::errno().0;
+    match check_code(code, task) {
+        None => Ok(()),
+        Some(_) if errno != 0 => Err(Error::OsError {
+            code: code.into(),
+            task,
+            errno,
+        }),
+        Some(err) => Err(err),
+    }
+}
+
+/// True if the OS error `errno` means that there is no room left to write
+fn is_disk_full(errno: i32) -> bool {
+    use io::ErrorKind;
+    matches!(
+        io::Error::from_raw_os_error(errno).kind(),
+        ErrorKind::StorageFull | ErrorKind::QuotaExceeded
+    )
+}
+
+/// Run a C call that reads `n` items, attaching the OS error if it reads fewer
+///
+/// Reading fewer items at the end of the file does not set `errno`, so that
+/// case produces a plain `Error::CApiError` with `code`.
+fn check_os_read(
+    n: c_int,
+    code: ErrorCode,
+    task: ErrorTask,
+    call: impl FnOnce() -> c_int,
+) -> Result<()> {
+    errno::set_errno(errno::Errno(0));
+    if call() == n {
+        return Ok(());
+    }
+    match errno::errno().0 {
+        0 => Err((code, task).into()),
+        errno => Err(Error::OsError { code, task, errno }),
+    }
+}
+
+/// Convert the number of atoms reported by the C API, rejecting zero
+///
+/// The C API happily reads a header that claims zero atoms, after which every
+/// read would silently produce an empty frame.
+fn checked_num_atoms(num_atoms: c_int, path: &Path) -> Result<usize> {
+    match to!(num_atoms, ErrorTask::ReadNumAtoms)? {
+        0 => Err(Error::ZeroAtoms {
+            path: path.to_owned(),
+        }),
+        n => Ok(n),
+    }
+}
+
 /// A safe wrapper around the c implementation of an XDRFile
 struct XDRFile {
     xdrfile: *mut XDRFILE,
     #[allow(dead_code)]
     filemode: FileMode,
     path: PathBuf,
+    /// True if frames were written since the last flush
+    dirty: bool,
 }
 
+// SAFETY: The XDRFILE is owned exclusively by this struct and freed only in
+// drop, so moving it to another thread cannot leave an alias behind. The C
+// library keeps all state of an open file in the XDRFILE itself, apart from
+// scratch space for padding bytes that is written but never read, so handles
+// on different threads do not interfere. XDRFile is deliberately not Sync, as
+// every operation moves the position of the underlying FILE.
+unsafe impl Send for XDRFile {}
+
 impl XDRFile {
     pub fn open(path: impl AsRef<Path>, filemode: FileMode) -> Result<XDRFile> {
         let path = path.as_ref();
@@ -172,6 +945,7 @@ impl XDRFile {
                     xdrfile,
                     filemode,
                     path,
+                    dirty: false,
                 })
             } else {
                 // Something went wrong. But the C api does not tell us what
@@ -181,449 +955,4773 @@ impl XDRFile {
     }
 
     /// Get the current position in the file
+    ///
+    /// # Panics
+    /// Panics if the position cannot be determined. See `try_tell`.
     pub fn tell(&self) -> u64 {
-        unsafe {
-            xdr_seek::xdr_tell(self.xdrfile)
-                .try_into()
-                .expect("i64 could not be converted to u64")
-        }
+        self.try_tell().expect("Could not get position in file")
     }
-}
 
-impl io::Seek for XDRFile {
-    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
-        let (whence, pos) = match pos {
-            SeekFrom::Start(u) => (
-                0,
-                i64::try_from(u).expect("Seek position did not fit in i64"),
-            ),
-            SeekFrom::Current(i) => (1, i),
-            SeekFrom::End(i) => (2, i),
-        };
-        unsafe {
-            let code = xdr_seek::xdr_seek(self.xdrfile, pos, whence);
-            match check_code(code, ErrorTask::Seek) {
-                None => Ok(self.tell()),
-                Some(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
-            }
+    /// Get the current position in the file, or an error if the C library
+    /// reports an invalid position
+    pub fn try_tell(&self) -> Result<u64> {
+        errno::set_errno(errno::Errno(0));
+        let pos = unsafe { xdr_seek::xdr_tell(self.xdrfile) };
+        let errno = errno::errno().0;
+        match u64::try_from(pos) {
+            Ok(pos) => Ok(pos),
+            Err(_) if errno != 0 => Err(Error::OsError {
+                code: ErrorCode::ExdrNr,
+                task: ErrorTask::Tell,
+                errno,
+            }),
+            Err(_) => Err((ErrorCode::ExdrNr, ErrorTask::Tell).into()),
         }
     }
-}
 
-impl Drop for XDRFile {
-    /// Close the underlying xdr file on drop
-    fn drop(&mut self) {
-        unsafe {
-            xdrfile::xdrfile_close(self.xdrfile);
+    /// True if the current position is at or past the end of the file
+    ///
+    /// The length is taken from the file system, so a file that ends exactly
+    /// at a frame boundary is at the end after its last frame is read.
+    /// Pending writes are flushed first so that they count towards the length.
+    fn at_eof(&mut self) -> Result<bool> {
+        if self.dirty {
+            self.flush()?;
         }
+        let pos = self.try_tell()?;
+        let len = std::fs::metadata(&self.path)
+            .map_err(|e| Error::from((e, ErrorTask::Tell)))?
+            .len();
+        Ok(pos >= len)
     }
-}
-
-/// The trajectory trait defines shared methods for xtc and trr trajectories
-pub trait Trajectory {
-    /// Read the next step of the trajectory into the frame object
-    fn read(&mut self, frame: &mut Frame) -> Result<()>;
-
-    /// Write the frame to the trajectory file
-    fn write(&mut self, frame: &Frame) -> Result<()>;
 
-    /// Flush the trajectory file
-    fn flush(&mut self) -> Result<()>;
+    /// Read `buf.len()` integers from the current position in the file
+    fn read_ints(&mut self, buf: &mut [c_int], task: ErrorTask) -> Result<()> {
+        let n: c_int = to!(buf.len(), task)?;
+        let xdrfile = self.xdrfile;
+        check_os_read(n, ErrorCode::ExdrInt, task, || unsafe {
+            xdrfile::xdrfile_read_int(buf.as_mut_ptr(), n, xdrfile)
+        })
+    }
 
-    /// Get the number of atoms from the give trajectory
-    fn get_num_atoms(&mut self) -> Result<usize>;
+    /// Read `buf.len()` floats from the current position in the file
+    fn read_floats(&mut self, buf: &mut [c_float], task: ErrorTask) -> Result<()> {
+        let n: c_int = to!(buf.len(), task)?;
+        let xdrfile = self.xdrfile;
+        check_os_read(n, ErrorCode::ExdrFloat, task, || unsafe {
+            xdrfile::xdrfile_read_float(buf.as_mut_ptr(), n, xdrfile)
+        })
+    }
 
-}
+    /// Read `buf.len()` doubles from the current position in the file
+    fn read_doubles(&mut self, buf: &mut [c_double], task: ErrorTask) -> Result<()> {
+        let n: c_int = to!(buf.len(), task)?;
+        let xdrfile = self.xdrfile;
+        check_os_read(n, ErrorCode::ExdrDouble, task, || unsafe {
+            xdrfile::xdrfile_read_double(buf.as_mut_ptr(), n, xdrfile)
+        })
+    }
 
-/// Handle to Read/Write XTC Trajectories
-pub struct XTCTrajectory {
-    handle: XDRFile,
-    precision: Cell<c_float>, // internal mutability required for read method
-    num_atoms: Lazy<Result<usize>>,
-}
-
-impl XTCTrajectory {
-    pub fn open(path: impl AsRef<Path>, filemode: FileMode) -> Result<XTCTrajectory> {
-        let xdr = XDRFile::open(path, filemode)?;
-        Ok(XTCTrajectory {
-            handle: xdr,
-            precision: Cell::new(1000.0),
-            num_atoms: Lazy::new(),
-        })
-    }
-
-    /// Open a file in read mode
-    pub fn open_read(path: impl AsRef<Path>) -> Result<Self> {
-        Self::open(path, FileMode::Read)
+    /// Read `out.len()` 3D vectors, converting from double precision if required
+    fn read_rvecs(&mut self, out: &mut [[f32; 3]], double: bool, task: ErrorTask) -> Result<()> {
+        if double {
+            let mut buf = vec![0.0; out.len() * 3];
+            self.read_doubles(&mut buf, task)?;
+            for (rvec, xyz) in out.iter_mut().zip(buf.chunks_exact(3)) {
+                *rvec = [xyz[0] as f32, xyz[1] as f32, xyz[2] as f32];
+            }
+            Ok(())
+        } else {
+            // SAFETY: [[f32; 3]] has the same layout as [f32] of three times the length
+            let flat = unsafe {
+                std::slice::from_raw_parts_mut(out.as_mut_ptr() as *mut c_float, out.len() * 3)
+            };
+            self.read_floats(flat, task)
+        }
     }
 
-    /// Open a file in append mode
-    pub fn open_append(path: impl AsRef<Path>) -> Result<Self> {
-        Self::open(path, FileMode::Append)
+    /// Flush buffered writes to disk
+    ///
+    /// Files open for reading have nothing to flush, so this does nothing for
+    /// them. Otherwise, a failing flush returns `Error::DiskFull` if the OS
+    /// reports that the disk or quota is full (`ENOSPC` or `EDQUOT`), an
+    /// `Error::OsError` with the OS error for other failures, and an
+    /// `Error::CApiError` if the OS did not say why.
+    fn flush(&mut self) -> Result<()> {
+        if self.filemode == FileMode::Read {
+            return Ok(());
+        }
+        let xdrfile = self.xdrfile;
+        match check_os_call(ErrorTask::Flush, || unsafe { xdr_seek::xdr_flush(xdrfile) }) {
+            Err(Error::OsError { errno, .. }) if is_disk_full(errno) => {
+                return Err(Error::DiskFull {
+                    path: self.path.clone(),
+                    errno,
+                });
+            }
+            result => result?,
+        }
+        self.dirty = false;
+        Ok(())
     }
 
-    /// Open a file in write mode
-    pub fn open_write(path: impl AsRef<Path>) -> Result<Self> {
-        Self::open(path, FileMode::Write)
+    /// Flush buffered writes and close the file, reporting any error
+    ///
+    /// The file is closed even if this fails, and must not be used afterwards.
+    fn close(&mut self) -> Result<()> {
+        if self.xdrfile.is_null() {
+            return Ok(());
+        }
+        // libxdrfile flushes while closing but ignores the result, so flush first
+        let flushed = if self.dirty { self.flush() } else { Ok(()) };
+        errno::set_errno(errno::Errno(0));
+        // fclose returns EOF rather than an xdrfile error code
+        let ret = unsafe { xdrfile::xdrfile_close(self.xdrfile) };
+        let errno = errno::errno().0;
+        self.xdrfile = std::ptr::null_mut();
+        let (code, task) = (ErrorCode::ExdrClose, ErrorTask::Close);
+        let closed = match (ret, errno) {
+            (0, _) => Ok(()),
+            (_, 0) => Err((code, task).into()),
+            (_, errno) => Err(Error::OsError { code, task, errno }),
+        };
+        flushed.and(closed)
     }
-}
-
-impl Trajectory for XTCTrajectory {
-    fn read(&mut self, frame: &mut Frame) -> Result<()> {
-        let mut step: c_int = 0;
 
-        let num_atoms = self
-            .get_num_atoms()
-            .map_err(|e| Error::CouldNotCheckNAtoms(Box::new(e)))?;
-        if num_atoms != frame.coords.len() {
-            return Err((&*frame, num_atoms).into());
-        }
+    /// SHA-256 digest of the raw bytes of the file, after flushing any pending writes
+    ///
+    /// The file is reopened by path and read with std, since the C library
+    /// can only read files opened in read mode.
+    #[cfg(feature = "sha2")]
+    fn digest(&mut self) -> Result<[u8; 32]> {
+        use sha2::{Digest, Sha256};
 
-        unsafe {
-            let code = xdrfile_xtc::read_xtc(
-                self.handle.xdrfile,
-                to!(num_atoms, ErrorTask::Read)?,
-                &mut step,
-                &mut frame.time,
-                &mut frame.box_vector,
-                frame.coords.as_mut_ptr(),
-                &mut self.precision.get(),
-            );
-            if let Some(err) = check_code(code, ErrorTask::Read) {
-                return Err(err);
-            }
-            frame.step = to!(step, ErrorTask::Read)?;
-            Ok(())
+        if self.filemode != FileMode::Read {
+            self.flush()?;
         }
+        let mut file = std::fs::File::open(&self.path)
+            .map_err(|_| Error::from((self.path.as_path(), FileMode::Read)))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher).map_err(|e| Error::from((e, ErrorTask::Digest)))?;
+        Ok(hasher.finalize().into())
     }
 
-    fn write(&mut self, frame: &Frame) -> Result<()> {
+    /// Seek relative to the current position, converting errors to the crate error type
+    fn skip(&mut self, bytes: i64, task: ErrorTask) -> Result<()> {
         unsafe {
-            let code = xdrfile_xtc::write_xtc(
-                self.handle.xdrfile,
-                to!(frame.num_atoms(), ErrorTask::Write)?,
-                to!(frame.step, ErrorTask::Write)?,
-                frame.time,
-                &frame.box_vector,
-                frame.coords.as_ptr(),
-                1000.0,
-            );
-            if let Some(err) = check_code(code, ErrorTask::Write) {
-                Err(err)
-            } else {
-                Ok(())
+            let code = xdr_seek::xdr_seek(self.xdrfile, bytes, 1);
+            match check_code(code, task) {
+                None => Ok(()),
+                Some(err) => Err(err),
             }
         }
     }
 
-    fn flush(&mut self) -> Result<()> {
+    /// Seek to an absolute position, converting errors to the crate error type
+    fn seek_to(&mut self, pos: u64, task: ErrorTask) -> Result<()> {
+        let pos: i64 = to!(pos, task)?;
         unsafe {
-            let code = xdr_seek::xdr_flush(self.handle.xdrfile);
-            if let Some(err) = check_code(code, ErrorTask::Flush) {
-                Err(err)
-            } else {
-                Ok(())
+            let code = xdr_seek::xdr_seek(self.xdrfile, pos, 0);
+            match check_code(code, task) {
+                None => Ok(()),
+                Some(err) => Err(err),
             }
         }
     }
 
-    fn get_num_atoms(&mut self) -> Result<usize> {
-        self.num_atoms
-            .get_or_create(|| {
-                let mut num_atoms: c_int = 0;
-
-                unsafe {
-                    let path = path_to_cstring(&self.handle.path)?;
-                    let path_p = path.into_raw();
-                    let code = xdrfile_xtc::read_xtc_natoms(path_p, &mut num_atoms);
-                    // Reconstitute the CString so it is deallocated correctly
-                    let _ = CString::from_raw(path_p);
+    /// Build an index by calling `skip_frame` until it reports the end of the
+    /// file, restoring the position in the file afterwards
+    ///
+    /// `skip_frame` must either return None at the end of the file, or return
+    /// the step and time of the next frame and leave the file positioned after it.
+    fn build_index(
+        &mut self,
+        mut skip_frame: impl FnMut(&mut Self) -> Result<Option<(usize, f32)>>,
+    ) -> Result<TrajectoryIndex> {
+        let task = ErrorTask::BuildIndex;
+        let start = self.try_tell()?;
+        self.seek_to(0, task)?;
 
-                    if let Some(err) = check_code(code, ErrorTask::ReadNumAtoms) {
-                        Err(err)
-                    } else {
-                        to!(num_atoms, ErrorTask::ReadNumAtoms)
-                    }
+        let mut scan = || {
+            let mut entries = Vec::new();
+            loop {
+                let offset = self.try_tell()?;
+                match skip_frame(self)? {
+                    Some((step, time)) => entries.push(IndexEntry { offset, step, time }),
+                    None => return Ok(TrajectoryIndex::new(entries)),
                 }
-            })
-            .clone()
+            }
+        };
+        let result = scan();
+        self.seek_to(start, task)?;
+        result
     }
 }
 
-impl XTCTrajectory {
-    /// Get the current position in the file
-    pub fn tell(&self) -> u64 {
-        self.handle.tell()
+impl io::Seek for XDRFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (whence, pos) = match pos {
+            SeekFrom::Start(u) => (
+                0,
+                i64::try_from(u).expect("Seek position did not fit in i64"),
+            ),
+            SeekFrom::Current(i) => (1, i),
+            SeekFrom::End(i) => (2, i),
+        };
+        unsafe {
+            let code = xdr_seek::xdr_seek(self.xdrfile, pos, whence);
+            match check_code(code, ErrorTask::Seek) {
+                None => self.try_tell().map_err(io::Error::other),
+                Some(err) => Err(io::Error::other(err)),
+            }
+        }
     }
 }
 
-impl io::Seek for XTCTrajectory {
-    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
-        self.handle.seek(pos)
+impl Drop for XDRFile {
+    /// Close the underlying xdr file on drop
+    ///
+    /// Closing flushes any buffered writes. Errors cannot be returned from
+    /// here, so call `close` on the trajectory to see them. Debug builds print
+    /// a warning if writes may have been lost.
+    fn drop(&mut self) {
+        let closed = self.close();
+        #[cfg(debug_assertions)]
+        if let Err(err) = closed {
+            if self.dirty {
+                eprintln!(
+                    "xdrfile: Frames written to {:?} may have been lost: {}",
+                    self.path, err
+                );
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = closed;
     }
 }
 
-/// Handle to Read/Write TRR Trajectories
-pub struct TRRTrajectory {
-    handle: XDRFile,
-    num_atoms: Lazy<Result<usize>>,
-}
+/// The trajectory trait defines shared methods for xtc and trr trajectories
+pub trait Trajectory {
+    /// Read the next step of the trajectory into the frame object
+    ///
+    /// XTC and TRR trajectories restart a read that a signal interrupts
+    /// (`EINTR`), as can happen under job schedulers, from the start of the
+    /// frame, giving up after 16 attempts. Writes are not restarted, as part
+    /// of the frame may already be in the file. A frame with a different
+    /// number of atoms than the first fails with `Error::VariableAtomCount`,
+    /// see `set_variable_atom_count`.
+    fn read(&mut self, frame: &mut Frame) -> Result<()>;
 
-impl TRRTrajectory {
-    pub fn open(path: impl AsRef<Path>, filemode: FileMode) -> Result<TRRTrajectory> {
-        let xdr = XDRFile::open(path, filemode)?;
-        Ok(TRRTrajectory {
-            handle: xdr,
-            num_atoms: Lazy::new(),
-        })
-    }
+    /// Write the frame to the trajectory file
+    fn write(&mut self, frame: &Frame) -> Result<()>;
 
-    /// Open a file in read mode
-    pub fn open_read(path: impl AsRef<Path>) -> Result<Self> {
-        Self::open(path, FileMode::Read)
-    }
+    /// Flush the trajectory file
+    ///
+    /// This does nothing for files open for reading. A flush that fails
+    /// because the disk is full returns `Error::DiskFull`.
+    fn flush(&mut self) -> Result<()>;
 
-    /// Open a file in append mode
-    pub fn open_append(path: impl AsRef<Path>) -> Result<Self> {
-        Self::open(path, FileMode::Append)
-    }
+    /// Get the number of atoms from the give trajectory
+    fn get_num_atoms(&mut self) -> Result<usize>;
 
-    /// Open a file in write mode
-    pub fn open_write(path: impl AsRef<Path>) -> Result<Self> {
-        Self::open(path, FileMode::Write)
+    /// Fold over all remaining frames of the trajectory
+    ///
+    /// `map` is applied to each frame in turn, and its results are combined
+    /// into the accumulator with `reduce`, starting from `init`. This covers
+    /// averages, histograms and other aggregates in a single pass without
+    /// keeping the frames around.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let num_frames = trj.map_reduce(|_| 1, |count, one| count + one, 0)?;
+    /// assert_eq!(num_frames, 38);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn map_reduce<T, A>(
+        &mut self,
+        map: impl Fn(&Frame) -> T,
+        reduce: impl Fn(A, T) -> A,
+        init: A,
+    ) -> Result<A>
+    where
+        Self: Sized,
+    {
+        let mut frame = Frame::with_len(self.get_num_atoms()?);
+        let mut acc = init;
+        loop {
+            match self.read(&mut frame) {
+                Ok(()) => acc = reduce(acc, map(&frame)),
+                Err(e) if e.is_eof() => return Ok(acc),
+                Err(e) => return Err(e),
+            }
+        }
     }
-}
-
-impl Trajectory for TRRTrajectory {
-    fn read(&mut self, frame: &mut Frame) -> Result<()> {
-        let mut step: c_int = 0;
-        let mut lambda: c_float = 0.0;
 
-        let num_atoms = self
-            .get_num_atoms()
-            .map_err(|e| Error::CouldNotCheckNAtoms(Box::new(e)))?;
-        if num_atoms != frame.coords.len() {
-            return Err((&*frame, num_atoms).into());
+    /// Read the next frame into a newly allocated frame, or None at the end
+    /// of the file
+    ///
+    /// This is simpler to use than `read` when the cost of allocating a frame
+    /// for every step does not matter.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// while let Some(frame) = trj.read_owned()? {
+    ///     assert_eq!(frame.len(), 304);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn read_owned(&mut self) -> Result<Option<Frame>> {
+        let mut frame = Frame::with_len(self.get_num_atoms()?);
+        match self.read(&mut frame) {
+            Ok(()) => Ok(Some(frame)),
+            Err(e) if e.is_eof() => Ok(None),
+            Err(e) => Err(e),
         }
+    }
 
-        unsafe {
-            let code = xdrfile_trr::read_trr(
-                self.handle.xdrfile,
-                to!(num_atoms, ErrorTask::Read)?,
-                &mut step,
-                &mut frame.time,
-                &mut lambda,
-                &mut frame.box_vector,
-                frame.coords.as_mut_ptr(),
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-            );
-            if let Some(err) = check_code(code, ErrorTask::Read) {
-                return Err(err);
+    /// Read the next frame into `frame`, resizing it to the number of atoms
+    /// of that frame first if needed
+    ///
+    /// `read` fails with `Error::WrongSizeFrame` when the frame does not have
+    /// exactly the number of atoms in the file, without reading anything.
+    /// This instead resizes the frame with `Frame::resize` to the number of
+    /// atoms given by the header of the next frame, then reads it, which is
+    /// convenient when the number of atoms is not known beforehand. Growing
+    /// the frame reallocates its coordinates, and its velocities, forces,
+    /// occupancies and B-factors if it has them; frames that already have the
+    /// right size are read without allocating.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut frame = Frame::new();
+    /// trj.read_autoresize(&mut frame)?;
+    /// assert_eq!(frame.len(), 304);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn read_autoresize(&mut self, frame: &mut Frame) -> Result<()> {
+        match self.read(frame) {
+            Err(Error::WrongSizeFrame { expected, .. }) => {
+                frame.resize(expected);
+                self.read(frame)
             }
-            frame.step = to!(step, ErrorTask::Read)?;
-            Ok(())
+            result => result,
         }
     }
 
-    fn write(&mut self, frame: &Frame) -> Result<()> {
-        unsafe {
-            let code = xdrfile_trr::write_trr(
-                self.handle.xdrfile,
-                to!(frame.len(), ErrorTask::Write)?,
-                to!(frame.step, ErrorTask::Write)?,
-                frame.time,
-                0.0,
-                &frame.box_vector,
-                frame.coords[..].as_ptr(),
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
-            );
-            if let Some(err) = check_code(code, ErrorTask::Write) {
-                Err(err)
+    /// Histogram a per-frame scalar over all remaining frames
+    ///
+    /// `range` is divided into `bins` bins of equal width, each including its
+    /// lower edge. Values outside the range are clamped into the first or last
+    /// bin, so the counts sum to the number of frames read unless `value`
+    /// returns NaN, which is not counted. Returns `Error::InvalidArgument` if
+    /// `bins` is zero or `range` is not a finite, non-empty interval.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let counts = trj.histogram(|frame| frame.time, 4, (0.0, 40.0))?;
+    /// assert_eq!(counts.iter().sum::<u64>(), 38);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn histogram(
+        &mut self,
+        value: impl Fn(&Frame) -> f32,
+        bins: usize,
+        range: (f32, f32),
+    ) -> Result<Vec<u64>>
+    where
+        Self: Sized,
+    {
+        let (lower, upper) = range;
+        if bins == 0 {
+            return Err(Error::InvalidArgument {
+                name: "bins",
+                value: bins.to_string(),
+            });
+        }
+        if !lower.is_finite() || !upper.is_finite() || lower >= upper {
+            return Err(Error::InvalidArgument {
+                name: "range",
+                value: format!("{:?}", range),
+            });
+        }
+        let width = (upper as f64 - lower as f64) / bins as f64;
+        let bin_of = |value: f32| {
+            if value.is_nan() {
+                return None;
+            }
+            // Saturating float to integer casts clamp values below the range to zero
+            let bin = ((value as f64 - lower as f64) / width).floor() as usize;
+            Some(bin.min(bins - 1))
+        };
+        self.map_reduce(
+            |frame| bin_of(value(frame)),
+            |mut counts, bin| {
+                if let Some(bin) = bin {
+                    counts[bin] += 1;
+                }
+                counts
+            },
+            vec![0; bins],
+        )
+    }
+
+    /// Write a CSV summary of all remaining frames to `w`, one row per frame
+    ///
+    /// The columns are `step`, `time`, `box_volume` (see
+    /// `Frame::box_determinant`) and the smallest and largest coordinate along
+    /// each axis, `min_x` to `max_z`, which are left empty for frames without
+    /// coordinates. The first row holds the column names. Every frame is read
+    /// in full for its coordinates.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut csv = Vec::new();
+    /// trj.export_summary_csv(&mut csv)?;
+    /// let csv = String::from_utf8(csv).unwrap();
+    /// assert!(csv.starts_with("step,time,box_volume,min_x,"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn export_summary_csv(&mut self, w: &mut impl io::Write) -> Result<()>
+    where
+        Self: Sized,
+    {
+        use io::Write as _;
+
+        let io_error = |e: io::Error| Error::from((e, ErrorTask::Write));
+        let mut w = io::BufWriter::new(w);
+        writeln!(
+            w,
+            "step,time,box_volume,min_x,min_y,min_z,max_x,max_y,max_z"
+        )
+        .map_err(io_error)?;
+        let mut frame = Frame::with_len(self.get_num_atoms()?);
+        loop {
+            match self.read(&mut frame) {
+                Ok(()) => {}
+                Err(e) if e.is_eof() => break,
+                Err(e) => return Err(e),
+            }
+            let mut row = format!("{},{},{}", frame.step, frame.time, frame.box_determinant());
+            if frame.coords.is_empty() {
+                row.push_str(",,,,,,");
             } else {
-                Ok(())
+                let mut min = [f32::INFINITY; 3];
+                let mut max = [f32::NEG_INFINITY; 3];
+                for x in &frame.coords {
+                    for k in 0..3 {
+                        min[k] = min[k].min(x[k]);
+                        max[k] = max[k].max(x[k]);
+                    }
+                }
+                for value in min.iter().chain(&max) {
+                    row.push_str(&format!(",{}", value));
+                }
             }
+            writeln!(w, "{}", row).map_err(io_error)?;
         }
+        w.flush().map_err(io_error)
     }
 
-    fn flush(&mut self) -> Result<()> {
-        unsafe {
-            let code = xdr_seek::xdr_flush(self.handle.xdrfile);
-            if let Some(err) = check_code(code, ErrorTask::Flush) {
-                Err(err)
-            } else {
-                Ok(())
+    /// Read frames until one satisfies `predicate`, and return it
+    ///
+    /// Reading stops at the first matching frame, leaving the trajectory
+    /// positioned after it. Returns `Ok(None)` if no remaining frame matches.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let frame = trj.find_frame(|frame| frame.time >= 10.0)?;
+    /// assert!(frame.is_some_and(|frame| frame.time >= 10.0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn find_frame(&mut self, predicate: impl Fn(&Frame) -> bool) -> Result<Option<Frame>>
+    where
+        Self: Sized,
+    {
+        let mut frame = Frame::with_len(self.get_num_atoms()?);
+        loop {
+            match self.read(&mut frame) {
+                Ok(()) if predicate(&frame) => return Ok(Some(frame)),
+                Ok(()) => {}
+                Err(e) if e.is_eof() => return Ok(None),
+                Err(e) => return Err(e),
             }
         }
     }
 
-    fn get_num_atoms(&mut self) -> Result<usize> {
-        self.num_atoms
-            .get_or_create(|| {
-                let mut num_atoms: c_int = 0;
-                unsafe {
-                    let path = path_to_cstring(&self.handle.path)?;
-                    let path_p = path.into_raw();
-                    let code = xdrfile_trr::read_trr_natoms(path_p, &mut num_atoms);
-                    // Reconstitute the CString so it is deallocated correctly
-                    let _ = CString::from_raw(path_p);
+    /// Turn the trajectory into a sink that frames can be pushed into
+    ///
+    /// This is the write-side counterpart of iterating over a trajectory.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let tempfile = tempfile::NamedTempFile::new().unwrap();
+    /// let mut sink = XTCTrajectory::open_write(tempfile.path())?.sink();
+    /// for frame in XTCTrajectory::open_read("tests/1l2y.xtc")? {
+    ///     sink.push(frame?)?;
+    /// }
+    /// assert_eq!(sink.finish()?, 38);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn sink(self) -> FrameSink<Self>
+    where
+        Self: Sized,
+    {
+        FrameSink::new(self)
+    }
 
-                    if let Some(err) = check_code(code, ErrorTask::ReadNumAtoms) {
-                        Err(err)
-                    } else {
-                        to!(num_atoms, ErrorTask::ReadNumAtoms)
-                    }
-                }
-            })
-            .clone()
+    /// Read only the atoms `start..end` of the next step into the frame object
+    ///
+    /// `frame` must hold exactly `end - start` atoms. The remaining atoms are
+    /// skipped without being decoded, and the trajectory is left positioned at
+    /// the start of the next step, or of the same step if an error is
+    /// returned. Only TRR supports this: XTC compresses all
+    /// coordinates of a frame together, so they cannot be partially decoded,
+    /// and the default implementation returns `Error::Unsupported`.
+    fn read_atom_range(&mut self, frame: &mut Frame, start: usize, end: usize) -> Result<()> {
+        let _ = (frame, start, end);
+        Err(Error::Unsupported {
+            operation: "Reading an atom range",
+        })
+    }
+
+    /// Turn a boxed trajectory into a boxed iterator over its frames
+    ///
+    /// This works on `Box<dyn Trajectory>` too, so code building on this
+    /// crate can return frames without exposing where they come from.
+    ///
+    /// ```rust
+    /// use std::rc::Rc;
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let trj: Box<dyn Trajectory> = Box::new(XTCTrajectory::open_read("tests/1l2y.xtc")?);
+    /// let frames: Box<dyn Iterator<Item = Result<Rc<Frame>>>> = trj.into_frames();
+    /// assert_eq!(frames.count(), 38);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn into_frames(self: Box<Self>) -> Box<dyn Iterator<Item = Result<std::rc::Rc<Frame>>>>
+    where
+        Self: 'static,
+    {
+        Box::new(iterator::into_iter_inner(self))
+    }
+
+    /// Iterate over the remaining frames without consuming the trajectory
+    ///
+    /// This is the same iterator as `into_iter` on `&mut` trajectory, which
+    /// `for frame in &mut trj` uses, and like the iterator of an owned
+    /// trajectory it reuses its frame where it can. The trajectory can be used
+    /// again once the iterator is dropped, for example to seek back.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// assert_eq!(trj.frames().count(), 38);
+    /// assert!(trj.at_eof()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn frames(&mut self) -> TrajectoryIterator<&mut Self>
+    where
+        Self: Sized,
+    {
+        iterator::into_iter_inner(self)
+    }
+
+    /// Iterate over the frames of a file that is still being written
+    ///
+    /// Like `tail -f`, the iterator does not end at the end of the file, but
+    /// waits for `poll_interval` and checks again for new frames, so that a
+    /// running simulation can be monitored as it goes. A frame that is only
+    /// partly written is read again from its start once more of the file is
+    /// there; libxdrfile may print a message to stderr for each such attempt
+    /// at an XTC frame. Other errors are yielded and end the iteration, as do
+    /// errors reading the number of atoms, so the file must already have the
+    /// header of its first frame if it is not empty. Otherwise, the iterator
+    /// only ends once its `Follow::stop_token` is set.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut frames = trj.follow(Duration::from_millis(100));
+    /// let stop = frames.stop_token();
+    /// for frame in &mut frames {
+    ///     if frame?.step == 38 {
+    ///         stop.store(true, std::sync::atomic::Ordering::Relaxed);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn follow(self, poll_interval: std::time::Duration) -> Follow<Self>
+    where
+        Self: Sized + io::Seek,
+    {
+        Follow::new(self, poll_interval)
+    }
+
+    /// Step of the next frame, without consuming it
+    ///
+    /// Only the header of the frame is read, after which the trajectory is
+    /// rewound so that the next `read` still returns that frame. This makes it
+    /// cheap to decide whether to process or skip the upcoming frame. Returns
+    /// `Ok(None)` at the end of the file. The default implementation returns
+    /// `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// assert_eq!(trj.peek_step()?, Some(1));
+    ///
+    /// let mut frame = Frame::with_len(trj.get_num_atoms()?);
+    /// trj.read(&mut frame)?;
+    /// assert_eq!(frame.step, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn peek_step(&mut self) -> Result<Option<usize>> {
+        Err(Error::Unsupported {
+            operation: "Peeking at the next frame",
+        })
+    }
+
+    /// Time of the next frame, without consuming it
+    ///
+    /// See `peek_step`.
+    fn peek_time(&mut self) -> Result<Option<f32>> {
+        Err(Error::Unsupported {
+            operation: "Peeking at the next frame",
+        })
+    }
+
+    /// Turn strict mode on or off
+    ///
+    /// By default, frames are read as they are. In strict mode, every frame
+    /// read is also checked, and any anomaly fails the read with
+    /// `Error::NonStandardFrame`, leaving the trajectory at the start of the
+    /// offending frame. The checks are:
+    ///
+    /// - the time, box, coordinates and, for TRR, lambda, velocities and
+    ///   forces are all finite
+    /// - the time is greater than that of the previous frame, if that frame
+    ///   was read just before this one without seeking elsewhere
+    /// - the frame has the same number of atoms as the first frame of the file
+    /// - for XTC, the precision of compressed frames is finite and positive
+    ///
+    /// Frames written in strict mode must have a box that GROMACS accepts,
+    /// see `Frame::is_valid_gromacs_box`; writing any other frame fails with
+    /// `Error::NonStandardFrame`, and nothing is written.
+    ///
+    /// The default implementation ignores the setting, as there is nothing
+    /// to check.
+    fn set_strict(&mut self, strict: bool) {
+        let _ = strict;
+    }
+
+    /// Allow or forbid frames with a different number of atoms than the first
+    ///
+    /// By default, reading a frame whose header reports a different number of
+    /// atoms than the first frame of the file fails with
+    /// `Error::VariableAtomCount`, leaving the trajectory at the start of that
+    /// frame, as such files are usually corrupt. When allowed, the frame read
+    /// into is instead resized to the number of atoms of each frame, as with
+    /// `Frame::resize`. Strict mode rejects these frames either way. The
+    /// default implementation ignores the setting.
+    fn set_variable_atom_count(&mut self, allow: bool) {
+        let _ = allow;
+    }
+
+    /// True if strict mode is on, see `set_strict`
+    fn is_strict(&self) -> bool {
+        false
+    }
+
+    /// Number of frames in the trajectory, if it is known without reading
+    ///
+    /// This is `Some` once the frames have been counted, for example by
+    /// `num_frames`, `build_index` or `seek_frame`, and `None` otherwise. It
+    /// never reads from the file, so it is cheap enough to size buffers or
+    /// progress bars opportunistically; use `num_frames` to count the frames
+    /// when they are not known yet. The default implementation returns `None`.
+    fn len(&self) -> Option<usize> {
+        None
+    }
+
+    /// True if the trajectory has no frames, if that is known without reading
+    ///
+    /// See `len`.
+    fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Number of frames in the trajectory, scanning the file if necessary
+    ///
+    /// Unlike `len`, this counts the frames if they are not known yet, which
+    /// reads every frame header. The count is cached. The default
+    /// implementation returns `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// assert_eq!(trj.len(), None);
+    /// assert_eq!(trj.num_frames()?, 38);
+    /// assert_eq!(trj.len(), Some(38));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn num_frames(&mut self) -> Result<usize> {
+        Err(Error::Unsupported {
+            operation: "Counting frames",
+        })
+    }
+
+    /// Position the trajectory at the first frame with a time of at least
+    /// `time`, so that the next read returns it
+    ///
+    /// The frame is found by a binary search over the times in the index of
+    /// all frame headers, built on first use as for `num_frames`, so the
+    /// times must not decrease through the file. Returns
+    /// `Error::TimeOutOfRange` if every frame is earlier than `time`. The
+    /// default implementation returns `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// trj.seek_by_time(10.0)?;
+    /// assert_eq!(trj.peek_time()?, Some(10.0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn seek_by_time(&mut self, time: f32) -> Result<()> {
+        let _ = time;
+        Err(Error::Unsupported {
+            operation: "Seeking by time",
+        })
+    }
+
+    /// Position the trajectory at the frame with the time closest to `time`,
+    /// so that the next read returns it
+    ///
+    /// Of two frames equally close, the earlier one is chosen. Unlike
+    /// `seek_by_time`, this fails with `Error::TimeOutOfRange` if
+    /// `time` is before the first or after the last frame, rather than
+    /// settling for the nearest end. The frame is found with a binary search
+    /// over the index of all frame headers, built on first use as for
+    /// `num_frames`, which assumes that the times never decrease through the
+    /// file. If they do, every frame is compared instead; see
+    /// `TrajectoryIndex::nearest_time`. The default implementation returns
+    /// `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// trj.seek_time(9.8)?;
+    /// assert_eq!(trj.peek_time()?, Some(10.0));
+    /// assert!(trj.seek_time(100.0).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn seek_time(&mut self, time: f32) -> Result<()> {
+        let _ = time;
+        Err(Error::Unsupported {
+            operation: "Seeking by time",
+        })
+    }
+
+    /// Read frame `n` into a newly allocated frame, counting from 0
+    ///
+    /// This seeks to the frame with the index of all frame headers, built on
+    /// first use as for `num_frames`, and leaves the trajectory positioned
+    /// after it, so that the next read returns frame `n + 1`. Returns
+    /// `Error::FrameOutOfRange` if the trajectory has `n` frames or fewer.
+    /// The default implementation returns `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let frame = trj.read_nth(19)?;
+    /// assert_eq!(frame.step, 20);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn read_nth(&mut self, n: usize) -> Result<Frame> {
+        let _ = n;
+        Err(Error::Unsupported {
+            operation: "Reading a frame by number",
+        })
+    }
+
+    /// Read frame `index` into `frame`, counting from 0
+    ///
+    /// Like `read_nth`, but into an existing frame, which must hold the right
+    /// number of atoms as for `read`. Frames are located with the cached
+    /// index of all frame headers, so after the index is built on first use,
+    /// any frame can be read without reading the frames before it, as when
+    /// scrubbing through a trajectory in a viewer. The default implementation
+    /// returns `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut frame = Frame::with_len(trj.get_num_atoms()?);
+    /// for index in [30, 2, 17] {
+    ///     trj.read_frame_at(index, &mut frame)?;
+    ///     assert_eq!(frame.step, index + 1);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn read_frame_at(&mut self, index: usize, frame: &mut Frame) -> Result<()> {
+        let _ = (index, frame);
+        Err(Error::Unsupported {
+            operation: "Reading a frame by number",
+        })
+    }
+
+    /// Estimated number of bytes that `num_frames` frames like `frame` take
+    /// when written with the current settings
+    ///
+    /// Together with `check_disk_space`, this lets a long write fail up front
+    /// rather than when the disk fills. For TRR the estimate is exact, given
+    /// the blocks selected with `TRRTrajectory::set_blocks`. XTC frames are
+    /// compressed to a size that depends on how the atoms are arranged, so
+    /// the estimate assumes every atom is stored at the full width of the
+    /// coordinate range of `frame` at the current precision. Most frames are
+    /// smaller, as atoms close to their neighbours take fewer bits, so treat
+    /// it as a generous upper bound. The default implementation returns
+    /// `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let tempfile = tempfile::NamedTempFile::new().unwrap();
+    /// let trj = TRRTrajectory::open_write(tempfile.path())?;
+    /// let frame = Frame::with_len(100);
+    /// // 84 bytes of header, 36 of box and 1200 of coordinates per frame
+    /// assert_eq!(trj.estimate_write_size(10, &frame)?, 13200);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn estimate_write_size(&self, num_frames: usize, frame: &Frame) -> Result<u64> {
+        let _ = (num_frames, frame);
+        Err(Error::Unsupported {
+            operation: "Estimating the size of a write",
+        })
+    }
+
+    /// True if the next `read` would reach the end of the file
+    ///
+    /// This allows driver loops that do not rely on recognising the error for
+    /// the end of the file:
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut frame = Frame::with_len(trj.get_num_atoms()?);
+    /// let mut num_frames = 0;
+    /// while !trj.at_eof()? {
+    ///     trj.read(&mut frame)?;
+    ///     num_frames += 1;
+    /// }
+    /// assert_eq!(num_frames, 38);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// A truncated frame at the end of the file is not the end of the file, so
+    /// reading it returns an error. The default implementation uses
+    /// `peek_step`.
+    fn at_eof(&mut self) -> Result<bool> {
+        Ok(self.peek_step()?.is_none())
+    }
+
+    /// Settings that would write frames like those in this trajectory
+    ///
+    /// For XTC, this is the precision of the next compressed frame, or of the
+    /// last frame read, falling back to the precision set for writing. For
+    /// TRR, it is the blocks of the next frame, with `TrrBlocks::LAMBDA` so
+    /// lambda is kept, falling back to the blocks set for writing. The
+    /// trajectory is left at the same position. The default implementation
+    /// returns no settings.
+    fn write_settings(&mut self) -> Result<WriteSettings> {
+        Ok(WriteSettings::default())
+    }
+
+    /// Apply those of `settings` that this trajectory supports, ignoring the rest
+    ///
+    /// The default implementation ignores all settings.
+    fn apply_write_settings(&mut self, settings: WriteSettings) {
+        let _ = settings;
+    }
+
+    /// Write frames from now on like those of `source`
+    ///
+    /// This copies the XTC precision or the TRR block layout of `source`, see
+    /// `write_settings`, so that filtering a trajectory does not recompress it
+    /// at a different precision or drop blocks. Settings of a different
+    /// format than this trajectory's are ignored.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let tempfile = tempfile::NamedTempFile::new().unwrap();
+    /// let mut src = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut dst = XTCTrajectory::open_write(tempfile.path())?;
+    /// dst.copy_settings_from(&mut src)?;
+    /// assert_eq!(dst.write_settings()?.precision, Some(10000.0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn copy_settings_from(&mut self, source: &mut dyn Trajectory) -> Result<()> {
+        let settings = source.write_settings()?;
+        self.apply_write_settings(settings);
+        Ok(())
+    }
+
+    /// SHA-256 digest of the trajectory file
+    ///
+    /// This hashes the raw bytes of the whole file, not the decoded frames, so
+    /// it is fast and does not depend on the position in the file, but the
+    /// same frames written with a different precision or by another program
+    /// give a different digest. It is meant for verifying downloads and
+    /// detecting modified files. Pending writes are flushed first. The default
+    /// implementation returns `Error::Unsupported`.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let digest = trj.digest()?;
+    /// assert_eq!(digest, trj.digest()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "sha2")]
+    fn digest(&mut self) -> Result<[u8; 32]> {
+        Err(Error::Unsupported {
+            operation: "Computing a digest",
+        })
     }
 }
 
-impl TRRTrajectory {
-    /// Get the current position in the file
-    pub fn tell(&self) -> u64 {
-        self.handle.tell()
+impl<T: Trajectory + ?Sized> Trajectory for Box<T> {
+    forward_trajectory!(|this| (**this));
+}
+
+/// Trajectories borrowed mutably are trajectories too, so that adapters such as
+/// `TrajectoryIterator` can be used without giving up the trajectory
+impl<T: Trajectory + ?Sized> Trajectory for &mut T {
+    forward_trajectory!(|this| (**this));
+}
+
+/// Header of an XTC frame read with `XTCTrajectory::read_header`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XtcHeader {
+    /// Number of atoms in the frame
+    pub num_atoms: usize,
+
+    /// Trajectory step
+    pub step: usize,
+
+    /// Time step (usually in picoseconds)
+    pub time: f32,
+
+    /// 3x3 box vector (in nanometers)
+    pub box_vector: [[f32; 3]; 3],
+}
+
+/// How the coordinates of an XTC frame are compressed, see
+/// `XTCTrajectory::peek_compression`
+///
+/// XTC rounds each coordinate to a multiple of `1 / precision` and stores
+/// the integers relative to their minimum in the frame. Most atoms are
+/// written as a full triplet of `large_bits` bits, the bit length of the
+/// product of the ranges of the three integer coordinates, so it grows with
+/// both the precision and the size of the system. Runs of atoms close to the
+/// previous one, as in water molecules, are written as differences using
+/// `small_bits` bits per triplet instead. A file that is larger than expected
+/// usually has a high precision, a spread-out system, or few atoms close
+/// enough to their neighbours for the small encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XtcCompression {
+    /// Precision the coordinates were compressed with
+    pub precision: f32,
+    /// Number of bits for the triplet of coordinates of an atom
+    pub large_bits: u32,
+    /// Number of bits for the triplet of differences to the previous atom, at
+    /// the start of the frame; it adapts to the data as the frame goes on
+    pub small_bits: u32,
+    /// Number of bytes of compressed coordinates, excluding the header
+    pub bytes: u64,
+}
+
+/// Statistics about the last frame written to an XTC trajectory
+///
+/// The C API does not report anything about the compression it performed, so
+/// these are limited to what can be observed from the outside: the number of
+/// bytes the frame occupies in the file and the precision it was written with.
+/// The number of bits used per coordinate is chosen internally by libxdrfile
+/// and is not exposed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteStats {
+    /// Number of bytes the frame occupies in the file, including its header
+    pub bytes: u64,
+    /// Precision the coordinates were compressed with
+    pub precision: f32,
+    /// False if the frame was stored uncompressed; XTC only compresses frames
+    /// with more than 9 atoms, and ignores the precision otherwise
+    pub compressed: bool,
+}
+
+/// Format-specific parameters that control how frames are written
+///
+/// Settings that do not apply to a format are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WriteSettings {
+    /// XTC compression precision
+    pub precision: Option<f32>,
+    /// TRR data blocks
+    pub blocks: Option<TrrBlocks>,
+}
+
+/// First frame in `index` at or after `time`, for `Trajectory::seek_by_time`
+fn first_at_time(index: &TrajectoryIndex, time: f32) -> Result<usize> {
+    if time.is_nan() {
+        return Err(Error::InvalidArgument {
+            name: "time",
+            value: time.to_string(),
+        });
+    }
+    index.first_at_time(time).ok_or(Error::TimeOutOfRange {
+        time,
+        range: index.time_range(),
+    })
+}
+
+/// Frame in `index` closest to `time`, for `Trajectory::seek_time`
+fn nearest_time(index: &TrajectoryIndex, time: f32) -> Result<usize> {
+    if time.is_nan() {
+        return Err(Error::InvalidArgument {
+            name: "time",
+            value: time.to_string(),
+        });
+    }
+    let range = index.time_range();
+    match (range, index.nearest_time(time)) {
+        (Some((first, last)), Some(k)) if first <= time && time <= last => Ok(k),
+        _ => Err(Error::TimeOutOfRange { time, range }),
+    }
+}
+
+/// Number of the frame starting at `position`, if it is known without reading
+/// the file
+///
+/// `next_frame` is the position after the last frame read and the number of
+/// the frame there, and `index` is the cached index, if any.
+fn frame_number_at(
+    position: u64,
+    next_frame: Option<(u64, usize)>,
+    index: Option<&TrajectoryIndex>,
+) -> Option<usize> {
+    match next_frame {
+        Some((end, k)) if end == position => Some(k),
+        _ if position == 0 => Some(0),
+        _ => index?
+            .entries()
+            .binary_search_by_key(&position, |entry| entry.offset)
+            .ok(),
+    }
+}
+
+/// Random access to the frames of a trajectory through an index of their
+/// offsets, shared by the XTC and TRR trajectories
+trait IndexedTrajectory: Trajectory + Sized {
+    /// Skip over the next frame, returning its step and time, or None at the
+    /// end of the file, as `XDRFile::build_index` expects
+    fn skip_frame(handle: &mut XDRFile) -> Result<Option<(usize, f32)>>;
+
+    /// Open the file at `path` for reading
+    #[cfg(feature = "rayon")]
+    fn reopen(path: &Path) -> Result<Self>;
+
+    /// The file handle and the cached index
+    fn handle_and_index(&mut self) -> (&mut XDRFile, &mut Option<TrajectoryIndex>);
+
+    /// Scan the file for all frames and cache the index
+    fn build_index(&mut self) -> Result<TrajectoryIndex> {
+        let (handle, cached) = self.handle_and_index();
+        let index = handle.build_index(Self::skip_frame)?;
+        *cached = Some(index.clone());
+        Ok(index)
+    }
+
+    /// The index of all frames, built with `build_index` on first use and cached
+    fn cached_index(&mut self) -> Result<&TrajectoryIndex> {
+        let (handle, cached) = self.handle_and_index();
+        if cached.is_none() {
+            *cached = Some(handle.build_index(Self::skip_frame)?);
+        }
+        Ok(cached.as_ref().expect("Index was just built"))
+    }
+
+    /// Position the file at the start of frame `k`
+    fn seek_frame(&mut self, k: usize) -> Result<()> {
+        let offset = {
+            let index = self.cached_index()?;
+            index.offset_of(k).ok_or(Error::FrameOutOfRange {
+                index: k,
+                num_frames: index.len(),
+            })?
+        };
+        self.handle_and_index().0.seek_to(offset, ErrorTask::Seek)
+    }
+
+    /// Number of the frame at the current position in the file
+    fn current_frame(&mut self) -> Result<usize> {
+        let position = self.handle_and_index().0.try_tell()?;
+        if position == 0 {
+            return Ok(0);
+        }
+        let index = self.cached_index()?;
+        let num_frames = index.len();
+        match index
+            .entries()
+            .binary_search_by_key(&position, |entry| entry.offset)
+        {
+            Ok(k) => Ok(k),
+            // The last frame ends at the end of the file
+            Err(k) if k == num_frames && self.handle_and_index().0.at_eof()? => Ok(k),
+            Err(_) => Err(Error::NotAtFrameStart { position }),
+        }
+    }
+
+    /// Iterate over the remaining frames, numbered from `current_frame`
+    fn indexed_frames(mut self) -> Result<IndexedFrames<Self>> {
+        let start = IndexedTrajectory::current_frame(&mut self)?;
+        Ok(IndexedFrames::new(iterator::into_iter_inner(self), start))
+    }
+
+    /// Fold over all frames in parallel, reading each chunk through its own
+    /// file handle
+    #[cfg(feature = "rayon")]
+    fn par_map_reduce<T, A>(
+        &mut self,
+        map: impl Fn(&Frame) -> T + Sync,
+        reduce: impl Fn(A, T) -> A + Sync,
+        combine: impl Fn(A, A) -> A,
+        init: impl Fn() -> A + Sync,
+    ) -> Result<A>
+    where
+        A: Send,
+    {
+        let index = self.cached_index()?.clone();
+        let num_atoms = self.get_num_atoms()?;
+        let path = &self.handle_and_index().0.path;
+        let open_at = |offset| {
+            let mut traj = Self::reopen(path)?;
+            traj.handle_and_index().0.seek_to(offset, ErrorTask::Seek)?;
+            Ok(traj)
+        };
+        parallel::map_reduce(&index, num_atoms, open_at, map, reduce, combine, init)
+    }
+}
+
+/// Why `frame` fails the checks of strict mode shared by all formats, if it
+/// does
+///
+/// `previous_time` is the time of the frame read just before it.
+fn check_frame_strict(frame: &Frame, previous_time: Option<f32>) -> Option<String> {
+    if !frame.time.is_finite() {
+        return Some(format!("time {} is not finite", frame.time));
+    }
+    if frame.box_vector.iter().flatten().any(|x| !x.is_finite()) {
+        return Some("box is not finite".to_string());
+    }
+    let blocks = [
+        ("coordinate", Some(&frame.coords)),
+        ("velocity", frame.velocities.as_ref()),
+        ("force", frame.forces.as_ref()),
+    ];
+    for (name, values) in blocks.iter() {
+        let values = values.map_or(&[][..], |v| &v[..]);
+        if let Some(atom) = values.iter().position(|x| x.iter().any(|x| !x.is_finite())) {
+            return Some(format!("{} of atom {} is not finite", name, atom));
+        }
+    }
+    match previous_time {
+        Some(previous) if frame.time <= previous => Some(format!(
+            "time {} does not follow time {}",
+            frame.time, previous
+        )),
+        _ => None,
+    }
+}
+
+/// Fail with `Error::NonStandardFrame` if the frame to write in strict mode
+/// does not have a box that GROMACS accepts
+fn check_box_strict(frame: &Frame) -> Result<()> {
+    if frame.is_valid_gromacs_box() {
+        Ok(())
+    } else {
+        Err(Error::NonStandardFrame {
+            step: frame.step,
+            reason: format!(
+                "box {:?} does not follow the GROMACS conventions",
+                frame.box_vector
+            ),
+        })
+    }
+}
+
+/// Handle to Read/Write XTC Trajectories
+///
+/// Frames are read and written by libxdrfile, which always assumes standard
+/// big-endian XDR, see `ByteOrder`.
+pub struct XTCTrajectory {
+    handle: XDRFile,
+    precision: Cell<c_float>, // precision used for writing
+    num_atoms: Lazy<Result<usize>>,
+    last_write_stats: Option<WriteStats>,
+    /// Number of bytes of the last frame read
+    last_read_len: Option<u64>,
+    /// Precision of the last frame read, if it was compressed
+    read_precision: Option<f32>,
+    index: Option<TrajectoryIndex>,
+    strict: bool,
+    /// End position and time of the last frame read in strict mode
+    last_read: Option<(u64, f32)>,
+    /// End position of the last frame read and the number of the frame there
+    next_frame: Option<(u64, usize)>,
+    /// True if frames may have a different number of atoms than the first
+    variable_atom_count: bool,
+    /// True if the precision should be taken from the file before writing
+    detect_precision: bool,
+}
+
+impl XTCTrajectory {
+    /// Open a file in the given mode
+    ///
+    /// This only opens the file. Nothing is read from it until the first
+    /// frame or the number of atoms is requested, so opening a file that is
+    /// not a valid trajectory succeeds and the error is reported by the first
+    /// read.
+    pub fn open(path: impl AsRef<Path>, filemode: FileMode) -> Result<XTCTrajectory> {
+        let xdr = XDRFile::open(path, filemode)?;
+        Ok(XTCTrajectory {
+            detect_precision: xdr.filemode == FileMode::Append,
+            handle: xdr,
+            precision: Cell::new(XTC_DEFAULT_PRECISION),
+            num_atoms: Lazy::new(),
+            last_write_stats: None,
+            last_read_len: None,
+            read_precision: None,
+            index: None,
+            strict: false,
+            variable_atom_count: false,
+            last_read: None,
+            next_frame: None,
+        })
+    }
+
+    /// Open a file in read mode
+    pub fn open_read(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(path, FileMode::Read)
+    }
+
+    /// Open a file in append mode
+    ///
+    /// So that all frames of the file are compressed alike, frames are
+    /// appended with the precision of the last frame already in the file,
+    /// rather than the default. This is looked up when the first frame is
+    /// written, which fails if the existing frames cannot be read. Calling
+    /// `set_precision` before writing overrides it. The default precision is
+    /// kept if the file is empty or its last frame is not compressed.
+    pub fn open_append(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(path, FileMode::Append)
+    }
+
+    /// Open a file in write mode
+    pub fn open_write(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(path, FileMode::Write)
+    }
+
+    /// Open a file in write mode, compressing frames with `precision`
+    ///
+    /// This is `open_write` followed by `set_precision`.
+    pub fn open_write_with_precision(path: impl AsRef<Path>, precision: f32) -> Result<Self> {
+        let mut xtc = Self::open_write(path)?;
+        xtc.set_precision(precision);
+        Ok(xtc)
+    }
+}
+
+impl TryFrom<&Path> for XTCTrajectory {
+    type Error = Error;
+
+    /// Open a file in read mode
+    fn try_from(path: &Path) -> Result<Self> {
+        Self::open_read(path)
+    }
+}
+
+impl TryFrom<PathBuf> for XTCTrajectory {
+    type Error = Error;
+
+    /// Open a file in read mode
+    fn try_from(path: PathBuf) -> Result<Self> {
+        Self::open_read(path)
+    }
+}
+
+impl Trajectory for XTCTrajectory {
+    /// Read the next frame into `frame`
+    ///
+    /// Frames of an unsupported revision of the XTC format fail with
+    /// `Error::UnsupportedXtcVersion`, see `XTCTrajectory::version`.
+    fn read(&mut self, frame: &mut Frame) -> Result<()> {
+        self.last_read_len = None;
+        let start = self.handle.try_tell()?;
+        // A header that cannot be parsed is reported by reading the frame
+        let header = self.peek_header().unwrap_or(None);
+        if self.strict {
+            self.check_num_atoms_strict(header.as_ref())?;
+        }
+        let next_num_atoms = header.map(|header| header.num_atoms);
+        let read = retry_interrupted(
+            self,
+            |xtc| xtc.read_supported(frame, next_num_atoms),
+            |xtc| xtc.handle.seek_to(start, ErrorTask::Read),
+        );
+        let stored_precision = match read {
+            Err(Error::CouldNotCheckNAtoms(e)) => {
+                // The number of atoms is read from the first frame
+                let e = Error::CouldNotCheckNAtoms(e);
+                return Err(self.unsupported_version_at(0).unwrap_or(e));
+            }
+            Err(e) if !e.is_eof() => {
+                return Err(self.unsupported_version_at(start).unwrap_or(e));
+            }
+            result => result?,
+        };
+        if self.strict {
+            let previous = self.last_read.filter(|&(end, _)| end == start);
+            let mut reason = check_frame_strict(frame, previous.map(|(_, time)| time));
+            if let Some(precision) = stored_precision.filter(|p| !(p.is_finite() && *p > 0.0)) {
+                let invalid = format!("precision {} is not finite and positive", precision);
+                reason = reason.or(Some(invalid));
+            }
+            if let Some(reason) = reason {
+                self.handle.seek_to(start, ErrorTask::Read)?;
+                return Err(Error::NonStandardFrame {
+                    step: frame.step,
+                    reason,
+                });
+            }
+            self.last_read = Some((self.handle.try_tell()?, frame.time));
+        }
+        let end = self.handle.try_tell()?;
+        self.next_frame =
+            frame_number_at(start, self.next_frame, self.index.as_ref()).map(|k| (end, k + 1));
+        self.last_read_len = Some(end - start);
+        Ok(())
+    }
+
+    /// Write the frame to the trajectory file
+    ///
+    /// Frames of more than 9 atoms are compressed, which fails with
+    /// `Error::CoordinateOutOfRange` if a coordinate is too large for the
+    /// precision. See `XTCTrajectory::max_coordinate`. Nothing is written in
+    /// that case.
+    fn write(&mut self, frame: &Frame) -> Result<()> {
+        if self.strict {
+            check_box_strict(frame)?;
+        }
+        if self.detect_precision {
+            if let Some(precision) = self.precision_of_last_frame()? {
+                self.precision.set(precision);
+            }
+            self.detect_precision = false;
+        }
+        let precision = self.precision.get();
+        if frame.num_atoms() > 9 {
+            check_compressible(&frame.coords, precision)?;
+        }
+        let start = self.handle.try_tell()?;
+        let num_atoms = to!(frame.num_atoms(), ErrorTask::Write)?;
+        let step = to!(frame.step, ErrorTask::Write)?;
+        let xdrfile = self.handle.xdrfile;
+        check_os_call(ErrorTask::Write, || unsafe {
+            xdrfile_xtc::write_xtc(
+                xdrfile,
+                num_atoms,
+                step,
+                frame.time,
+                &frame.box_vector,
+                frame.coords.as_ptr(),
+                precision,
+            )
+        })?;
+        self.index = None;
+        self.next_frame = None;
+        self.handle.dirty = true;
+        self.last_write_stats = Some(WriteStats {
+            bytes: self.handle.try_tell()? - start,
+            precision,
+            compressed: frame.num_atoms() > 9,
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.handle.flush()
+    }
+
+    fn get_num_atoms(&mut self) -> Result<usize> {
+        self.num_atoms
+            .get_or_create(|| {
+                let mut num_atoms: c_int = 0;
+
+                unsafe {
+                    let path = path_to_cstring(&self.handle.path)?;
+                    let path_p = path.into_raw();
+                    let code = xdrfile_xtc::read_xtc_natoms(path_p, &mut num_atoms);
+                    // Reconstitute the CString so it is deallocated correctly
+                    let _ = CString::from_raw(path_p);
+
+                    if let Some(err) = check_code(code, ErrorTask::ReadNumAtoms) {
+                        Err(err)
+                    } else {
+                        checked_num_atoms(num_atoms, &self.handle.path)
+                    }
+                }
+            })
+            .clone()
+    }
+
+    fn peek_step(&mut self) -> Result<Option<usize>> {
+        Ok(self.peek_header()?.map(|header| header.step))
+    }
+
+    fn peek_time(&mut self) -> Result<Option<f32>> {
+        Ok(self.peek_header()?.map(|header| header.time))
+    }
+
+    fn at_eof(&mut self) -> Result<bool> {
+        self.handle.at_eof()
+    }
+
+    fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+        self.last_read = None;
+    }
+
+    fn set_variable_atom_count(&mut self, allow: bool) {
+        self.variable_atom_count = allow;
+    }
+
+    fn len(&self) -> Option<usize> {
+        self.index.as_ref().map(|index| index.len())
+    }
+
+    fn num_frames(&mut self) -> Result<usize> {
+        Ok(self.cached_index()?.len())
+    }
+
+    fn seek_by_time(&mut self, time: f32) -> Result<()> {
+        let k = first_at_time(self.cached_index()?, time)?;
+        self.seek_frame(k)
+    }
+
+    fn seek_time(&mut self, time: f32) -> Result<()> {
+        let k = nearest_time(self.cached_index()?, time)?;
+        self.seek_frame(k)
+    }
+
+    fn read_nth(&mut self, n: usize) -> Result<Frame> {
+        let mut frame = Frame::with_len(self.get_num_atoms()?);
+        self.read_frame_at(n, &mut frame)?;
+        Ok(frame)
+    }
+
+    fn read_frame_at(&mut self, index: usize, frame: &mut Frame) -> Result<()> {
+        self.seek_frame(index)?;
+        self.read(frame)
+    }
+
+    fn estimate_write_size(&self, num_frames: usize, frame: &Frame) -> Result<u64> {
+        // Magic number, atoms, step, time, box and atoms again
+        let mut bytes = 4 * 4 + 9 * 4 + 4;
+        let num_atoms = frame.len() as u64;
+        if num_atoms <= 9 {
+            bytes += num_atoms * 3 * 4;
+        } else {
+            let precision = f64::from(self.precision.get());
+            let mut bits_per_atom = 1.0;
+            for k in 0..3 {
+                let scaled = frame
+                    .coords
+                    .iter()
+                    .map(|x| (f64::from(x[k]) * precision).round());
+                let (min, max) =
+                    scaled.fold((0.0_f64, 0.0_f64), |(min, max), x| (min.min(x), max.max(x)));
+                bits_per_atom += (max - min + 1.0).log2();
+            }
+            let data = (num_atoms as f64 * bits_per_atom.ceil() / 8.0).ceil() as u64;
+            // Precision, minimum and maximum, small index, byte count and
+            // data padded to whole words
+            bytes += 4 + 2 * 3 * 4 + 4 + 4 + data.div_ceil(4) * 4;
+        }
+        Ok(bytes * num_frames as u64)
+    }
+
+    fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    fn write_settings(&mut self) -> Result<WriteSettings> {
+        // Files opened for writing cannot be read
+        let next = if self.handle.filemode == FileMode::Read {
+            self.peek_precision()?
+        } else {
+            None
+        };
+        let precision = match next {
+            Some(precision) => precision,
+            None => self.read_precision.unwrap_or(self.precision.get()),
+        };
+        Ok(WriteSettings {
+            precision: Some(precision),
+            blocks: None,
+        })
+    }
+
+    fn apply_write_settings(&mut self, settings: WriteSettings) {
+        if let Some(precision) = settings.precision {
+            self.set_precision(precision);
+        }
+    }
+
+    #[cfg(feature = "sha2")]
+    fn digest(&mut self) -> Result<[u8; 32]> {
+        self.handle.digest()
+    }
+}
+
+impl XTCTrajectory {
+    /// Get the current position in the file
+    ///
+    /// # Panics
+    /// Panics if the position cannot be determined. See `try_tell`.
+    pub fn tell(&self) -> u64 {
+        self.handle.tell()
+    }
+
+    /// Get the current position in the file, or an error if it cannot be determined
+    pub fn try_tell(&self) -> Result<u64> {
+        self.handle.try_tell()
+    }
+
+    /// Flush any buffered writes and close the file
+    ///
+    /// Dropping the trajectory also flushes and closes it, but cannot report
+    /// a failure, beyond a warning in debug builds. Closing explicitly is
+    /// preferred after writing, as it returns the error.
+    pub fn close(mut self) -> Result<()> {
+        self.handle.close()
+    }
+
+    /// Set the precision used to compress the coordinates of frames written
+    /// from now on
+    ///
+    /// Coordinates are rounded to multiples of `1 / precision`, so the
+    /// default of 1000.0 keeps 3 decimal places. Lower precisions give
+    /// smaller files, and allow larger coordinates; see `max_coordinate`.
+    pub fn set_precision(&mut self, precision: f32) {
+        self.precision.set(precision);
+        self.detect_precision = false;
+    }
+
+    /// Precision of the last compressed frame of the file, read through a
+    /// separate handle as append mode cannot read
+    fn precision_of_last_frame(&self) -> Result<Option<f32>> {
+        let mut existing = match XTCTrajectory::open_read(&self.handle.path) {
+            Ok(existing) => existing,
+            // Appending created the file, or it cannot be read
+            Err(Error::CouldNotOpen { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match existing.build_index()?.entries().last() {
+            Some(last) => {
+                existing.handle.seek_to(last.offset, ErrorTask::Read)?;
+                existing.peek_precision()
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Revision of the XTC format the file is written in
+    ///
+    /// This is detected from the magic number of the first frame, without
+    /// changing the position in the file. Returns None for an empty file,
+    /// and an error if the first frame does not start with an XTC magic
+    /// number. Only `XtcVersion::V1995` can be read; reading frames of other
+    /// revisions fails with `Error::UnsupportedXtcVersion`, which helps to
+    /// tell why a file written by a newer GROMACS cannot be read.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// assert_eq!(trj.version()?, Some(XtcVersion::V1995));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn version(&mut self) -> Result<Option<XtcVersion>> {
+        match self.magic_at(0)? {
+            None => Ok(None),
+            Some(magic) => match XtcVersion::from_magic(magic) {
+                Some(version) => Ok(Some(version)),
+                None => Err((ErrorCode::ExdrMagic, ErrorTask::Read).into()),
+            },
+        }
+    }
+
+    /// Precision the last frame read was compressed with
+    ///
+    /// This is None if no frame has been read yet, or if the last frame had
+    /// 9 atoms or fewer, as XTC stores such frames uncompressed.
+    pub fn read_precision(&self) -> Option<f32> {
+        self.read_precision
+    }
+
+    /// Read the header of the next step and skip its coordinates, or return
+    /// None at the end of the file
+    ///
+    /// The size of the compressed coordinates is stored in front of them, so
+    /// they are skipped without being decoded, which makes this much cheaper
+    /// than `read` for scanning a large file. The trajectory is left at the
+    /// start of the next frame, so the skipped frame can only be read by
+    /// seeking back to it, for example with `seek_frame`. If the header
+    /// cannot be read, the trajectory is returned to the start of the frame.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut last_step = 0;
+    /// while let Some(header) = trj.read_header()? {
+    ///     assert_eq!(header.num_atoms, 304);
+    ///     last_step = header.step;
+    /// }
+    /// assert_eq!(last_step, 38);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_header(&mut self) -> Result<Option<XtcHeader>> {
+        let task = ErrorTask::Read;
+        let start = self.handle.try_tell()?;
+        let header = Self::read_header_from(&mut self.handle, task);
+        if header.is_err() {
+            self.handle.seek_to(start, task)?;
+        }
+        header
+    }
+
+    /// Compression parameters of the next frame, without consuming it
+    ///
+    /// Only the header of the frame's coordinates is read, after which the
+    /// trajectory is rewound as for `peek_step`. Returns `Ok(None)` at the
+    /// end of the file, and for frames of 9 atoms or fewer, which are stored
+    /// uncompressed. See `XtcCompression` for what the numbers mean.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let compression = trj.peek_compression()?.expect("The frame is compressed");
+    /// assert_eq!(compression.precision, 10000.0);
+    /// assert!(compression.large_bits > compression.small_bits);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn peek_compression(&mut self) -> Result<Option<XtcCompression>> {
+        let task = ErrorTask::Read;
+        self.rewinding(|handle| {
+            let precision = match Self::parse_header(handle, task)? {
+                Some(header) => Self::parse_precision(handle, &header, task)?,
+                None => None,
+            };
+            let precision = match precision {
+                Some(precision) => precision,
+                None => return Ok(None),
+            };
+            // Minimum and maximum integer coordinates, small exponent, length
+            let mut ints = [0; 8];
+            handle.read_ints(&mut ints, task)?;
+            let ranges: Vec<u128> = (0..3)
+                .map(|i| (i64::from(ints[i + 3]) - i64::from(ints[i]) + 1) as u128)
+                .collect();
+            let bit_length = |x: u128| 128 - x.leading_zeros();
+            // As in libxdrfile, ranges this large are encoded one by one
+            let large_bits = if ranges.iter().any(|&r| r > 0xff_ffff) {
+                ranges.iter().map(|&r| bit_length(r)).sum()
+            } else {
+                bit_length(ranges.iter().product())
+            };
+            Ok(Some(XtcCompression {
+                precision,
+                large_bits,
+                small_bits: to!(ints[6], task)?,
+                bytes: to!(ints[7], task)?,
+            }))
+        })
+    }
+
+    /// Read the next frame, and report how lossy its compression was
+    ///
+    /// Returns the precision the frame was stored with and the largest
+    /// rounding error this implies for each coordinate. Coordinates are
+    /// rounded to the nearest multiple of `1 / precision`, so the error is at
+    /// most `0.5 / precision`: 0.0005 nm for the default of 1000. Frames of 9
+    /// atoms or fewer are stored uncompressed, and are reported with an
+    /// infinite precision and no error.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// let mut frame = Frame::with_len(trj.get_num_atoms()?);
+    /// let (precision, max_error) = trj.read_with_precision_check(&mut frame)?;
+    /// assert_eq!(precision, 10000.0);
+    /// assert_eq!(max_error, 0.00005);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_with_precision_check(&mut self, frame: &mut Frame) -> Result<(f32, f32)> {
+        self.read(frame)?;
+        Ok(match self.read_precision {
+            Some(precision) => (precision, 0.5 / precision),
+            None => (f32::INFINITY, 0.0),
+        })
+    }
+
+    /// Largest coordinate that can be compressed with the given precision
+    ///
+    /// XTC stores coordinates as integer multiples of `1 / precision`, which
+    /// must fit in an `i32`. Along each axis, the magnitude of every
+    /// coordinate and the distance between the smallest and largest
+    /// coordinate must both be below this value, so keeping all coordinates
+    /// within half of it in either direction is always safe. For the default
+    /// precision of 1000.0 this is about 2.1e6 nm.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// let max = XTCTrajectory::max_coordinate(1000.0);
+    /// assert!(max > 2.1e6 && max < 2.2e6);
+    /// ```
+    pub fn max_coordinate(precision: f32) -> f32 {
+        (MAX_SCALED_COORDINATE / f64::from(precision)) as f32
+    }
+
+    /// Number of bytes the most recently read frame occupies in the file,
+    /// including its header
+    ///
+    /// This is the distance between the positions before and after the read,
+    /// so the frame's raw bytes can be sliced out of the file, or frames like
+    /// it skipped without decoding them. Returns None if no frame has been
+    /// read yet, or if the last read failed.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+    /// assert_eq!(trj.last_frame_byte_len(), None);
+    /// let mut frame = Frame::with_len(trj.get_num_atoms()?);
+    /// trj.read(&mut frame)?;
+    /// assert_eq!(trj.last_frame_byte_len(), Some(trj.tell()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn last_frame_byte_len(&self) -> Option<u64> {
+        self.last_read_len
+    }
+
+    /// Statistics about the most recently written frame, or None if no frame
+    /// has been written yet
+    pub fn last_write_stats(&self) -> Option<WriteStats> {
+        self.last_write_stats
+    }
+
+    /// Scan the file for the offset, step and time of every frame
+    ///
+    /// Only the frame headers are read; the compressed coordinates are
+    /// skipped. The position in the file is unchanged afterwards. The index
+    /// is also kept for `seek_frame` and `Trajectory::len`.
+    pub fn build_index(&mut self) -> Result<TrajectoryIndex> {
+        IndexedTrajectory::build_index(self)
+    }
+
+    /// Use an index built earlier instead of scanning the file
+    ///
+    /// An index from `build_index` can be stored alongside a large
+    /// trajectory, for example by saving its `TrajectoryIndex::entries`, and
+    /// handed back when the file is opened again, so that `seek_frame` and
+    /// the other random access methods do not have to scan it. The index is
+    /// trusted as it is: it must have been built from the same file, or
+    /// seeks end up at the wrong positions. Writing to the trajectory
+    /// discards it, as for a built index.
+    pub fn set_index(&mut self, index: TrajectoryIndex) {
+        self.index = Some(index);
+    }
+
+    /// Position the trajectory at the start of frame `k`, so that the next
+    /// read returns it
+    ///
+    /// The first call scans all frame headers to build an index (see
+    /// `build_index`), which is cached for later seeks. Returns
+    /// `Error::FrameOutOfRange` if the trajectory has `k` frames or fewer.
+    pub fn seek_frame(&mut self, k: usize) -> Result<()> {
+        IndexedTrajectory::seek_frame(self, k)
+    }
+
+    /// Number of the frame that the next read will return, counting from 0
+    ///
+    /// At the start of the file this is always 0; elsewhere, it is looked up
+    /// in the cached index, which is built if necessary. After the last frame
+    /// it is the number of frames. Returns `Error::NotAtFrameStart` if the
+    /// file was positioned in the middle of a frame with `io::Seek`.
+    pub fn current_frame(&mut self) -> Result<usize> {
+        IndexedTrajectory::current_frame(self)
+    }
+
+    /// Iterate over the remaining frames along with their frame numbers
+    ///
+    /// Numbering starts at `current_frame`, so after `seek_frame(k)` the first
+    /// frame yielded is numbered `k`, unlike with `into_iter().enumerate()`,
+    /// which always counts from 0.
+    pub fn indexed_frames(self) -> Result<IndexedFrames<Self>> {
+        IndexedTrajectory::indexed_frames(self)
+    }
+
+    /// Fold over all frames of the trajectory in parallel
+    ///
+    /// Like `Trajectory::map_reduce`, but the frames are split into contiguous
+    /// chunks that are read on separate threads, each with its own file handle.
+    /// Every chunk is folded with `reduce` starting from `init()`, and the
+    /// chunk results are merged in file order with `combine`. Unlike
+    /// `map_reduce`, this covers every frame in the file regardless of the
+    /// current position, which is left unchanged.
+    #[cfg(feature = "rayon")]
+    pub fn par_map_reduce<T, A>(
+        &mut self,
+        map: impl Fn(&Frame) -> T + Sync,
+        reduce: impl Fn(A, T) -> A + Sync,
+        combine: impl Fn(A, A) -> A,
+        init: impl Fn() -> A + Sync,
+    ) -> Result<A>
+    where
+        A: Send,
+    {
+        IndexedTrajectory::par_map_reduce(self, map, reduce, combine, init)
+    }
+
+    /// Read the next frame with libxdrfile, which only supports `XtcVersion::V1995`
+    ///
+    /// Returns the precision stored in the frame as it is, or `None` if the
+    /// frame is not compressed.
+    fn read_supported(
+        &mut self,
+        frame: &mut Frame,
+        next_num_atoms: Option<usize>,
+    ) -> Result<Option<f32>> {
+        let mut step: c_int = 0;
+
+        let num_atoms = self
+            .get_num_atoms()
+            .map_err(|e| Error::CouldNotCheckNAtoms(Box::new(e)))?;
+        let num_atoms = self.check_variable_atom_count(frame, num_atoms, next_num_atoms)?;
+        if num_atoms != frame.coords.len() {
+            return Err((&*frame, num_atoms).into());
+        }
+
+        let xdrfile = self.handle.xdrfile;
+        let num_atoms = to!(num_atoms, ErrorTask::Read)?;
+        // Left untouched by libxdrfile for uncompressed frames
+        let mut precision: c_float = -1.0;
+        check_os_call(ErrorTask::Read, || unsafe {
+            xdrfile_xtc::read_xtc(
+                xdrfile,
+                num_atoms,
+                &mut step,
+                &mut frame.time,
+                &mut frame.box_vector,
+                frame.coords.as_mut_ptr(),
+                &mut precision,
+            )
+        })?;
+        let stored_precision = Some(precision).filter(|_| num_atoms > 9);
+        self.read_precision = stored_precision.filter(|&p| p > 0.0);
+        frame.step = to!(step, ErrorTask::Read)?;
+        Ok(stored_precision)
+    }
+
+    /// Number of atoms of the next frame, given that of the first and the one
+    /// in the header of the next frame
+    ///
+    /// Fails with `Error::VariableAtomCount` if they differ, unless that is
+    /// allowed, in which case `frame` is resized to the next frame. If the
+    /// header could not be read, `expected` is returned and reading the frame
+    /// reports the error.
+    fn check_variable_atom_count(
+        &mut self,
+        frame: &mut Frame,
+        expected: usize,
+        next_num_atoms: Option<usize>,
+    ) -> Result<usize> {
+        match next_num_atoms {
+            Some(num_atoms) if self.variable_atom_count => {
+                frame.resize(num_atoms);
+                Ok(num_atoms)
+            }
+            Some(num_atoms) if num_atoms != expected => Err(Error::VariableAtomCount {
+                frame: frame_number_at(
+                    self.handle.try_tell()?,
+                    self.next_frame,
+                    self.index.as_ref(),
+                ),
+                expected,
+                got: num_atoms,
+            }),
+            _ => Ok(expected),
+        }
+    }
+
+    /// Fail with `Error::NonStandardFrame` if the header of the next frame
+    /// does not have the number of atoms of the first frame
+    fn check_num_atoms_strict(&mut self, header: Option<&XtcHeader>) -> Result<()> {
+        // Anything that cannot be read is reported by reading the frame
+        let (header, expected) = match (header, self.get_num_atoms()) {
+            (Some(header), Ok(expected)) => (header, expected),
+            _ => return Ok(()),
+        };
+        if header.num_atoms != expected {
+            return Err(Error::NonStandardFrame {
+                step: header.step,
+                reason: format!("{} atoms instead of {}", header.num_atoms, expected),
+            });
+        }
+        Ok(())
+    }
+
+    /// Read the magic number of the frame at `offset`, then return to the
+    /// current position
+    ///
+    /// Returns None if there is no frame at `offset`.
+    fn magic_at(&mut self, offset: u64) -> Result<Option<i32>> {
+        let task = ErrorTask::Read;
+        let start = self.handle.try_tell()?;
+        self.handle.seek_to(offset, task)?;
+        let mut magic = [0];
+        let result = match self.handle.read_ints(&mut magic, task) {
+            Ok(()) => Ok(Some(magic[0])),
+            // Nothing left to read, rather than a failing read
+            Err(e) if e.os_error().is_none() => Ok(None),
+            Err(e) => Err(e),
+        };
+        self.handle.seek_to(start, task)?;
+        result
+    }
+
+    /// The error for the frame at `offset`, if it is of an unsupported revision
+    fn unsupported_version_at(&mut self, offset: u64) -> Option<Error> {
+        let magic = self.magic_at(offset).ok()??;
+        let version = XtcVersion::from_magic(magic)?;
+        if version.is_supported() {
+            None
+        } else {
+            Some(Error::UnsupportedXtcVersion { version })
+        }
+    }
+
+    /// Run `peek` on the file, then rewind to the current position
+    fn rewinding<T>(&mut self, peek: impl FnOnce(&mut XDRFile) -> Result<T>) -> Result<T> {
+        let start = self.handle.try_tell()?;
+        let result = peek(&mut self.handle);
+        self.handle.seek_to(start, ErrorTask::Read)?;
+        result
+    }
+
+    /// Read the header of the next frame, then rewind to its start
+    ///
+    /// Returns None at the end of the file.
+    fn peek_header(&mut self) -> Result<Option<XtcHeader>> {
+        self.rewinding(|handle| Self::parse_header(handle, ErrorTask::Read))
+    }
+
+    /// Read the precision of the next frame, then rewind to its start
+    ///
+    /// Returns None at the end of the file, or if the frame is not compressed.
+    fn peek_precision(&mut self) -> Result<Option<f32>> {
+        let task = ErrorTask::Read;
+        self.rewinding(|handle| match Self::parse_header(handle, task)? {
+            Some(header) => Self::parse_precision(handle, &header, task),
+            None => Ok(None),
+        })
+    }
+
+    /// Read the header of the next step and skip over its coordinates, or
+    /// return None at the end of the file
+    fn read_header_from(handle: &mut XDRFile, task: ErrorTask) -> Result<Option<XtcHeader>> {
+        let header = match Self::parse_header(handle, task)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        // The coordinate block repeats the number of atoms
+        let mut natoms = [0];
+        handle.read_ints(&mut natoms, task)?;
+        if natoms[0] <= 9 {
+            // Small frames are stored uncompressed
+            let natoms: i64 = to!(natoms[0], task)?;
+            handle.skip(natoms * 3 * 4, task)?;
+        } else {
+            // Precision, minint, maxint and smallidx precede the compressed bytes
+            handle.skip((1 + 3 + 3 + 1) * 4, task)?;
+            let mut num_bytes = [0];
+            handle.read_ints(&mut num_bytes, task)?;
+            let num_bytes: i64 = to!(num_bytes[0], task)?;
+            // Opaque data is padded to a multiple of 4 bytes
+            handle.skip((num_bytes + 3) / 4 * 4, task)?;
+        }
+        Ok(Some(header))
+    }
+
+    /// Read the header of the next frame, leaving the file at the start of its
+    /// coordinates, or return None at the end of the file
+    ///
+    /// All other readers of XTC headers go through this one.
+    fn parse_header(handle: &mut XDRFile, task: ErrorTask) -> Result<Option<XtcHeader>> {
+        let mut magic = [0];
+        match handle.read_ints(&mut magic, task) {
+            // Nothing left to read, rather than a failing read
+            Err(e) if e.os_error().is_none() => return Ok(None),
+            result => result?,
+        }
+        match XtcVersion::from_magic(magic[0]) {
+            Some(XtcVersion::V1995) => {}
+            Some(version) => return Err(Error::UnsupportedXtcVersion { version }),
+            None => return Err((ErrorCode::ExdrMagic, task).into()),
+        }
+        let mut natoms_step = [0; 2];
+        handle.read_ints(&mut natoms_step, task)?;
+        let mut time = [0.0];
+        handle.read_floats(&mut time, task)?;
+        let mut box_vector = [[0.0; 3]; 3];
+        handle.read_floats(box_vector.as_flattened_mut(), task)?;
+        Ok(Some(XtcHeader {
+            num_atoms: to!(natoms_step[0], task)?,
+            step: to!(natoms_step[1], task)?,
+            time: time[0],
+            box_vector,
+        }))
+    }
+
+    /// Read the precision at the start of the coordinates following `header`,
+    /// or return None if they are not compressed
+    fn parse_precision(
+        handle: &mut XDRFile,
+        header: &XtcHeader,
+        task: ErrorTask,
+    ) -> Result<Option<f32>> {
+        if header.num_atoms <= 9 {
+            return Ok(None);
+        }
+        // The coordinate block repeats the number of atoms
+        handle.skip(4, task)?;
+        let mut precision = [0.0];
+        handle.read_floats(&mut precision, task)?;
+        Ok(Some(precision[0]))
+    }
+}
+
+/// Largest magnitude of a coordinate scaled by the precision, and of the
+/// spread of scaled coordinates along an axis, accepted by the XTC compression
+const MAX_SCALED_COORDINATE: f64 = (i32::MAX - 2) as f64;
+
+/// Check that libxdrfile can compress the coordinates with the precision
+///
+/// libxdrfile only prints a warning when the scaled coordinates overflow, and
+/// then writes a corrupt frame, so this mirrors its checks before writing.
+fn check_compressible(coords: &[[f32; 3]], precision: f32) -> Result<()> {
+    let out_of_range = |atom: usize, dim: usize| Error::CoordinateOutOfRange {
+        atom,
+        value: coords[atom][dim],
+        precision,
+    };
+    let precision_f64 = f64::from(precision);
+    for dim in 0..3 {
+        // Atoms with the smallest and largest scaled coordinate
+        let mut min = (0, f64::INFINITY);
+        let mut max = (0, f64::NEG_INFINITY);
+        for (atom, coord) in coords.iter().enumerate() {
+            let scaled = f64::from(coord[dim]) * precision_f64;
+            if !scaled.is_finite() || scaled.abs() + 0.5 > MAX_SCALED_COORDINATE {
+                return Err(out_of_range(atom, dim));
+            }
+            if scaled < min.1 {
+                min = (atom, scaled);
+            }
+            if scaled > max.1 {
+                max = (atom, scaled);
+            }
+        }
+        if max.1 - min.1 >= MAX_SCALED_COORDINATE {
+            let atom = if max.1.abs() >= min.1.abs() {
+                max.0
+            } else {
+                min.0
+            };
+            return Err(out_of_range(atom, dim));
+        }
+    }
+    Ok(())
+}
+
+impl IndexedTrajectory for XTCTrajectory {
+    fn skip_frame(handle: &mut XDRFile) -> Result<Option<(usize, f32)>> {
+        let header = Self::read_header_from(handle, ErrorTask::BuildIndex)?;
+        Ok(header.map(|header| (header.step, header.time)))
+    }
+
+    #[cfg(feature = "rayon")]
+    fn reopen(path: &Path) -> Result<Self> {
+        XTCTrajectory::open_read(path)
+    }
+
+    fn handle_and_index(&mut self) -> (&mut XDRFile, &mut Option<TrajectoryIndex>) {
+        (&mut self.handle, &mut self.index)
+    }
+}
+
+impl io::Seek for XTCTrajectory {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.handle.seek(pos)
+    }
+}
+
+bitflags! {
+    /// The data blocks stored in a TRR frame
+    ///
+    /// Each TRR frame may independently contain coordinates, velocities,
+    /// forces and a box. The lambda value is always stored in the frame
+    /// header; without `LAMBDA` it is written as zero.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct TrrBlocks: u8 {
+        /// Coordinates, from `Frame::coords`
+        const X = 1;
+        /// Velocities, from `Frame::velocities`
+        const V = 1 << 1;
+        /// Forces, from `Frame::forces`
+        const F = 1 << 2;
+        /// Box vectors, from `Frame::box_vector`
+        const BOX = 1 << 3;
+        /// Free energy coupling parameter, from `Frame::lambda`
+        const LAMBDA = 1 << 4;
+    }
+}
+
+impl Default for TrrBlocks {
+    /// Coordinates and box
+    fn default() -> Self {
+        TrrBlocks::X | TrrBlocks::BOX
+    }
+}
+
+/// Header of a TRR frame read with `TRRTrajectory::read_trr_into`
+///
+/// The box is included here as it is too small to need a caller buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrrHeader {
+    pub num_atoms: usize,
+    pub step: usize,
+    pub time: f32,
+    pub lambda: f32,
+    /// Box vectors, if the frame has a box
+    pub box_vector: Option<[[f32; 3]; 3]>,
+    /// The blocks present in the frame; `LAMBDA` is never set, as lambda is
+    /// always part of the header
+    pub blocks: TrrBlocks,
+    /// True if the frame is stored in double precision
+    pub double: bool,
+}
+
+/// Handle to Read/Write TRR Trajectories
+///
+/// Frames are read and written by libxdrfile, which always assumes standard
+/// big-endian XDR, see `ByteOrder`.
+pub struct TRRTrajectory {
+    handle: XDRFile,
+    num_atoms: Lazy<Result<usize>>,
+    blocks: TrrBlocks,
+    index: Option<TrajectoryIndex>,
+    strict: bool,
+    /// End position and time of the last frame read in strict mode
+    last_read: Option<(u64, f32)>,
+    /// End position of the last frame read and the number of the frame there
+    next_frame: Option<(u64, usize)>,
+    /// True if frames may have a different number of atoms than the first
+    variable_atom_count: bool,
+}
+
+impl TRRTrajectory {
+    /// Open a file in the given mode
+    ///
+    /// This only opens the file. Nothing is read from it until the first
+    /// frame or the number of atoms is requested, so opening a file that is
+    /// not a valid trajectory succeeds and the error is reported by the first
+    /// read.
+    pub fn open(path: impl AsRef<Path>, filemode: FileMode) -> Result<TRRTrajectory> {
+        let xdr = XDRFile::open(path, filemode)?;
+        Ok(TRRTrajectory {
+            handle: xdr,
+            num_atoms: Lazy::new(),
+            blocks: TrrBlocks::default(),
+            index: None,
+            strict: false,
+            variable_atom_count: false,
+            last_read: None,
+            next_frame: None,
+        })
+    }
+
+    /// Open a file in read mode
+    pub fn open_read(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(path, FileMode::Read)
+    }
+
+    /// Open a file in append mode
+    pub fn open_append(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(path, FileMode::Append)
+    }
+
+    /// Open a file in write mode
+    pub fn open_write(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(path, FileMode::Write)
+    }
+}
+
+impl TryFrom<&Path> for TRRTrajectory {
+    type Error = Error;
+
+    /// Open a file in read mode
+    fn try_from(path: &Path) -> Result<Self> {
+        Self::open_read(path)
+    }
+}
+
+impl TryFrom<PathBuf> for TRRTrajectory {
+    type Error = Error;
+
+    /// Open a file in read mode
+    fn try_from(path: PathBuf) -> Result<Self> {
+        Self::open_read(path)
+    }
+}
+
+impl Trajectory for TRRTrajectory {
+    /// Read the next step of the trajectory into the frame object
+    ///
+    /// Only the blocks present in the file are filled in. Coordinates are
+    /// cleared if the step has none, and velocities and forces are set to
+    /// `None` if absent, or resized to the number of atoms if present. The
+    /// box is zeroed if the step has none. If the step has coordinates, the
+    /// frame must already hold the right number of atoms.
+    fn read(&mut self, frame: &mut Frame) -> Result<()> {
+        let start = self.handle.try_tell()?;
+        retry_interrupted(
+            self,
+            |trr| trr.read_at(frame, start),
+            |trr| trr.handle.seek_to(start, ErrorTask::Read),
+        )?;
+        let end = self.handle.try_tell()?;
+        self.next_frame =
+            frame_number_at(start, self.next_frame, self.index.as_ref()).map(|k| (end, k + 1));
+        Ok(())
+    }
+
+    /// Write the frame to the trajectory file
+    ///
+    /// Only the blocks selected with `set_blocks` are written, coordinates and
+    /// box by default. All written per-atom blocks must have the same length.
+    fn write(&mut self, frame: &Frame) -> Result<()> {
+        if self.strict {
+            check_box_strict(frame)?;
+        }
+        let blocks = self.blocks;
+        let x = if blocks.contains(TrrBlocks::X) {
+            Some(&frame.coords[..])
+        } else {
+            None
+        };
+        let v = if blocks.contains(TrrBlocks::V) {
+            let v = frame.velocities.as_deref();
+            Some(v.ok_or(Error::MissingData { name: "velocities" })?)
+        } else {
+            None
+        };
+        let f = if blocks.contains(TrrBlocks::F) {
+            let f = frame.forces.as_deref();
+            Some(f.ok_or(Error::MissingData { name: "forces" })?)
+        } else {
+            None
+        };
+        let len = x.or(v).or(f).map_or(frame.len(), <[_]>::len);
+        for (name, values) in [("coordinates", x), ("velocities", v), ("forces", f)] {
+            if let Some(values) = values {
+                if values.len() != len {
+                    return Err(Error::WrongLength {
+                        name,
+                        expected: len,
+                        found: values.len(),
+                    });
+                }
+            }
+        }
+        let as_ptr = |values: Option<&[[f32; 3]]>| values.map_or(std::ptr::null(), <[_]>::as_ptr);
+        let box_vector = if blocks.contains(TrrBlocks::BOX) {
+            &frame.box_vector
+        } else {
+            std::ptr::null()
+        };
+        let lambda = if blocks.contains(TrrBlocks::LAMBDA) {
+            frame.lambda
+        } else {
+            0.0
+        };
+
+        let num_atoms = to!(len, ErrorTask::Write)?;
+        let step = to!(frame.step, ErrorTask::Write)?;
+        let xdrfile = self.handle.xdrfile;
+        check_os_call(ErrorTask::Write, || unsafe {
+            xdrfile_trr::write_trr(
+                xdrfile,
+                num_atoms,
+                step,
+                frame.time,
+                lambda,
+                box_vector,
+                as_ptr(x),
+                as_ptr(v),
+                as_ptr(f),
+            )
+        })?;
+        self.index = None;
+        self.next_frame = None;
+        self.handle.dirty = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.handle.flush()
+    }
+
+    /// Get the number of atoms in the trajectory
+    ///
+    /// The number is first queried from the file at the trajectory's path
+    /// with libxdrfile's `read_trr_natoms`. If that fails or reports zero
+    /// atoms, the header of the first frame is read through the open handle
+    /// instead, and the position in the file restored. If both fail, the error
+    /// of the query is returned. Either way the result is cached.
+    fn get_num_atoms(&mut self) -> Result<usize> {
+        let handle = &mut self.handle;
+        self.num_atoms
+            .get_or_create(|| {
+                let mut num_atoms: c_int = 0;
+                let queried = unsafe {
+                    let path = path_to_cstring(&handle.path)?;
+                    let path_p = path.into_raw();
+                    let code = xdrfile_trr::read_trr_natoms(path_p, &mut num_atoms);
+                    // Reconstitute the CString so it is deallocated correctly
+                    let _ = CString::from_raw(path_p);
+
+                    if let Some(err) = check_code(code, ErrorTask::ReadNumAtoms) {
+                        Err(err)
+                    } else {
+                        checked_num_atoms(num_atoms, &handle.path)
+                    }
+                };
+                queried.or_else(|err| Self::num_atoms_from_header(handle).map_err(|_| err))
+            })
+            .clone()
+    }
+
+    fn read_atom_range(&mut self, frame: &mut Frame, start: usize, end: usize) -> Result<()> {
+        let task = ErrorTask::Read;
+        let frame_start = self.handle.try_tell()?;
+        let header = self.read_header()?;
+        let num_atoms = to!(header.natoms, task)?;
+        if start > end || end > num_atoms {
+            self.handle.seek_to(frame_start, task)?;
+            return Err(Error::InvalidAtomRange {
+                start,
+                end,
+                num_atoms,
+            });
+        }
+        if frame.len() != end - start {
+            self.handle.seek_to(frame_start, task)?;
+            return Err((&*frame, end - start).into());
+        }
+        if header.x_size == 0 {
+            self.handle.seek_to(frame_start, task)?;
+            return Err(Error::MissingData {
+                name: "coordinates",
+            });
+        }
+
+        let double = header.bDouble != 0;
+        let float_size: i64 = if double { 8 } else { 4 };
+        let frame_end = trr_data_size(&header);
+        let mut pos = 0;
+
+        if header.box_size != 0 {
+            self.handle
+                .read_rvecs(&mut frame.box_vector, double, task)?;
+            pos += i64::from(header.box_size);
+        } else {
+            frame.box_vector = [[0.0; 3]; 3];
+        }
+        let x_start = i64::from(header.box_size + header.vir_size + header.pres_size);
+        let skipped_atoms: i64 = to!(start, task)?;
+        let range_len: i64 = to!(frame.len(), task)?;
+        let range_start = x_start + skipped_atoms * 3 * float_size;
+        self.handle.skip(range_start - pos, task)?;
+        self.handle.read_rvecs(&mut frame.coords, double, task)?;
+        pos = range_start + range_len * 3 * float_size;
+        self.handle.skip(frame_end - pos, task)?;
+
+        frame.step = to!(header.step, task)?;
+        frame.time = header.tf;
+        Ok(())
+    }
+
+    fn peek_step(&mut self) -> Result<Option<usize>> {
+        Ok(self.peek_header()?.map(|(step, _)| step))
+    }
+
+    fn peek_time(&mut self) -> Result<Option<f32>> {
+        Ok(self.peek_header()?.map(|(_, time)| time))
+    }
+
+    fn at_eof(&mut self) -> Result<bool> {
+        self.handle.at_eof()
+    }
+
+    fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+        self.last_read = None;
+    }
+
+    fn set_variable_atom_count(&mut self, allow: bool) {
+        self.variable_atom_count = allow;
+    }
+
+    fn len(&self) -> Option<usize> {
+        self.index.as_ref().map(|index| index.len())
+    }
+
+    fn num_frames(&mut self) -> Result<usize> {
+        Ok(self.cached_index()?.len())
+    }
+
+    fn seek_by_time(&mut self, time: f32) -> Result<()> {
+        let k = first_at_time(self.cached_index()?, time)?;
+        self.seek_frame(k)
+    }
+
+    fn seek_time(&mut self, time: f32) -> Result<()> {
+        let k = nearest_time(self.cached_index()?, time)?;
+        self.seek_frame(k)
+    }
+
+    fn read_nth(&mut self, n: usize) -> Result<Frame> {
+        let mut frame = Frame::with_len(self.get_num_atoms()?);
+        self.read_frame_at(n, &mut frame)?;
+        Ok(frame)
+    }
+
+    fn read_frame_at(&mut self, index: usize, frame: &mut Frame) -> Result<()> {
+        self.seek_frame(index)?;
+        self.read(frame)
+    }
+
+    fn estimate_write_size(&self, num_frames: usize, frame: &Frame) -> Result<u64> {
+        let blocks = self.blocks;
+        let vector_blocks = [TrrBlocks::X, TrrBlocks::V, TrrBlocks::F];
+        let num_vectors = vector_blocks
+            .iter()
+            .filter(|&&b| blocks.contains(b))
+            .count();
+        let mut bytes = TRR_HEADER_BYTES + 3 * 4 * frame.len() as u64 * num_vectors as u64;
+        if blocks.contains(TrrBlocks::BOX) {
+            bytes += 9 * 4;
+        }
+        Ok(bytes * num_frames as u64)
+    }
+
+    fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    fn write_settings(&mut self) -> Result<WriteSettings> {
+        let blocks = match self.peek_blocks()? {
+            Some(blocks) => blocks | TrrBlocks::LAMBDA,
+            None => self.blocks,
+        };
+        Ok(WriteSettings {
+            precision: None,
+            blocks: Some(blocks),
+        })
+    }
+
+    fn apply_write_settings(&mut self, settings: WriteSettings) {
+        if let Some(blocks) = settings.blocks {
+            self.set_blocks(blocks);
+        }
+    }
+
+    #[cfg(feature = "sha2")]
+    fn digest(&mut self) -> Result<[u8; 32]> {
+        self.handle.digest()
+    }
+}
+
+impl TRRTrajectory {
+    /// Get the current position in the file
+    ///
+    /// # Panics
+    /// Panics if the position cannot be determined. See `try_tell`.
+    pub fn tell(&self) -> u64 {
+        self.handle.tell()
+    }
+
+    /// Get the current position in the file, or an error if it cannot be determined
+    pub fn try_tell(&self) -> Result<u64> {
+        self.handle.try_tell()
+    }
+
+    /// Flush any buffered writes and close the file
+    ///
+    /// Dropping the trajectory also flushes and closes it, but cannot report
+    /// a failure, beyond a warning in debug builds. Closing explicitly is
+    /// preferred after writing, as it returns the error.
+    pub fn close(mut self) -> Result<()> {
+        self.handle.close()
+    }
+
+    /// The data blocks written to each frame
+    pub fn blocks(&self) -> TrrBlocks {
+        self.blocks
+    }
+
+    /// Select the data blocks written to each subsequent frame
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    /// # use tempfile::NamedTempFile;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let tempfile = NamedTempFile::new().unwrap();
+    /// # let path = tempfile.path();
+    /// let mut trj = TRRTrajectory::open_write(path)?;
+    /// trj.set_blocks(TrrBlocks::V | TrrBlocks::BOX);
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.velocities = Some(vec![[0.1, 0.2, 0.3]]);
+    /// trj.write(&frame)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_blocks(&mut self, blocks: TrrBlocks) {
+        self.blocks = blocks;
+    }
+
+    /// Read the next step into separate coordinate, velocity and force buffers
+    ///
+    /// This avoids `Frame` entirely, for processing with externally managed
+    /// memory. Every buffer must hold exactly the number of atoms of the step.
+    /// Passing `None` for `v` or `f` skips that block without decoding it. A
+    /// buffer is left untouched if the step has no such block, which can be
+    /// checked with the `blocks` of the returned header. If a buffer has the
+    /// wrong length, nothing is read.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+    /// let mut x = vec![[0.0; 3]; trj.get_num_atoms()?];
+    /// let header = trj.read_trr_into(&mut x, None, None)?;
+    /// assert_eq!(header.step, 1);
+    /// assert!(header.blocks.contains(TrrBlocks::X));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_trr_into(
+        &mut self,
+        x: &mut [[f32; 3]],
+        v: Option<&mut [[f32; 3]]>,
+        f: Option<&mut [[f32; 3]]>,
+    ) -> Result<TrrHeader> {
+        self.read_blocks_into(Some(x), v, f)
+    }
+
+    /// Read only the velocities of the next step into `out`, or return None
+    /// at the end of the file
+    ///
+    /// TRR stores the size of every block in the frame header, so the
+    /// coordinates and forces are skipped without being decoded. `out` must
+    /// hold exactly the number of atoms of the step, or nothing is read. As
+    /// with `read_trr_into`, `out` is left untouched if the step has no
+    /// velocities, which can be checked with the `blocks` of the returned
+    /// header.
+    ///
+    /// ```rust
+    /// use xdrfile::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let mut trj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+    /// let mut v = vec![[0.0; 3]; trj.get_num_atoms()?];
+    /// while let Some(header) = trj.read_velocities(&mut v)? {
+    ///     assert_eq!(header.num_atoms, v.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_velocities(&mut self, out: &mut [[f32; 3]]) -> Result<Option<TrrHeader>> {
+        match self.read_blocks_into(None, Some(out), None) {
+            Ok(header) => Ok(Some(header)),
+            Err(e) if e.is_eof() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read the next step into the buffers given, skipping the other blocks,
+    /// for `read_trr_into` and `read_velocities`
+    fn read_blocks_into(
+        &mut self,
+        x: Option<&mut [[f32; 3]]>,
+        v: Option<&mut [[f32; 3]]>,
+        f: Option<&mut [[f32; 3]]>,
+    ) -> Result<TrrHeader> {
+        let task = ErrorTask::Read;
+        let start = self.handle.try_tell()?;
+        let header = self.read_header()?;
+        let num_atoms = to!(header.natoms, task)?;
+        let lengths = [
+            ("coordinates", x.as_ref().map(|x| x.len())),
+            ("velocities", v.as_ref().map(|v| v.len())),
+            ("forces", f.as_ref().map(|f| f.len())),
+        ];
+        for (name, len) in lengths {
+            match len {
+                Some(found) if found != num_atoms => {
+                    self.handle.seek_to(start, task)?;
+                    return Err(Error::WrongLength {
+                        name,
+                        expected: num_atoms,
+                        found,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let double = header.bDouble != 0;
+        let mut blocks = TrrBlocks::empty();
+        let box_vector = if header.box_size != 0 {
+            blocks |= TrrBlocks::BOX;
+            let mut box_vector = [[0.0; 3]; 3];
+            self.handle.read_rvecs(&mut box_vector, double, task)?;
+            Some(box_vector)
+        } else {
+            None
+        };
+        self.handle.skip(
+            i64::from(header.vir_size) + i64::from(header.pres_size),
+            task,
+        )?;
+        for (block, size, values) in [
+            (TrrBlocks::X, header.x_size, x),
+            (TrrBlocks::V, header.v_size, v),
+            (TrrBlocks::F, header.f_size, f),
+        ] {
+            if size == 0 {
+                continue;
+            }
+            blocks |= block;
+            match values {
+                Some(values) => self.handle.read_rvecs(values, double, task)?,
+                None => self.handle.skip(i64::from(size), task)?,
+            }
+        }
+
+        Ok(TrrHeader {
+            num_atoms,
+            step: to!(header.step, task)?,
+            time: header.tf,
+            lambda: header.lambdaf,
+            box_vector,
+            blocks,
+            double,
+        })
+    }
+
+    /// Scan the file for the offset, step and time of every frame
+    ///
+    /// Only the frame headers are read; the data blocks are skipped. The
+    /// position in the file is unchanged afterwards. The index is also kept
+    /// for `seek_frame` and `Trajectory::len`.
+    pub fn build_index(&mut self) -> Result<TrajectoryIndex> {
+        IndexedTrajectory::build_index(self)
+    }
+
+    /// Use an index built earlier instead of scanning the file
+    ///
+    /// An index from `build_index` can be stored alongside a large
+    /// trajectory, for example by saving its `TrajectoryIndex::entries`, and
+    /// handed back when the file is opened again, so that `seek_frame` and
+    /// the other random access methods do not have to scan it. The index is
+    /// trusted as it is: it must have been built from the same file, or
+    /// seeks end up at the wrong positions. Writing to the trajectory
+    /// discards it, as for a built index.
+    pub fn set_index(&mut self, index: TrajectoryIndex) {
+        self.index = Some(index);
+    }
+
+    /// Position the trajectory at the start of frame `k`, so that the next
+    /// read returns it
+    ///
+    /// The first call scans all frame headers to build an index (see
+    /// `build_index`), which is cached for later seeks. Returns
+    /// `Error::FrameOutOfRange` if the trajectory has `k` frames or fewer.
+    pub fn seek_frame(&mut self, k: usize) -> Result<()> {
+        IndexedTrajectory::seek_frame(self, k)
+    }
+
+    /// Number of the frame that the next read will return, counting from 0
+    ///
+    /// At the start of the file this is always 0; elsewhere, it is looked up
+    /// in the cached index, which is built if necessary. After the last frame
+    /// it is the number of frames. Returns `Error::NotAtFrameStart` if the
+    /// file was positioned in the middle of a frame with `io::Seek`.
+    pub fn current_frame(&mut self) -> Result<usize> {
+        IndexedTrajectory::current_frame(self)
+    }
+
+    /// Iterate over the remaining frames along with their frame numbers
+    ///
+    /// Numbering starts at `current_frame`, so after `seek_frame(k)` the first
+    /// frame yielded is numbered `k`, unlike with `into_iter().enumerate()`,
+    /// which always counts from 0.
+    pub fn indexed_frames(self) -> Result<IndexedFrames<Self>> {
+        IndexedTrajectory::indexed_frames(self)
+    }
+
+    /// Fold over all frames of the trajectory in parallel
+    ///
+    /// Like `Trajectory::map_reduce`, but the frames are split into contiguous
+    /// chunks that are read on separate threads, each with its own file handle.
+    /// Every chunk is folded with `reduce` starting from `init()`, and the
+    /// chunk results are merged in file order with `combine`. Unlike
+    /// `map_reduce`, this covers every frame in the file regardless of the
+    /// current position, which is left unchanged.
+    #[cfg(feature = "rayon")]
+    pub fn par_map_reduce<T, A>(
+        &mut self,
+        map: impl Fn(&Frame) -> T + Sync,
+        reduce: impl Fn(A, T) -> A + Sync,
+        combine: impl Fn(A, A) -> A,
+        init: impl Fn() -> A + Sync,
+    ) -> Result<A>
+    where
+        A: Send,
+    {
+        IndexedTrajectory::par_map_reduce(self, map, reduce, combine, init)
+    }
+
+    /// Read the step and time of the next frame, then rewind to its start
+    ///
+    /// Returns None at the end of the file.
+    fn peek_header(&mut self) -> Result<Option<(usize, f32)>> {
+        let task = ErrorTask::Read;
+        let start = self.handle.try_tell()?;
+        let result = match Self::read_header_from(&mut self.handle, task) {
+            Ok(header) => to!(header.step, task).map(|step| Some((step, header.tf))),
+            Err(e) if e.is_eof() => Ok(None),
+            Err(e) => Err(e),
+        };
+        self.handle.seek_to(start, task)?;
+        result
+    }
+
+    /// Read the blocks present in the next frame, then rewind to its start
+    ///
+    /// Returns None at the end of the file or if the file cannot be read.
+    fn peek_blocks(&mut self) -> Result<Option<TrrBlocks>> {
+        let task = ErrorTask::Read;
+        let start = self.handle.try_tell()?;
+        let header = Self::read_header_from(&mut self.handle, task);
+        self.handle.seek_to(start, task)?;
+        Ok(header.ok().map(|header| {
+            let mut blocks = TrrBlocks::empty();
+            blocks.set(TrrBlocks::X, header.x_size != 0);
+            blocks.set(TrrBlocks::V, header.v_size != 0);
+            blocks.set(TrrBlocks::F, header.f_size != 0);
+            blocks.set(TrrBlocks::BOX, header.box_size != 0);
+            blocks
+        }))
+    }
+
+    /// Read the frame starting at `start`, the current position, for `read`
+    fn read_at(&mut self, frame: &mut Frame, start: u64) -> Result<()> {
+        let task = ErrorTask::Read;
+        let header = self.read_header()?;
+        let num_atoms = to!(header.natoms, task)?;
+        if self.strict {
+            if let Ok(expected) = self.get_num_atoms() {
+                if num_atoms != expected {
+                    self.handle.seek_to(start, task)?;
+                    return Err(Error::NonStandardFrame {
+                        step: to!(header.step, task)?,
+                        reason: format!("{} atoms instead of {}", num_atoms, expected),
+                    });
+                }
+            }
+        }
+        if self.variable_atom_count {
+            frame.resize(num_atoms);
+        } else if let Ok(expected) = self.get_num_atoms() {
+            if num_atoms != expected {
+                self.handle.seek_to(start, task)?;
+                return Err(Error::VariableAtomCount {
+                    frame: frame_number_at(start, self.next_frame, self.index.as_ref()),
+                    expected,
+                    got: num_atoms,
+                });
+            }
+        }
+        if header.x_size != 0 && frame.coords.len() != num_atoms {
+            self.handle.seek_to(start, task)?;
+            return Err((&*frame, num_atoms).into());
+        }
+
+        let double = header.bDouble != 0;
+        if header.box_size != 0 {
+            self.handle
+                .read_rvecs(&mut frame.box_vector, double, task)?;
+        } else {
+            frame.box_vector = [[0.0; 3]; 3];
+        }
+        self.handle.skip(
+            i64::from(header.vir_size) + i64::from(header.pres_size),
+            task,
+        )?;
+        if header.x_size != 0 {
+            self.handle.read_rvecs(&mut frame.coords, double, task)?;
+        } else {
+            frame.coords.clear();
+        }
+        for (size, values) in [
+            (header.v_size, &mut frame.velocities),
+            (header.f_size, &mut frame.forces),
+        ] {
+            if size != 0 {
+                let values = values.get_or_insert_with(Vec::new);
+                values.resize(num_atoms, [0.0; 3]);
+                self.handle.read_rvecs(values, double, task)?;
+            } else {
+                *values = None;
+            }
+        }
+
+        frame.step = to!(header.step, task)?;
+        frame.time = header.tf;
+        frame.lambda = header.lambdaf;
+
+        if self.strict {
+            let previous = self.last_read.filter(|&(end, _)| end == start);
+            let mut reason = check_frame_strict(frame, previous.map(|(_, time)| time));
+            if !frame.lambda.is_finite() {
+                reason = reason.or(Some(format!("lambda {} is not finite", frame.lambda)));
+            }
+            if let Some(reason) = reason {
+                self.handle.seek_to(start, task)?;
+                return Err(Error::NonStandardFrame {
+                    step: frame.step,
+                    reason,
+                });
+            }
+            self.last_read = Some((self.handle.try_tell()?, frame.time));
+        }
+        Ok(())
+    }
+
+    /// Read the number of atoms from the header of the first frame, restoring
+    /// the position in the file afterwards
+    fn num_atoms_from_header(handle: &mut XDRFile) -> Result<usize> {
+        let task = ErrorTask::ReadNumAtoms;
+        let start = handle.try_tell()?;
+        handle.seek_to(0, task)?;
+        let header = Self::read_header_from(handle, task);
+        handle.seek_to(start, task)?;
+        checked_num_atoms(header?.natoms, &handle.path)
+    }
+
+    /// Read the header of the next step, leaving the file positioned after it
+    fn read_header(&mut self) -> Result<xdrfile_trr::t_trnheader> {
+        Self::read_header_from(&mut self.handle, ErrorTask::Read)
+    }
+
+    fn read_header_from(handle: &mut XDRFile, task: ErrorTask) -> Result<xdrfile_trr::t_trnheader> {
+        let mut header = xdrfile_trr::t_trnheader::default();
+        unsafe {
+            let code = xdrfile_trr::do_trnheader(handle.xdrfile, 1, &mut header);
+            if let Some(err) = check_code(code, task) {
+                return Err(err);
+            }
+        }
+        Ok(header)
+    }
+}
+
+/// Number of bytes in the header of a single precision TRR frame
+///
+/// The magic number, the version string with its length padded to whole
+/// words, ten block sizes and the atom count, step, number of energies, time
+/// and lambda.
+const TRR_HEADER_BYTES: u64 = 4 + 4 + 16 + 11 * 4 + 2 * 4 + 2 * 4;
+
+/// Number of bytes of data following a TRR header
+fn trr_data_size(header: &xdrfile_trr::t_trnheader) -> i64 {
+    i64::from(header.box_size)
+        + i64::from(header.vir_size)
+        + i64::from(header.pres_size)
+        + i64::from(header.x_size)
+        + i64::from(header.v_size)
+        + i64::from(header.f_size)
+}
+
+impl IndexedTrajectory for TRRTrajectory {
+    fn skip_frame(handle: &mut XDRFile) -> Result<Option<(usize, f32)>> {
+        let task = ErrorTask::BuildIndex;
+        let header = match Self::read_header_from(handle, task) {
+            Ok(header) => header,
+            Err(e) if e.is_eof() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        handle.skip(trr_data_size(&header), task)?;
+        Ok(Some((to!(header.step, task)?, header.tf)))
+    }
+
+    #[cfg(feature = "rayon")]
+    fn reopen(path: &Path) -> Result<Self> {
+        TRRTrajectory::open_read(path)
+    }
+
+    fn handle_and_index(&mut self) -> (&mut XDRFile, &mut Option<TrajectoryIndex>) {
+        (&mut self.handle, &mut self.index)
+    }
+}
+
+impl io::Seek for TRRTrajectory {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.handle.seek(pos)
+    }
+}
+
+#[cfg(test)]
+// The original tests predate these lints
+#[allow(
+    clippy::assertions_on_constants,
+    clippy::needless_borrows_for_generic_args,
+    clippy::nonminimal_bool,
+    clippy::single_match,
+    clippy::useless_vec
+)]
+mod tests {
+
+    use super::*;
+    use std::io::Read;
+    use std::io::Seek;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_read_write_xtc() -> Result<()> {
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let tmp_path = tempfile.path();
+
+        let natoms = 2;
+        let frame = Frame {
+            step: 5,
+            time: 2.0,
+            box_vector: [[1.0, 2.0, 3.0], [2.0, 1.0, 3.0], [3.0, 2.0, 1.0]],
+            coords: vec![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
+            ..Default::default()
+        };
+        let mut f = XTCTrajectory::open_write(&tmp_path)?;
+        let write_status = f.write(&frame);
+        match write_status {
+            Err(_) => panic!("Failed"),
+            Ok(()) => {}
+        }
+        f.flush()?;
+
+        let mut new_frame = Frame::with_len(natoms);
+        let mut f = XTCTrajectory::open_read(tmp_path)?;
+        let num_atoms = f.get_num_atoms()?;
+        assert_eq!(num_atoms, natoms);
+
+        let read_status = f.read(&mut new_frame);
+        match read_status {
+            Err(e) => assert!(false, "{:?}", e),
+            Ok(()) => {}
+        }
+
+        assert_eq!(new_frame.len(), frame.len());
+        assert_eq!(new_frame.step, frame.step);
+        assert_approx_eq!(new_frame.time, frame.time);
+        assert_eq!(new_frame.box_vector, frame.box_vector);
+        assert_eq!(new_frame.coords, frame.coords);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_write_trr() -> Result<()> {
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let tmp_path = tempfile.path();
+
+        let natoms = 2;
+        let frame = Frame {
+            step: 5,
+            time: 2.0,
+            box_vector: [[1.0, 2.0, 3.0], [2.0, 1.0, 3.0], [3.0, 2.0, 1.0]],
+            coords: vec![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
+            ..Default::default()
+        };
+        let mut f = TRRTrajectory::open_write(tmp_path)?;
+        let write_status = f.write(&frame);
+        match write_status {
+            Err(_) => panic!("Failed"),
+            Ok(()) => {}
+        }
+        f.flush()?;
+
+        let mut new_frame = Frame::with_len(natoms);
+        let mut f = TRRTrajectory::open_read(tmp_path)?;
+        let num_atoms = f.get_num_atoms()?;
+        assert_eq!(num_atoms, natoms);
+
+        let read_status = f.read(&mut new_frame);
+        match read_status {
+            Err(e) => assert!(false, "{:?}", e),
+            Ok(()) => {}
+        }
+
+        assert_eq!(new_frame.len(), frame.len());
+        assert_eq!(new_frame.step, frame.step);
+        assert_eq!(new_frame.time, frame.time);
+        assert_eq!(new_frame.box_vector, frame.box_vector);
+        assert_eq!(new_frame.coords, frame.coords);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trr_blocks() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let tmp_path = tempfile.path();
+
+        let velocities = vec![[0.1, 0.2, 0.3], [-0.1, -0.2, -0.3]];
+        let forces = vec![[10.0, 20.0, 30.0], [-10.0, -20.0, -30.0]];
+        let frame = Frame {
+            step: 5,
+            time: 2.0,
+            box_vector: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            coords: vec![[1.0, 1.0, 1.0], [2.0, 2.0, 2.0]],
+            velocities: Some(velocities.clone()),
+            forces: Some(forces.clone()),
+            lambda: 0.25,
+            ..Default::default()
+        };
+        let mut f = TRRTrajectory::open_write(tmp_path)?;
+        assert_eq!(f.blocks(), TrrBlocks::X | TrrBlocks::BOX);
+        f.write(&frame)?;
+        f.set_blocks(TrrBlocks::V);
+        f.write(&frame)?;
+        f.set_blocks(TrrBlocks::all());
+        f.write(&frame)?;
+        f.flush()?;
+
+        let mut f = TRRTrajectory::open_read(tmp_path)?;
+        let mut new_frame = Frame::with_len(2);
+        f.read(&mut new_frame)?;
+        assert_eq!(new_frame.coords, frame.coords);
+        assert_eq!(new_frame.box_vector, frame.box_vector);
+        assert_eq!(new_frame.velocities, None);
+        assert_eq!(new_frame.forces, None);
+        assert_eq!(new_frame.lambda, 0.0);
+
+        // A velocity-only frame has no coordinates
+        f.read(&mut new_frame)?;
+        assert!(new_frame.coords.is_empty());
+        assert_eq!(new_frame.box_vector, [[0.0; 3]; 3]);
+        assert_eq!(new_frame.velocities.as_ref(), Some(&velocities));
+        assert_eq!(new_frame.forces, None);
+        assert_eq!(new_frame.step, 5);
+
+        new_frame.resize(2);
+        f.read(&mut new_frame)?;
+        assert_eq!(new_frame.coords, frame.coords);
+        assert_eq!(new_frame.velocities.as_ref(), Some(&velocities));
+        assert_eq!(new_frame.forces.as_ref(), Some(&forces));
+        assert_eq!(new_frame.lambda, 0.25);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_velocities() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_blocks(TrrBlocks::X | TrrBlocks::V | TrrBlocks::F);
+        for step in 0..3 {
+            let v = step as f32;
+            let frame = Frame {
+                step,
+                coords: vec![[1.0, 2.0, 3.0]; 3],
+                velocities: Some(vec![[v, -v, 0.5 * v]; 3]),
+                forces: Some(vec![[9.0; 3]; 3]),
+                ..Default::default()
+            };
+            trr.write(&frame)?;
+        }
+        trr.close()?;
+
+        let mut trr = TRRTrajectory::open_read(tempfile.path())?;
+        let mut v = vec![[0.0; 3]; 3];
+        let mut steps = vec![];
+        while let Some(header) = trr.read_velocities(&mut v)? {
+            let expected = header.step as f32;
+            assert_eq!(v, vec![[expected, -expected, 0.5 * expected]; 3]);
+            steps.push(header.step);
+        }
+        assert_eq!(steps, vec![0, 1, 2]);
+
+        let mut trr = TRRTrajectory::open_read(tempfile.path())?;
+        let result = trr.read_velocities(&mut v[..2]);
+        assert!(matches!(
+            result,
+            Err(Error::WrongLength {
+                name: "velocities",
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert_eq!(trr.tell(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_trr_into() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let frame = Frame {
+            step: 5,
+            time: 2.0,
+            box_vector: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            coords: vec![[1.0, 1.0, 1.0], [2.0, 2.0, 2.0]],
+            velocities: Some(vec![[0.1, 0.2, 0.3], [-0.1, -0.2, -0.3]]),
+            forces: Some(vec![[10.0, 20.0, 30.0], [-10.0, -20.0, -30.0]]),
+            lambda: 0.25,
+            ..Default::default()
+        };
+        let mut f = TRRTrajectory::open_write(tempfile.path())?;
+        f.set_blocks(TrrBlocks::all());
+        f.write(&frame)?;
+        f.write(&frame)?;
+        f.set_blocks(TrrBlocks::X);
+        f.write(&frame)?;
+        f.flush()?;
+
+        let mut f = TRRTrajectory::open_read(tempfile.path())?;
+        let mut x = [[0.0; 3]; 2];
+        let mut v = [[0.0; 3]; 2];
+        let header = f.read_trr_into(&mut x, Some(&mut v), None)?;
+        let expected = TrrHeader {
+            num_atoms: 2,
+            step: 5,
+            time: 2.0,
+            lambda: 0.25,
+            box_vector: Some(frame.box_vector),
+            blocks: TrrBlocks::X | TrrBlocks::V | TrrBlocks::F | TrrBlocks::BOX,
+            double: false,
+        };
+        assert_eq!(header, expected);
+        assert_eq!(x.to_vec(), frame.coords);
+        assert_eq!(Some(v.to_vec()), frame.velocities);
+
+        // Buffers of the wrong length leave the trajectory where it was
+        let mut short = [[0.0; 3]; 1];
+        let start = f.tell();
+        let err = f.read_trr_into(&mut x, None, Some(&mut short)).unwrap_err();
+        assert_eq!(
+            err,
+            Error::WrongLength {
+                name: "forces",
+                expected: 2,
+                found: 1
+            }
+        );
+        assert_eq!(f.tell(), start);
+        let mut forces = [[0.0; 3]; 2];
+        f.read_trr_into(&mut x, None, Some(&mut forces))?;
+        assert_eq!(Some(forces.to_vec()), frame.forces);
+
+        // Absent blocks leave their buffers untouched
+        let mut v = [[7.0; 3]; 2];
+        let header = f.read_trr_into(&mut x, Some(&mut v), None)?;
+        assert_eq!(header.blocks, TrrBlocks::X);
+        assert_eq!(header.box_vector, None);
+        assert_eq!(v, [[7.0; 3]; 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trr_num_atoms_fallback() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("traj.trr");
+        std::fs::copy("tests/1l2y.trr", &path)?;
+        let mut f = TRRTrajectory::open_read(&path)?;
+        let mut frame = Frame::with_len(304);
+        f.read(&mut frame)?;
+
+        // The query by path fails, but the open handle can still be read
+        std::fs::remove_file(&path)?;
+        assert_eq!(f.get_num_atoms()?, 304);
+        f.read(&mut frame)?;
+        assert_eq!(frame.step, 2);
+
+        // Without a readable handle, the error of the query is kept
+        std::fs::copy("tests/1l2y.trr", &path)?;
+        let mut f = TRRTrajectory::open_append(&path)?;
+        std::fs::remove_file(&path)?;
+        let err = f.get_num_atoms().unwrap_err();
+        assert_eq!(err.task(), Some(ErrorTask::ReadNumAtoms));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trr_blocks_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let mut f = TRRTrajectory::open_write(tempfile.path())?;
+        let mut frame = Frame::with_len(2);
+
+        f.set_blocks(TrrBlocks::X | TrrBlocks::F);
+        let result = f.write(&frame);
+        assert_eq!(result, Err(Error::MissingData { name: "forces" }));
+
+        frame.forces = Some(vec![[0.0; 3]; 3]);
+        let result = f.write(&frame);
+        assert_eq!(
+            result,
+            Err(Error::WrongLength {
+                name: "forces",
+                expected: 2,
+                found: 3
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_manual_loop() -> Result<(), Box<dyn std::error::Error>> {
+        let mut xtc_frames = Vec::new();
+        let mut xtc_traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(xtc_traj.get_num_atoms()?);
+
+        while let Ok(()) = xtc_traj.read(&mut frame) {
+            xtc_frames.push(frame.clone());
+        }
+
+        let mut trr_frames = Vec::new();
+        let mut trr_traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+
+        while let Ok(()) = trr_traj.read(&mut frame) {
+            trr_frames.push(frame.clone());
+        }
+
+        for (xtc, trr) in xtc_frames.into_iter().zip(trr_frames) {
+            assert_eq!(xtc.len(), trr.len());
+            assert_eq!(xtc.step, trr.step);
+            assert_eq!(xtc.time, trr.time);
+            assert_eq!(xtc.box_vector, trr.box_vector);
+            for (xtc_xyz, trr_xyz) in xtc.coords.into_iter().zip(trr.coords) {
+                assert!(xtc_xyz[0] - trr_xyz[0] <= 1e-5);
+                assert!(xtc_xyz[1] - trr_xyz[1] <= 1e-5);
+                assert!(xtc_xyz[2] - trr_xyz[2] <= 1e-5);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_wrong_size_frame() -> Result<(), Box<dyn std::error::Error>> {
+        let mut xtc_traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::new();
+
+        let result = xtc_traj.read(&mut frame);
+        if let Err(e) = result {
+            assert!(matches!(e, Error::WrongSizeFrame { .. }));
+        } else {
+            panic!("A read with an incorrectly sized frame should not succeed")
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_autoresize() -> Result<(), Box<dyn std::error::Error>> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(10);
+        xtc.read_autoresize(&mut frame)?;
+        assert_eq!(frame.len(), 304);
+        assert_eq!(frame.step, 1);
+
+        let mut expected = Frame::with_len(304);
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        trr.read(&mut expected)?;
+        let mut frame = Frame::new();
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        trr.read_autoresize(&mut frame)?;
+        assert_eq!(frame.coords, expected.coords);
+
+        // Too large frames shrink, and the end of the file is still an error
+        let mut frame = Frame::with_len(1000);
+        trr.read_autoresize(&mut frame)?;
+        assert_eq!(frame.len(), 304);
+        assert_eq!(frame.step, 2);
+        for _ in 2..38 {
+            trr.read_autoresize(&mut frame)?;
+        }
+        assert!(trr.read_autoresize(&mut frame).unwrap_err().is_eof());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_atom_range() -> Result<(), Box<dyn std::error::Error>> {
+        let mut traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut full = Frame::with_len(traj.get_num_atoms()?);
+        traj.read(&mut full)?;
+        traj.read(&mut full)?;
+
+        let mut traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut partial = Frame::with_len(10);
+        traj.read_atom_range(&mut partial, 100, 110)?;
+        traj.read_atom_range(&mut partial, 100, 110)?;
+
+        assert_eq!(partial.step, full.step);
+        assert_eq!(partial.time, full.time);
+        assert_eq!(partial.box_vector, full.box_vector);
+        assert_eq!(partial.coords[..], full.coords[100..110]);
+
+        // The trajectory is left at the start of the next step
+        traj.read(&mut full)?;
+        assert_eq!(full.step, 3);
+
+        // A step without a box does not keep the box of the last step read
+        let tempfile = NamedTempFile::new()?;
+        let mut f = TRRTrajectory::open_write(tempfile.path())?;
+        f.set_blocks(TrrBlocks::X);
+        f.write(&full)?;
+        f.flush()?;
+        let mut f = TRRTrajectory::open_read(tempfile.path())?;
+        f.read_atom_range(&mut partial, 100, 110)?;
+        assert_eq!(partial.box_vector, [[0.0; 3]; 3]);
+        assert_eq!(partial.coords[..], full.coords[100..110]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_atom_range_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let mut traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut frame = Frame::with_len(10);
+        let result = traj.read_atom_range(&mut frame, 300, 310);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidAtomRange {
+                start: 300,
+                end: 310,
+                num_atoms: 304
+            })
+        ));
+        assert_eq!(traj.try_tell()?, 0);
+
+        traj.read(&mut Frame::with_len(304))?;
+        let second = traj.try_tell()?;
+        let result = traj.read_atom_range(&mut frame, 0, 20);
+        assert!(matches!(result, Err(Error::WrongSizeFrame { .. })));
+        assert_eq!(traj.try_tell()?, second);
+
+        // The step the error was returned for is read next
+        let mut full = Frame::with_len(304);
+        traj.read(&mut full)?;
+        assert_eq!(full.step, 2);
+
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let result = traj.read_atom_range(&mut frame, 0, 10);
+        assert!(matches!(result, Err(Error::Unsupported { .. })));
+        Ok(())
+    }
+
+    /// Box volume of a frame with a lower-triangular box
+    fn box_volume(frame: &Frame) -> f64 {
+        let b = frame.box_vector;
+        f64::from(b[0][0]) * f64::from(b[1][1]) * f64::from(b[2][2])
+    }
+
+    #[test]
+    fn test_map_reduce() -> Result<()> {
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let (sum, count) = traj.map_reduce(box_volume, |(sum, n), v| (sum + v, n + 1), (0.0, 0))?;
+        assert_eq!(count, 38);
+        assert!(sum > 0.0);
+
+        // Folding from the middle of the file only covers the remaining frames
+        let mut traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        traj.read(&mut Frame::with_len(304))?;
+        let count = traj.map_reduce(|_| 1, |n, one| n + one, 0)?;
+        assert_eq!(count, 37);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_map_reduce() -> Result<()> {
+        // Collecting the steps checks that chunks are combined in file order
+        let step = |frame: &Frame| frame.step;
+        let push = |mut steps: Vec<usize>, step| {
+            steps.push(step);
+            steps
+        };
+        let append = |mut a: Vec<usize>, mut b: Vec<usize>| {
+            a.append(&mut b);
+            a
+        };
+
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let serial = traj.map_reduce(step, push, Vec::new())?;
+        assert_eq!(serial, (1..=38).collect::<Vec<_>>());
+
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        assert_eq!(traj.par_map_reduce(step, push, append, Vec::new)?, serial);
+        assert_eq!(traj.tell(), 0);
+
+        let mut traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        assert_eq!(traj.par_map_reduce(step, push, append, Vec::new)?, serial);
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_frame() -> Result<(), Box<dyn std::error::Error>> {
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(304);
+        traj.seek_frame(10)?;
+        assert_eq!(traj.current_frame()?, 10);
+        traj.read(&mut frame)?;
+        assert_eq!(frame.step, 11);
+        assert_eq!(traj.current_frame()?, 11);
+
+        traj.seek_frame(2)?;
+        traj.read(&mut frame)?;
+        assert_eq!(frame.step, 3);
+
+        let result = traj.seek_frame(38);
+        assert_eq!(
+            result,
+            Err(Error::FrameOutOfRange {
+                index: 38,
+                num_frames: 38
+            })
+        );
+
+        let mut traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        traj.seek_frame(37)?;
+        traj.read(&mut frame)?;
+        assert_eq!(frame.step, 38);
+        assert_eq!(traj.current_frame()?, 38);
+
+        traj.seek(SeekFrom::Start(4))?;
+        assert_eq!(
+            traj.current_frame(),
+            Err(Error::NotAtFrameStart { position: 4 })
+        );
+
+        // Inside the last frame is not at a frame start either
+        let position = traj.build_index()?.offset_of(37).unwrap() + 4;
+        traj.seek(SeekFrom::Start(position))?;
+        assert_eq!(
+            traj.current_frame(),
+            Err(Error::NotAtFrameStart { position })
+        );
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let position = xtc.build_index()?.offset_of(37).unwrap() + 4;
+        xtc.seek(SeekFrom::Start(position))?;
+        assert_eq!(
+            xtc.current_frame(),
+            Err(Error::NotAtFrameStart { position })
+        );
+        xtc.seek(SeekFrom::End(0))?;
+        assert_eq!(xtc.current_frame()?, 38);
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_write_size() -> Result<(), Box<dyn std::error::Error>> {
+        let mut src = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut frame = Frame::with_len(304);
+        src.read(&mut frame)?;
+        frame.velocities = Some(vec![[1.0, 2.0, 3.0]; 304]);
+
+        let tempfile = NamedTempFile::new()?;
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        for blocks in [TrrBlocks::X | TrrBlocks::BOX, TrrBlocks::X | TrrBlocks::V] {
+            trr.set_blocks(blocks);
+            let start = trr.try_tell()?;
+            for _ in 0..3 {
+                trr.write(&frame)?;
+            }
+            trr.flush()?;
+            let written = trr.try_tell()? - start;
+            assert_eq!(trr.estimate_write_size(3, &frame)?, written);
+        }
+
+        // An upper bound for XTC, but not by too much
+        let tempfile = NamedTempFile::new()?;
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        for num_atoms in [5, 304] {
+            frame.resize(num_atoms);
+            let start = xtc.try_tell()?;
+            xtc.write(&frame)?;
+            xtc.flush()?;
+            let written = xtc.try_tell()? - start;
+            let estimate = xtc.estimate_write_size(1, &frame)?;
+            assert!(written <= estimate && estimate < 2 * written);
+        }
+
+        let available = available_space(tempfile.path())?;
+        assert!(available > 0);
+        assert!(check_disk_space(tempfile.path(), 1)? >= 1);
+        assert!(matches!(
+            check_disk_space(tempfile.path(), u64::MAX),
+            Err(Error::InsufficientSpace { .. })
+        ));
+        // The directory of a file that does not exist yet
+        available_space(tempfile.path().with_extension("new"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_summary_csv() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut csv = Vec::new();
+        xtc.export_summary_csv(&mut csv)?;
+        let csv = String::from_utf8(csv).expect("CSV is not UTF-8");
+        let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(rows.len(), 39);
+        assert_eq!(rows[0][..3], ["step", "time", "box_volume"]);
+        assert!(rows.iter().all(|row| row.len() == 9));
+
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(304);
+        xtc.read(&mut frame)?;
+        let min_x = frame
+            .coords
+            .iter()
+            .map(|x| x[0])
+            .fold(f32::INFINITY, f32::min);
+        assert_eq!(rows[1][0], "1");
+        assert_eq!(rows[1][1].parse::<f32>(), Ok(frame.time));
+        assert_eq!(rows[1][2].parse::<f32>(), Ok(frame.box_determinant()));
+        assert_eq!(rows[1][3].parse::<f32>(), Ok(min_x));
+
+        // Frames without coordinates leave the coordinate columns empty
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_blocks(TrrBlocks::V);
+        let frame = Frame {
+            velocities: Some(vec![[1.0; 3]; 2]),
+            ..Frame::with_len(2)
+        };
+        trr.write(&frame)?;
+        trr.close()?;
+        let mut trr = TRRTrajectory::open_read(tempfile.path())?;
+        let mut csv = Vec::new();
+        trr.export_summary_csv(&mut csv)?;
+        assert!(String::from_utf8(csv).unwrap().ends_with("\n0,0,0,,,,,,\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_nth() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let frame = xtc.read_nth(19)?;
+        assert_eq!(frame.step, 20);
+        assert_eq!(xtc.current_frame()?, 20);
+        assert_eq!(xtc.read_owned()?.map(|f| f.step), Some(21));
+        assert_eq!(xtc.read_nth(0)?.step, 1);
+        assert_eq!(
+            xtc.read_nth(38).unwrap_err(),
+            Error::FrameOutOfRange {
+                index: 38,
+                num_frames: 38
+            }
+        );
+
+        let mut trr: Box<dyn Trajectory> = Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?);
+        assert_eq!(trr.read_nth(37)?.step, 38);
+        assert!(trr.read_owned()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_by_time() -> Result<()> {
+        let mut frame = Frame::with_len(304);
+        let trajectories: Vec<Box<dyn Trajectory>> = vec![
+            Box::new(XTCTrajectory::open_read("tests/1l2y.xtc")?),
+            Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?),
+        ];
+        for mut traj in trajectories {
+            traj.seek_by_time(18.5)?;
+            traj.read(&mut frame)?;
+            assert_eq!(frame.time, 19.0);
+
+            // An exact match, after seeking backwards
+            traj.seek_by_time(5.0)?;
+            traj.read(&mut frame)?;
+            assert_eq!(frame.time, 5.0);
+
+            traj.seek_by_time(-1.0)?;
+            traj.read(&mut frame)?;
+            assert_eq!(frame.step, 1);
+
+            assert_eq!(
+                traj.seek_by_time(38.5),
+                Err(Error::TimeOutOfRange {
+                    time: 38.5,
+                    range: Some((1.0, 38.0))
+                })
+            );
+            assert!(matches!(
+                traj.seek_by_time(f32::NAN),
+                Err(Error::InvalidArgument { name: "time", .. })
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_time() -> Result<(), Box<dyn std::error::Error>> {
+        let mut frame = Frame::with_len(304);
+        let trajectories: Vec<Box<dyn Trajectory>> = vec![
+            Box::new(XTCTrajectory::open_read("tests/1l2y.xtc")?),
+            Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?),
+        ];
+        for mut traj in trajectories {
+            traj.seek_time(18.4)?;
+            traj.read(&mut frame)?;
+            assert_eq!(frame.time, 18.0);
+            traj.seek_time(2.5)?;
+            traj.read(&mut frame)?;
+            assert_eq!(frame.time, 2.0);
+            traj.seek_time(38.0)?;
+            traj.read(&mut frame)?;
+            assert_eq!(frame.step, 38);
+
+            for time in [0.5, 38.5] {
+                assert_eq!(
+                    traj.seek_time(time),
+                    Err(Error::TimeOutOfRange {
+                        time,
+                        range: Some((1.0, 38.0))
+                    })
+                );
+            }
+            assert!(matches!(
+                traj.seek_time(f32::NAN),
+                Err(Error::InvalidArgument { name: "time", .. })
+            ));
+        }
+
+        // Times that go back, as after a restart
+        let tempfile = NamedTempFile::new()?;
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        for (step, time) in [0.0, 1.0, 2.0, 1.5, 2.5].iter().enumerate() {
+            xtc.write(&Frame {
+                step,
+                time: *time,
+                ..Frame::with_len(3)
+            })?;
+        }
+        xtc.close()?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        xtc.seek_time(1.6)?;
+        assert_eq!(xtc.peek_step()?, Some(3));
+
+        let empty = NamedTempFile::new()?;
+        let mut xtc = XTCTrajectory::open_read(empty.path())?;
+        assert_eq!(
+            xtc.seek_time(1.0),
+            Err(Error::TimeOutOfRange {
+                time: 1.0,
+                range: None
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_indexed_frames() -> Result<()> {
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        traj.seek_frame(10)?;
+        let mut count = 0;
+        for (i, frame) in traj.indexed_frames()? {
+            assert_eq!(i, 10 + count);
+            assert_eq!(frame?.step, i + 1);
+            count += 1;
+        }
+        assert_eq!(count, 28);
+
+        let traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let first = traj.indexed_frames()?.next().map(|(i, _)| i);
+        assert_eq!(first, Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_frames() -> Result<()> {
+        let sources: Vec<Box<dyn Trajectory>> = vec![
+            Box::new(XTCTrajectory::open_read("tests/1l2y.xtc")?),
+            Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?),
+        ];
+        for source in sources {
+            let frames: Box<dyn Iterator<Item = Result<std::rc::Rc<Frame>>>> = source.into_frames();
+            let steps: Result<Vec<usize>> = frames.map(|frame| Ok(frame?.step)).collect();
+            assert_eq!(steps?, (1..=38).collect::<Vec<_>>());
+        }
+
+        let frames = Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?).into_frames();
+        assert_eq!(frames.count(), 38);
+        Ok(())
+    }
+
+    #[test]
+    fn test_err_coordinate_out_of_range() -> Result<()> {
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        let max = XTCTrajectory::max_coordinate(1000.0);
+        let mut frame = Frame::with_len(10);
+
+        frame.coords[3][1] = 1e7;
+        let err = xtc.write(&frame).unwrap_err();
+        let expected = Error::CoordinateOutOfRange {
+            atom: 3,
+            value: 1e7,
+            precision: 1000.0,
+        };
+        assert_eq!(err, expected);
+        assert_eq!(xtc.tell(), 0);
+        assert!(!xtc.handle.dirty);
+
+        // Each coordinate fits, but their spread does not
+        frame.coords[3][1] = 0.0;
+        frame.coords[5][2] = 0.6 * max;
+        frame.coords[6][2] = -0.7 * max;
+        let err = xtc.write(&frame).unwrap_err();
+        assert!(matches!(err, Error::CoordinateOutOfRange { atom: 6, .. }));
+
+        frame.coords[3][1] = f32::NAN;
+        assert!(xtc.write(&frame).is_err());
+        assert_eq!(xtc.tell(), 0);
+
+        // A lower precision accommodates larger coordinates
+        xtc.set_precision(1.0);
+        frame.coords[3][1] = 1e7;
+        xtc.write(&frame)?;
+        xtc.flush()?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        let mut read = Frame::with_len(10);
+        xtc.read(&mut read)?;
+        assert_eq!(read.coords[3][1], 1e7);
+        assert_approx_eq!(read.coords[6][2], frame.coords[6][2], 1.0);
+
+        // Uncompressed frames can hold any coordinate
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        let mut small = Frame::with_len(9);
+        small.coords[0][0] = 1e30;
+        xtc.write(&small)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_precision() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        assert_eq!(xtc.read_precision(), None);
+        let mut frame = Frame::with_len(xtc.get_num_atoms()?);
+        let (precision, _) = xtc.read_with_precision_check(&mut frame)?;
+        assert_eq!(xtc.read_precision(), Some(10000.0));
+        assert_eq!(precision, 10000.0);
+
+        // The error bound holds for coordinates written at lower precisions
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let mut writer = XTCTrajectory::open_write(tempfile.path())?;
+        writer.write(&frame)?;
+        writer.set_precision(100.0);
+        writer.write(&frame)?;
+        writer.close()?;
+
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        let mut coarse = Frame::with_len(frame.len());
+        for (expected_precision, expected_error) in [(1000.0, 0.0005), (100.0, 0.005)] {
+            let (precision, max_error) = xtc.read_with_precision_check(&mut coarse)?;
+            assert_eq!(precision, expected_precision);
+            assert_approx_eq!(max_error, expected_error);
+            for (x, original) in coarse.coords.iter().zip(&frame.coords) {
+                for k in 0..3 {
+                    assert!((x[k] - original[k]).abs() <= max_error + 1e-5);
+                }
+            }
+        }
+
+        // Small frames are not compressed
+        let mut small = Frame::with_len(3);
+        let mut writer = XTCTrajectory::open_write(tempfile.path())?;
+        writer.write(&small)?;
+        writer.close()?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        assert_eq!(
+            xtc.read_with_precision_check(&mut small)?,
+            (f32::INFINITY, 0.0)
+        );
+        assert_eq!(xtc.read_precision(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_settings_from() -> Result<(), Box<dyn std::error::Error>> {
+        // Filtering XTC to XTC keeps the precision of the source
+        let tempfile = NamedTempFile::new()?;
+        let mut src = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut dst = XTCTrajectory::open_write(tempfile.path())?;
+        dst.copy_settings_from(&mut src)?;
+        assert_eq!(src.peek_step()?, Some(1));
+        for frame in src {
+            let frame = frame?;
+            if frame.step % 2 == 0 {
+                dst.write(&frame)?;
+            }
+        }
+        dst.close()?;
+        let mut filtered = XTCTrajectory::open_read(tempfile.path())?;
+        let mut frame = Frame::with_len(304);
+        while filtered.read(&mut frame).is_ok() {
+            assert_eq!(filtered.read_precision(), Some(10000.0));
+        }
+
+        // TRR copies the blocks of the next frame
+        let mut frame = Frame {
+            velocities: Some(vec![[1.0; 3]; 2]),
+            lambda: 0.5,
+            ..Frame::with_len(2)
+        };
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_blocks(TrrBlocks::X | TrrBlocks::V);
+        trr.write(&frame)?;
+        trr.close()?;
+        let mut src = TRRTrajectory::open_read(tempfile.path())?;
+        let other = NamedTempFile::new()?;
+        let mut dst = TRRTrajectory::open_write(other.path())?;
+        dst.copy_settings_from(&mut src)?;
+        assert_eq!(
+            dst.blocks(),
+            TrrBlocks::X | TrrBlocks::V | TrrBlocks::LAMBDA
+        );
+        src.read(&mut frame)?;
+        assert_eq!(frame.lambda, 0.0);
+
+        // Settings of the other format are ignored
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        dst.copy_settings_from(&mut xtc)?;
+        assert_eq!(
+            dst.blocks(),
+            TrrBlocks::X | TrrBlocks::V | TrrBlocks::LAMBDA
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_at_eof() -> Result<(), Box<dyn std::error::Error>> {
+        let mut frame = Frame::with_len(304);
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        for trj in [&mut xtc as &mut dyn Trajectory, &mut trr].iter_mut() {
+            let mut num_frames = 0;
+            while !trj.at_eof()? {
+                trj.read(&mut frame)?;
+                num_frames += 1;
+            }
+            assert_eq!(num_frames, 38);
+            assert!(trj.at_eof()?);
+            assert!(trj.read(&mut frame).unwrap_err().is_eof());
+        }
+
+        // An empty file is at the end from the start
+        let tempfile = NamedTempFile::new()?;
+        assert!(XTCTrajectory::open_read(tempfile.path())?.at_eof()?);
+
+        // A partial frame after the last complete one is not the end
+        let mut bytes = std::fs::read("tests/1l2y.xtc")?;
+        bytes.extend_from_within(..8);
+        std::fs::write(tempfile.path(), bytes)?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        for _ in 0..38 {
+            xtc.read(&mut frame)?;
+        }
+        assert!(!xtc.at_eof()?);
+        assert!(xtc.read(&mut frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_histogram() -> Result<()> {
+        let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let volume = |frame: &Frame| frame.box_determinant();
+        let counts = trj.histogram(volume, 10, (0.0, 1.0))?;
+        assert_eq!(counts.len(), 10);
+        assert_eq!(counts.iter().sum::<u64>(), 38);
+
+        // Steps 1..=38 in bins of width 10, with the out of range steps clamped
+        let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let counts = trj.histogram(|frame| frame.step as f32, 3, (10.0, 40.0))?;
+        assert_eq!(counts, vec![19, 10, 9]);
+        let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let counts = trj.histogram(|frame| frame.step as f32, 2, (0.0, 20.0))?;
+        assert_eq!(counts, vec![9, 29]);
+
+        // NaN is not counted
+        let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let counts = trj.histogram(|_| f32::NAN, 2, (0.0, 1.0))?;
+        assert_eq!(counts, vec![0, 0]);
+
+        let result = trj.histogram(|_| 0.0, 0, (0.0, 1.0));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidArgument { name: "bins", .. })
+        ));
+        for &range in [(1.0, 1.0), (1.0, 0.0), (0.0, f32::INFINITY)].iter() {
+            let result = trj.histogram(|_| 0.0, 2, range);
+            assert!(matches!(
+                result,
+                Err(Error::InvalidArgument { name: "range", .. })
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_recommend_precision() -> Result<(), Box<dyn std::error::Error>> {
+        let frame = Frame {
+            coords: (0..50)
+                .map(|i| {
+                    let i = i as f32;
+                    [0.2468 * i, -12.0 + 0.1357 * i, (i * 0.7).sin() * 9.87654]
+                })
+                .collect(),
+            ..Default::default()
+        };
+        // The fraction of the error left for rounding to multiples of
+        // 1 / precision shrinks as f32 rounding takes up more of it
+        for &(max_error, fraction) in &[(0.01, 0.99), (1e-4, 0.9), (5e-6, 0.4)] {
+            let precision = recommend_precision(&frame, max_error)?;
+            let tempfile = NamedTempFile::new()?;
+            let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+            xtc.set_precision(precision);
+            xtc.write(&frame)?;
+            xtc.close()?;
+            let mut read = Frame::with_len(50);
+            XTCTrajectory::open_read(tempfile.path())?.read(&mut read)?;
+            let error = read
+                .coords
+                .iter()
+                .flatten()
+                .zip(frame.coords.iter().flatten())
+                .map(|(x, y)| (x - y).abs())
+                .fold(0.0, f32::max);
+            assert!(error <= max_error, "{} > {}", error, max_error);
+            assert!(0.5 / precision > fraction * max_error);
+        }
+
+        for &max_error in &[0.0, -1.0, f32::NAN, 1e-7] {
+            assert!(matches!(
+                recommend_precision(&frame, max_error),
+                Err(Error::InvalidArgument {
+                    name: "max_error",
+                    ..
+                })
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_approx_eq_coords() -> Result<(), Box<dyn std::error::Error>> {
+        let a = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let mut b = a;
+        b[1][2] += 0.9 * XTC_COORD_TOLERANCE;
+        assert!(approx_eq_coords(&a, &b, XTC_DEFAULT_PRECISION));
+        b[1][2] += 0.2 * XTC_COORD_TOLERANCE;
+        assert!(!approx_eq_coords(&a, &b, XTC_DEFAULT_PRECISION));
+        assert!(approx_eq_coords(&a, &b, 100.0));
+        assert!(!approx_eq_coords(&a, &a[..1], 100.0));
+
+        // Round trip of the test trajectory at its own precision
+        let tempfile = NamedTempFile::new()?;
+        let mut src = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(304);
+        src.read(&mut frame)?;
+        let mut dst = XTCTrajectory::open_write(tempfile.path())?;
+        dst.write(&frame)?;
+        dst.close()?;
+        let mut read = Frame::with_len(304);
+        XTCTrajectory::open_read(tempfile.path())?.read(&mut read)?;
+        assert!(approx_eq_coords(
+            &read.coords,
+            &frame.coords,
+            XTC_DEFAULT_PRECISION
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xtc_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        assert_eq!(xtc.version()?, Some(XtcVersion::V1995));
+        for version in [XtcVersion::V1995, XtcVersion::V2023].iter() {
+            assert_eq!(XtcVersion::from_magic(version.magic()), Some(*version));
+        }
+        assert_eq!(XtcVersion::from_magic(TRR_MAGIC), None);
+
+        // A file written entirely in the newer revision
+        let mut bytes = std::fs::read("tests/1l2y.xtc")?;
+        let second = xtc.build_index()?.offset_of(1).unwrap() as usize;
+        bytes[..4].copy_from_slice(&2023_i32.to_be_bytes());
+        let tempfile = NamedTempFile::new()?;
+        std::fs::write(tempfile.path(), &bytes)?;
+        let mut newer = XTCTrajectory::open_read(tempfile.path())?;
+        assert_eq!(newer.version()?, Some(XtcVersion::V2023));
+        let mut frame = Frame::with_len(304);
+        let expected = Error::UnsupportedXtcVersion {
+            version: XtcVersion::V2023,
+        };
+        assert_eq!(newer.read(&mut frame), Err(expected.clone()));
+        assert_eq!(newer.peek_step(), Err(expected.clone()));
+
+        // A newer frame after a supported one
+        bytes[..4].copy_from_slice(&XTC_MAGIC.to_be_bytes());
+        bytes[second..second + 4].copy_from_slice(&2023_i32.to_be_bytes());
+        std::fs::write(tempfile.path(), &bytes)?;
+        let mut mixed = XTCTrajectory::open_read(tempfile.path())?;
+        mixed.read(&mut frame)?;
+        assert_eq!(mixed.read(&mut frame), Err(expected));
+
+        // Anything else is not an XTC file
+        std::fs::write(tempfile.path(), b"not a trajectory")?;
+        let mut other = XTCTrajectory::open_read(tempfile.path())?;
+        assert_eq!(
+            other.version().unwrap_err().code(),
+            Some(ErrorCode::ExdrMagic)
+        );
+        std::fs::write(tempfile.path(), b"")?;
+        assert_eq!(XTCTrajectory::open_read(tempfile.path())?.version()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> Result<(), Box<dyn std::error::Error>> {
+        // Uncompressed XTC frames can hold a NaN
+        let tempfile = NamedTempFile::new()?;
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        for (step, x) in [0.0, f32::NAN, 2.0].iter().enumerate() {
+            let frame = Frame {
+                step,
+                time: step as f32,
+                coords: vec![[0.0; 3], [*x, 0.0, 0.0]],
+                ..Default::default()
+            };
+            xtc.write(&frame)?;
+        }
+        xtc.close()?;
+
+        let mut frame = Frame::with_len(2);
+        let permissive = XTCTrajectory::open_read(tempfile.path())?;
+        assert!(!permissive.is_strict());
+        assert_eq!(permissive.into_iter().filter(|f| f.is_ok()).count(), 3);
+        let mut strict = XTCTrajectory::open_read(tempfile.path())?;
+        strict.set_strict(true);
+        strict.read(&mut frame)?;
+        let position = strict.tell();
+        let result = strict.read(&mut frame);
+        assert!(
+            matches!(result, Err(Error::NonStandardFrame { step: 1, .. })),
+            "{:?}",
+            result
+        );
+        // The offending frame can still be read permissively
+        assert_eq!(strict.tell(), position);
+        strict.set_strict(false);
+        strict.read(&mut frame)?;
+        assert!(frame[1][0].is_nan());
+
+        // Time must increase, and the atom count stay the same
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_blocks(TrrBlocks::V);
+        for (num_atoms, time) in [(2, 0.0), (2, 1.0), (2, 1.0), (3, 2.0)].iter() {
+            let frame = Frame {
+                time: *time,
+                velocities: Some(vec![[0.0; 3]; *num_atoms]),
+                ..Frame::with_len(*num_atoms)
+            };
+            trr.write(&frame)?;
+        }
+        trr.close()?;
+        let mut strict = TRRTrajectory::open_read(tempfile.path())?;
+        strict.set_strict(true);
+        strict.read(&mut frame)?;
+        strict.read(&mut frame)?;
+        let result = strict.read(&mut frame);
+        assert!(
+            matches!(&result, Err(Error::NonStandardFrame { reason, .. }) if reason.contains("follow")),
+            "{:?}",
+            result
+        );
+        // Turning strict mode on again forgets the previous frame
+        strict.set_strict(true);
+        strict.read(&mut frame)?;
+        let result = strict.read(&mut frame);
+        assert!(
+            matches!(&result, Err(Error::NonStandardFrame { reason, .. }) if reason.contains("atoms")),
+            "{:?}",
+            result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_precision() -> Result<(), Box<dyn std::error::Error>> {
+        // The precision of the first frame follows its header and atom count
+        let mut bytes = std::fs::read("tests/1l2y.xtc")?;
+        bytes[56..60].copy_from_slice(&(-1000.0f32).to_be_bytes());
+        let tempfile = NamedTempFile::new()?;
+        std::fs::write(tempfile.path(), &bytes)?;
+
+        let mut frame = Frame::with_len(304);
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        xtc.read(&mut frame)?;
+        assert_eq!(xtc.read_precision(), None);
+
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        xtc.set_strict(true);
+        let result = xtc.read(&mut frame);
+        assert!(
+            matches!(&result, Err(Error::NonStandardFrame { step: 1, reason }) if reason.contains("precision -1000")),
+            "{:?}",
+            result
+        );
+        assert_eq!(xtc.tell(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_write_box() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let mut frame = Frame::with_len(2);
+        frame.box_vector = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, 1.0]];
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        xtc.write(&frame)?;
+        xtc.set_strict(true);
+        let result = xtc.write(&frame);
+        assert!(
+            matches!(&result, Err(Error::NonStandardFrame { reason, .. }) if reason.contains("GROMACS")),
+            "{:?}",
+            result
+        );
+        frame.canonicalize_box()?;
+        xtc.write(&frame)?;
+        xtc.close()?;
+        assert_eq!(XTCTrajectory::open_read(tempfile.path())?.num_frames()?, 2);
+
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_strict(true);
+        trr.write(&frame)?;
+        frame.box_vector[0][1] = 0.5;
+        assert!(trr.write(&frame).is_err());
+        trr.close()?;
+        assert_eq!(TRRTrajectory::open_read(tempfile.path())?.num_frames()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_owned() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut trr: Box<dyn Trajectory> = Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?);
+        for trj in [&mut xtc as &mut dyn Trajectory, &mut trr].iter_mut() {
+            let mut steps = Vec::new();
+            while let Some(frame) = trj.read_owned()? {
+                assert_eq!(frame.len(), 304);
+                steps.push(frame.step);
+            }
+            assert_eq!(steps, (1..=38).collect::<Vec<_>>());
+            assert!(trj.read_owned()?.is_none());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_precision() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        std::fs::copy("tests/1l2y.xtc", tempfile.path())?;
+        let mut frame = Frame::with_len(304);
+        XTCTrajectory::open_read("tests/1l2y.xtc")?.read(&mut frame)?;
+
+        // Appended frames keep the precision of the file, not the default
+        let mut append = XTCTrajectory::open_append(tempfile.path())?;
+        append.write(&frame)?;
+        append.write(&frame)?;
+        append.close()?;
+        let mut read = XTCTrajectory::open_read(tempfile.path())?;
+        let mut num_frames = 0;
+        while read.read(&mut frame).is_ok() {
+            assert_eq!(read.read_precision(), Some(10000.0));
+            num_frames += 1;
+        }
+        assert_eq!(num_frames, 40);
+
+        // Unless overridden
+        let mut append = XTCTrajectory::open_append(tempfile.path())?;
+        append.set_precision(100.0);
+        append.write(&frame)?;
+        append.close()?;
+        let mut read = XTCTrajectory::open_read(tempfile.path())?;
+        read.seek_frame(40)?;
+        read.read(&mut frame)?;
+        assert_eq!(read.read_precision(), Some(100.0));
+
+        // Appending to a new file uses the default
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("new.xtc");
+        let mut append = XTCTrajectory::open_append(&path)?;
+        append.write(&frame)?;
+        append.close()?;
+        let mut read = XTCTrajectory::open_read(&path)?;
+        read.read(&mut frame)?;
+        assert_eq!(read.read_precision(), Some(XTC_DEFAULT_PRECISION));
+        Ok(())
+    }
+
+    #[test]
+    fn test_len() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        assert_eq!(xtc.len(), None);
+        xtc.build_index()?;
+        assert_eq!(xtc.len(), Some(38));
+
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        assert_eq!(trr.len(), None);
+        trr.seek_frame(37)?;
+        assert_eq!(trr.len(), Some(38));
+        assert_eq!(trr.num_frames()?, 38);
+        assert_eq!(trr.is_empty(), Some(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_frame() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let frame = xtc.find_frame(|frame| frame.step > 20)?;
+        assert_eq!(frame.map(|frame| frame.step), Some(21));
+        // Reading continues after the matching frame
+        assert_eq!(xtc.peek_step()?, Some(22));
+        assert!(xtc.find_frame(|frame| frame.step > 100)?.is_none());
+
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let frame = trr
+            .find_frame(|frame| frame.step > 20)?
+            .expect("No frame found");
+        assert_eq!(frame.step, 21);
+        assert_eq!(frame.len(), 304);
+        Ok(())
+    }
+
+    #[test]
+    fn test_peek() -> Result<()> {
+        let sources: Vec<Box<dyn Trajectory>> = vec![
+            Box::new(XTCTrajectory::open_read("tests/1l2y.xtc")?),
+            Box::new(TRRTrajectory::open_read("tests/1l2y.trr")?),
+        ];
+        for mut traj in sources {
+            let mut frame = Frame::with_len(traj.get_num_atoms()?);
+            for _ in 0..38 {
+                let step = traj.peek_step()?;
+                let time = traj.peek_time()?;
+                // Peeking twice does not advance either
+                assert_eq!(traj.peek_step()?, step);
+                traj.read(&mut frame)?;
+                assert_eq!(step, Some(frame.step));
+                assert_eq!(time, Some(frame.time));
+            }
+            assert_eq!(traj.peek_step()?, None);
+            assert_eq!(traj.peek_time()?, None);
+            assert!(traj.read(&mut frame).unwrap_err().is_eof());
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn test_digest() -> Result<()> {
+        let hex = |digest: [u8; 32]| -> String {
+            digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+        };
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let digest = xtc.digest()?;
+        assert_eq!(
+            hex(digest),
+            "0e6c1148b60de17ab568da564e99e3cf68873c7d7a3757f67a5f64f0bb4c554c"
+        );
+
+        // The digest is of the file, not of the remaining frames
+        let mut frame = Frame::with_len(xtc.get_num_atoms()?);
+        xtc.read(&mut frame)?;
+        assert_eq!(xtc.digest()?, digest);
+        assert_eq!(
+            XTCTrajectory::open_read("tests/1l2y.xtc")?.digest()?,
+            digest
+        );
+
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        assert_eq!(
+            hex(trr.digest()?),
+            "a4349991856e50f075988f2f0d36f90e2e54d345f7453da29e6b62c7bc7b1561"
+        );
+
+        // Frames still buffered by a writer are included
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let mut writer = XTCTrajectory::open_write(tempfile.path())?;
+        writer.write(&frame)?;
+        let written = writer.digest()?;
+        drop(writer);
+        assert_eq!(
+            XTCTrajectory::open_read(tempfile.path())?.digest()?,
+            written
+        );
+        Ok(())
     }
-}
 
-impl io::Seek for TRRTrajectory {
-    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
-        self.handle.seek(pos)
+    #[test]
+    fn test_try_from_path() -> Result<()> {
+        let path = Path::new("tests/1l2y.xtc");
+        let mut xtc: XTCTrajectory = path.try_into()?;
+        assert_eq!(xtc.get_num_atoms()?, 304);
+
+        let path = PathBuf::from("tests/1l2y.trr");
+        let mut trr: TRRTrajectory = path.try_into()?;
+        assert_eq!(trr.get_num_atoms()?, 304);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn test_try_from_path_not_found() {
+        let path = Path::new("non-existent.xtc");
+        let result = XTCTrajectory::try_from(path);
+        assert!(matches!(result, Err(Error::CouldNotOpen { .. })));
 
-    use super::*;
-    use std::io::Seek;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+        let result = TRRTrajectory::try_from(path.to_path_buf());
+        assert!(matches!(result, Err(Error::CouldNotOpen { .. })));
+    }
 
     #[test]
-    fn test_read_write_xtc() -> Result<()> {
-        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
-        let tmp_path = tempfile.path();
+    fn test_peek_compression() -> Result<(), Box<dyn std::error::Error>> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let compression = xtc.peek_compression()?.expect("The frame is compressed");
+        assert_eq!(xtc.tell(), 0);
+        let header_bytes = 4 * (3 + 10 + 1 + 1 + 8);
+        let mut frame = Frame::with_len(304);
+        xtc.read(&mut frame)?;
+        let len = xtc.last_frame_byte_len().expect("A frame was read");
+        assert_eq!(compression.bytes.div_ceil(4) * 4, len - header_bytes);
 
-        let natoms = 2;
-        let frame = Frame {
-            step: 5,
-            time: 2.0,
-            box_vector: [[1.0, 2.0, 3.0], [2.0, 1.0, 3.0], [3.0, 2.0, 1.0]],
-            coords: vec![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
-        };
-        let mut f = XTCTrajectory::open_write(&tmp_path)?;
-        let write_status = f.write(&frame);
-        match write_status {
-            Err(_) => panic!("Failed"),
-            Ok(()) => {}
+        let tempfile = NamedTempFile::new()?;
+        let mut sizes = vec![];
+        for precision in [10.0, 1000.0].iter() {
+            let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+            xtc.set_precision(*precision);
+            xtc.write(&frame)?;
+            xtc.close()?;
+            let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+            let compression = xtc.peek_compression()?.expect("The frame is compressed");
+            assert_eq!(compression.precision, *precision);
+            sizes.push(compression.large_bits);
+            xtc.read(&mut frame)?;
+            assert_eq!(xtc.peek_compression()?, None);
         }
-        f.flush()?;
+        assert!(sizes[0] < sizes[1], "{:?}", sizes);
 
-        let mut new_frame = Frame::with_len(natoms);
-        let mut f = XTCTrajectory::open_read(tmp_path)?;
-        let num_atoms = f.get_num_atoms()?;
-        assert_eq!(num_atoms, natoms);
+        // Small frames are not compressed
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        xtc.write(&Frame::with_len(9))?;
+        xtc.close()?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        assert_eq!(xtc.peek_compression()?, None);
+        Ok(())
+    }
 
-        let read_status = f.read(&mut new_frame);
-        match read_status {
-            Err(e) => assert!(false, "{:?}", e),
-            Ok(()) => {}
+    #[test]
+    fn test_last_frame_byte_len() -> Result<(), Box<dyn std::error::Error>> {
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(traj.get_num_atoms()?);
+        let mut total = 0;
+        let mut offsets = vec![];
+        while traj.read(&mut frame).is_ok() {
+            let len = traj.last_frame_byte_len().expect("A frame was read");
+            offsets.push(total);
+            total += len;
+            assert_eq!(traj.tell(), total);
         }
+        assert_eq!(traj.last_frame_byte_len(), None);
+        assert_eq!(total, std::fs::metadata("tests/1l2y.xtc")?.len());
+        let index = traj.build_index()?;
+        let expected: Vec<_> = index.entries().iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, expected);
+        Ok(())
+    }
 
-        assert_eq!(new_frame.len(), frame.len());
-        assert_eq!(new_frame.step, frame.step);
-        assert_approx_eq!(new_frame.time, frame.time);
-        assert_eq!(new_frame.box_vector, frame.box_vector);
-        assert_eq!(new_frame.coords, frame.coords);
+    #[test]
+    fn test_last_write_stats() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let tmp_path = tempfile.path();
+
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = Frame::with_len(traj.get_num_atoms()?);
+        traj.read(&mut frame)?;
+
+        let mut f = XTCTrajectory::open_write(tmp_path)?;
+        assert_eq!(f.last_write_stats(), None);
+        f.write(&frame)?;
+        let stats = f.last_write_stats().expect("No stats after write");
+        assert_eq!(stats.bytes, f.tell());
+        assert_eq!(stats.precision, 1000.0);
+        assert!(stats.compressed);
+        // Compression should beat 12 bytes per atom by a wide margin
+        assert!(stats.bytes < 12 * frame.len() as u64 / 2);
+
+        frame.filter_coords(&[0, 1]);
+        f.write(&frame)?;
+        let stats = f.last_write_stats().expect("No stats after write");
+        assert!(!stats.compressed);
         Ok(())
     }
 
     #[test]
-    fn test_read_write_trr() -> Result<()> {
+    #[cfg(target_os = "linux")]
+    fn test_err_disk_full() -> Result<()> {
+        // Writes to /dev/full always fail with ENOSPC once the buffer is flushed
+        let mut f = TRRTrajectory::open_write("/dev/full")?;
+        f.write(&Frame::with_len(2))?;
+        let err = f.flush().expect_err("Flushing to /dev/full should fail");
+        assert!(matches!(err, Error::DiskFull { .. }));
+        assert_eq!(err.task(), Some(ErrorTask::Flush));
+        assert_eq!(
+            err.os_error().map(|e| e.raw_os_error()),
+            Some(Some(28)) // ENOSPC
+        );
+        assert!(
+            format!("{}", err).contains("No space left on device"),
+            "{}",
+            err
+        );
+        assert!(is_disk_full(libc::EDQUOT));
+        assert!(!is_disk_full(libc::EIO));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_on_drop() -> Result<()> {
         let tempfile = NamedTempFile::new().expect("Could not create temporary file");
-        let tmp_path = tempfile.path();
+        let mut frame = Frame::with_len(10);
+        frame.step = 3;
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        xtc.write(&frame)?;
+        drop(xtc);
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        assert_eq!(xtc.peek_step()?, Some(3));
+        xtc.close()?;
 
-        let natoms = 2;
-        let frame = Frame {
-            step: 5,
-            time: 2.0,
-            box_vector: [[1.0, 2.0, 3.0], [2.0, 1.0, 3.0], [3.0, 2.0, 1.0]],
-            coords: vec![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
-        };
-        let mut f = TRRTrajectory::open_write(tmp_path)?;
-        let write_status = f.write(&frame);
-        match write_status {
-            Err(_) => panic!("Failed"),
-            Ok(()) => {}
-        }
-        f.flush()?;
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.write(&frame)?;
+        trr.close()?;
+        let mut trr = TRRTrajectory::open_read(tempfile.path())?;
+        assert_eq!(trr.peek_step()?, Some(3));
+        Ok(())
+    }
 
-        let mut new_frame = Frame::with_len(natoms);
-        let mut f = TRRTrajectory::open_read(tmp_path)?;
-        // let num_atoms = f.get_num_atoms()?;
-        // assert_eq!(num_atoms, natoms);
+    #[test]
+    fn test_flush_read_only() -> Result<()> {
+        // Nothing to flush, before or after reading
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        xtc.flush()?;
+        let mut frame = Frame::with_len(xtc.get_num_atoms()?);
+        xtc.read(&mut frame)?;
+        let position = xtc.tell();
+        xtc.flush()?;
+        assert_eq!(xtc.tell(), position);
+        xtc.read(&mut frame)?;
+        assert_eq!(frame.step, 2);
 
-        let read_status = f.read(&mut new_frame);
-        match read_status {
-            Err(e) => assert!(false, "{:?}", e),
-            Ok(()) => {}
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        trr.flush()?;
+        trr.close()?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_err_read_only_dir() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Permissions are not checked for root
+        if unsafe { libc::geteuid() } == 0 {
+            return Ok(());
         }
+        let dir = tempfile::tempdir()?;
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o555))?;
+        let path = dir.path().join("out.xtc");
+        let result = XTCTrajectory::open_write(&path);
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755))?;
+        assert_eq!(
+            result.err(),
+            Some(Error::CouldNotOpen {
+                path: path.clone(),
+                mode: FileMode::Write,
+            })
+        );
+        assert!(!path.exists());
+        Ok(())
+    }
 
-        assert_eq!(new_frame.len(), frame.len());
-        assert_eq!(new_frame.step, frame.step);
-        assert_eq!(new_frame.time, frame.time);
-        assert_eq!(new_frame.box_vector, frame.box_vector);
-        assert_eq!(new_frame.coords, frame.coords);
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_err_close() -> Result<()> {
+        let mut f = XTCTrajectory::open_write("/dev/full")?;
+        f.write(&Frame::with_len(2))?;
+        let err = f.close().expect_err("Closing /dev/full should fail");
+        assert_eq!(err.task(), Some(ErrorTask::Flush));
+        assert_eq!(
+            err.os_error().map(|e| e.raw_os_error()),
+            Some(Some(libc::ENOSPC))
+        );
         Ok(())
     }
 
     #[test]
-    pub fn test_manual_loop() -> Result<(), Box<dyn std::error::Error>> {
-        let mut xtc_frames = Vec::new();
-        let mut xtc_traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
-        let mut frame = Frame::with_len(xtc_traj.get_num_atoms()?);
+    fn test_filemode_from_str() {
+        assert_eq!("r".parse::<FileMode>(), Ok(FileMode::Read));
+        assert_eq!("w".parse::<FileMode>(), Ok(FileMode::Write));
+        assert_eq!("a".parse::<FileMode>(), Ok(FileMode::Append));
+        for mode in &[FileMode::Read, FileMode::Write, FileMode::Append] {
+            assert_eq!(mode.to_string().parse::<FileMode>().as_ref(), Ok(mode));
+        }
 
-        while let Ok(()) = xtc_traj.read(&mut frame) {
-            xtc_frames.push(frame.clone());
+        for invalid in &["", "rw", "x", "R", "r+"] {
+            let result = invalid.parse::<FileMode>();
+            assert!(
+                matches!(result, Err(Error::InvalidArgument { name: "mode", .. })),
+                "{:?}",
+                result
+            );
         }
+    }
 
-        let mut trr_frames = Vec::new();
-        let mut trr_traj = TRRTrajectory::open_read("tests/1l2y.trr")?;
+    #[test]
+    fn test_retry_interrupted() {
+        let interrupted = Error::OsError {
+            code: ErrorCode::ExdrFloat,
+            task: ErrorTask::Read,
+            errno: libc::EINTR,
+        };
+        // (attempts, rewinds), failing with EINTR for the first `fail` attempts
+        let run = |fail: usize| {
+            let mut state = (0, 0);
+            let result = retry_interrupted(
+                &mut state,
+                |(attempts, _)| {
+                    *attempts += 1;
+                    if *attempts <= fail {
+                        Err(interrupted.clone())
+                    } else {
+                        Ok(*attempts)
+                    }
+                },
+                |(_, rewinds)| {
+                    *rewinds += 1;
+                    Ok(())
+                },
+            );
+            (result, state)
+        };
+        assert_eq!(run(0), (Ok(1), (1, 0)));
+        assert_eq!(run(3), (Ok(4), (4, 3)));
+        let n = MAX_INTERRUPTED_ATTEMPTS;
+        assert_eq!(run(n), (Err(interrupted.clone()), (n, n - 1)));
 
-        while let Ok(()) = trr_traj.read(&mut frame) {
-            trr_frames.push(frame.clone());
-        }
+        // Other errors are returned straight away
+        let mut attempts = 0;
+        let eof = Error::from((ErrorCode::ExdrEndOfFile, ErrorTask::Read));
+        let result: Result<()> = retry_interrupted(
+            &mut attempts,
+            |attempts| {
+                *attempts += 1;
+                Err(eof.clone())
+            },
+            |_| unreachable!(),
+        );
+        assert_eq!((result, attempts), (Err(eof), 1));
+    }
 
-        for (xtc, trr) in xtc_frames.into_iter().zip(trr_frames) {
-            assert_eq!(xtc.len(), trr.len());
-            assert_eq!(xtc.step, trr.step);
-            assert_eq!(xtc.time, trr.time);
-            assert_eq!(xtc.box_vector, trr.box_vector);
-            for (xtc_xyz, trr_xyz) in xtc.coords.into_iter().zip(trr.coords) {
-                assert!(xtc_xyz[0] - trr_xyz[0] <= 1e-5);
-                assert!(xtc_xyz[1] - trr_xyz[1] <= 1e-5);
-                assert!(xtc_xyz[2] - trr_xyz[2] <= 1e-5);
-            }
+    #[test]
+    fn test_format_from_str() -> Result<()> {
+        assert_eq!("xtc".parse::<Format>(), Ok(Format::Xtc));
+        assert_eq!("TRR".parse::<Format>(), Ok(Format::Trr));
+        assert_eq!(".xtc".parse::<Format>(), Ok(Format::Xtc));
+        for format in &[Format::Xtc, Format::Trr] {
+            assert_eq!(format.to_string().parse::<Format>().as_ref(), Ok(format));
         }
+        for invalid in &["", "dcd", "xtc.", "..trr"] {
+            let result = invalid.parse::<Format>();
+            assert!(
+                matches!(result, Err(Error::InvalidArgument { name: "format", .. })),
+                "{:?}",
+                result
+            );
+        }
+
+        assert_eq!(Format::from_path("a/b.XTC")?, Format::Xtc);
+        assert_eq!(Format::from_magic(1995), Some(Format::Xtc));
+        assert_eq!(Format::from_magic(1993), Some(Format::Trr));
+        assert_eq!(Format::from_magic(0), None);
+        assert_eq!(Format::detect("tests/1l2y.xtc")?, Format::Xtc);
+        assert_eq!(Format::detect("tests/1l2y.trr")?, Format::Trr);
+        assert!(matches!(
+            Format::detect("Cargo.toml"),
+            Err(Error::UnknownFormat { .. })
+        ));
         Ok(())
     }
 
     #[test]
-    pub fn test_wrong_size_frame() -> Result<(), Box<dyn std::error::Error>> {
-        let mut xtc_traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
-        let mut frame = Frame::new();
+    fn test_detect_byte_order() -> Result<(), Box<dyn std::error::Error>> {
+        // A little-endian TRR header: magic, then the version string length
+        let tempfile = NamedTempFile::new()?;
+        let mut header = TRR_MAGIC.to_le_bytes().to_vec();
+        header.extend_from_slice(&13_i32.to_le_bytes());
+        std::fs::write(tempfile.path(), &header)?;
 
-        let result = xtc_traj.read(&mut frame);
-        if let Err(e) = result {
-            assert!(matches!(e, Error::WrongSizeFrame { .. }));
-        } else {
-            panic!("A read with an incorrectly sized frame should not succeed")
-        }
+        assert!(matches!(
+            Format::detect(tempfile.path()),
+            Err(Error::UnknownFormat { .. })
+        ));
+        let little = Format::detect_with_byte_order(tempfile.path(), ByteOrder::LittleEndian)?;
+        assert_eq!(little, Format::Trr);
+        assert!(Format::detect_with_byte_order("tests/1l2y.xtc", ByteOrder::LittleEndian).is_err());
+        assert_eq!(ByteOrder::default(), ByteOrder::BigEndian);
         Ok(())
     }
 
+    #[test]
+    fn test_open_options() {
+        assert_eq!(OpenOptions::new().read(true).mode(), Ok(FileMode::Read));
+        assert_eq!(OpenOptions::new().write(true).mode(), Ok(FileMode::Write));
+        assert_eq!(OpenOptions::new().append(true).mode(), Ok(FileMode::Append));
+        assert_eq!(
+            OpenOptions::new().write(true).append(true).mode(),
+            Ok(FileMode::Append)
+        );
+        assert!(OpenOptions::new().mode().is_err());
+        assert!(OpenOptions::new().read(true).write(true).mode().is_err());
+    }
+
     #[test]
     fn test_path_to_cstring() -> Result<(), Box<dyn std::error::Error>> {
         // A valid string should convert to CString successfully
@@ -658,6 +5756,7 @@ mod tests {
             time: 2.0,
             box_vector: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
             coords: vec![[0.0, 0.0, 0.0], [0.5, 0.5, 0.5]],
+            ..Default::default()
         };
         let mut f = TRRTrajectory::open_write(tmp_path)?;
         assert_eq!(f.tell(), 0);
@@ -675,6 +5774,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_try_tell() -> Result<()> {
+        let xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        assert_eq!(xtc.try_tell(), Ok(0));
+
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        assert_eq!(trr.try_tell(), Ok(0));
+        trr.read(&mut Frame::with_len(304))?;
+        assert_eq!(trr.try_tell(), Ok(trr.tell()));
+        assert!(trr.tell() > 0);
+        Ok(())
+    }
+
     #[test]
     fn test_seek() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let tempfile = NamedTempFile::new()?;
@@ -686,6 +5798,7 @@ mod tests {
             time: 0.0,
             box_vector: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
             coords: vec![[0.0, 0.0, 0.0], [0.5, 0.5, 0.5]],
+            ..Default::default()
         };
         let mut f = TRRTrajectory::open_write(tmp_path)?;
         f.write(&frame)?;
@@ -744,6 +5857,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_magic_numbers() -> Result<(), Box<dyn std::error::Error>> {
+        for (path, magic) in &[("tests/1l2y.xtc", XTC_MAGIC), ("tests/1l2y.trr", TRR_MAGIC)] {
+            let mut first_int = [0; 4];
+            std::fs::File::open(path)?.read_exact(&mut first_int)?;
+            assert_eq!(i32::from_be_bytes(first_int), *magic);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_err_zero_atoms() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let tmp_path = tempfile.path();
+
+        // A valid XTC header (magic, natoms, step, time) that claims zero atoms
+        let mut file = std::fs::File::create(tmp_path)?;
+        for int in &[1995_i32, 0, 1, 0] {
+            file.write_all(&int.to_be_bytes())?;
+        }
+        file.write_all(&[0; 64])?;
+        file.flush()?;
+
+        let mut f = XTCTrajectory::open_read(tmp_path)?;
+        let expected = Err(Error::ZeroAtoms {
+            path: tmp_path.to_owned(),
+        });
+        assert_eq!(f.get_num_atoms(), expected);
+        let result = f.read(&mut Frame::new());
+        assert!(matches!(result, Err(Error::CouldNotCheckNAtoms(_))));
+        Ok(())
+    }
+
     #[test]
     fn test_err_could_not_read() -> Result<()> {
         let file_name = "README.md"; // not a trajectory
@@ -768,6 +5914,7 @@ mod tests {
             time: 2.0,
             box_vector: [[1.0, 2.0, 3.0], [2.0, 1.0, 3.0], [3.0, 2.0, 1.0]],
             coords: vec![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
+            ..Default::default()
         };
         let mut f = XTCTrajectory::open_write(&tmp_path)?;
         f.write(&frame)?;
@@ -845,6 +5992,7 @@ mod tests {
             time: 0.0,
             box_vector: [[0.0; 3]; 3],
             coords: vec![[1.0; 3]],
+            ..Default::default()
         };
         let expected = Error::OutOfRange {
             name: "frame.step",
@@ -862,4 +6010,244 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_send() -> Result<()> {
+        fn assert_send<T: Send>() {}
+        assert_send::<XTCTrajectory>();
+        assert_send::<TRRTrajectory>();
+        assert_send::<Frame>();
+
+        // A trajectory moved to another thread carries on where it was
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut frame = Frame::with_len(304);
+        xtc.read(&mut frame)?;
+        trr.read(&mut frame)?;
+        let handles = [
+            std::thread::spawn(move || xtc.read_owned()),
+            std::thread::spawn(move || trr.read_owned()),
+        ];
+        for handle in handles {
+            let frame = handle.join().expect("Reader thread panicked")?;
+            assert_eq!(frame.map(|f| f.step), Some(2));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_variable_atom_count() -> Result<(), Box<dyn std::error::Error>> {
+        for format in [Format::Xtc, Format::Trr] {
+            let tempfile = NamedTempFile::new()?;
+            let mut traj = format.open(tempfile.path(), FileMode::Write)?;
+            for (step, num_atoms) in [(0, 20), (1, 20), (2, 15), (3, 20)] {
+                let frame = Frame {
+                    step,
+                    ..Frame::with_len(num_atoms)
+                };
+                traj.write(&frame)?;
+            }
+            traj.flush()?;
+
+            // Iteration stops at the shrinking frame with an error
+            let mut traj = format.open(tempfile.path(), FileMode::Read)?;
+            let results: Vec<_> = traj.frames().collect();
+            assert_eq!(results.len(), 3);
+            assert_eq!(results[1].as_ref().map(|f| f.step), Ok(1));
+            let expected = Error::VariableAtomCount {
+                frame: Some(2),
+                expected: 20,
+                got: 15,
+            };
+            assert_eq!(results[2].as_ref().unwrap_err(), &expected);
+
+            // The trajectory is left at the start of the frame
+            let mut traj = format.open(tempfile.path(), FileMode::Read)?;
+            let mut frame = Frame::with_len(20);
+            traj.read(&mut frame)?;
+            traj.read(&mut frame)?;
+            assert_eq!(traj.read(&mut frame), Err(expected));
+            assert_eq!(traj.peek_step()?, Some(2));
+
+            traj.set_variable_atom_count(true);
+            let mut lens = Vec::new();
+            while traj.read(&mut frame).is_ok() {
+                lens.push((frame.step, frame.len()));
+            }
+            assert_eq!(lens, vec![(2, 15), (3, 20)]);
+        }
+
+        // The frame number is not known after seeking to an unindexed frame
+        let tempfile = NamedTempFile::new()?;
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        for num_atoms in [20, 20, 15] {
+            xtc.write(&Frame::with_len(num_atoms))?;
+        }
+        xtc.flush()?;
+        let index = XTCTrajectory::open_read(tempfile.path())?.build_index()?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        xtc.seek(SeekFrom::Start(index.offset_of(2).unwrap()))?;
+        let result = xtc.read(&mut Frame::with_len(20));
+        assert!(matches!(
+            result,
+            Err(Error::VariableAtomCount { frame: None, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_write_with_precision() -> Result<(), Box<dyn std::error::Error>> {
+        let tempfile = NamedTempFile::new()?;
+        let mut frame = Frame::with_len(20);
+        frame[3] = [1.25, -2.5, 0.125];
+        for precision in [10.0, 1000.0, 100_000.0] {
+            let mut xtc = XTCTrajectory::open_write_with_precision(tempfile.path(), precision)?;
+            xtc.write(&frame)?;
+            xtc.close()?;
+
+            let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+            let mut read = Frame::with_len(20);
+            xtc.read(&mut read)?;
+            assert_eq!(xtc.read_precision(), Some(precision));
+            for k in 0..3 {
+                assert!((read[3][k] - frame[3][k]).abs() <= 0.5 / precision + 1e-6);
+            }
+            // Only the coarsest precision cannot represent them exactly
+            assert_eq!(read.coords == frame.coords, precision > 10.0);
+        }
+
+        // The default is unchanged
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        xtc.write(&frame)?;
+        assert_eq!(xtc.last_write_stats().map(|s| s.precision), Some(1000.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xtc_read_header() -> Result<(), Box<dyn std::error::Error>> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let frames: Vec<_> = xtc.frames().collect::<Result<_>>()?;
+        xtc.seek_frame(0)?;
+        for frame in &frames {
+            let header = xtc.read_header()?.expect("Every frame has a header");
+            assert_eq!(header.step, frame.step);
+            assert_eq!(header.time, frame.time);
+            assert_eq!(header.box_vector, frame.box_vector);
+        }
+        assert_eq!(xtc.read_header()?, None);
+
+        // The next read returns the frame after the header
+        xtc.seek_frame(4)?;
+        xtc.read_header()?;
+        assert_eq!(xtc.read_owned()?.map(|f| f.step), Some(6));
+
+        // Uncompressed frames are skipped too
+        let tempfile = NamedTempFile::new()?;
+        let mut writer = XTCTrajectory::open_write(tempfile.path())?;
+        for step in 0..3 {
+            writer.write(&Frame {
+                step,
+                ..Frame::with_len(3)
+            })?;
+        }
+        writer.close()?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        let header = xtc.read_header()?;
+        assert_eq!(header.map(|h| (h.num_atoms, h.step)), Some((3, 0)));
+        assert_eq!(xtc.read_owned()?.map(|f| f.step), Some(1));
+
+        let mut not_xtc = XTCTrajectory::open_read("tests/1l2y.trr")?;
+        assert!(not_xtc.read_header().is_err());
+        assert_eq!(not_xtc.try_tell()?, 0);
+
+        let mut bytes = std::fs::read("tests/1l2y.xtc")?;
+        bytes[..4].copy_from_slice(&2023_i32.to_be_bytes());
+        std::fs::write(tempfile.path(), &bytes)?;
+        let mut newer = XTCTrajectory::open_read(tempfile.path())?;
+        let expected = Error::UnsupportedXtcVersion {
+            version: XtcVersion::V2023,
+        };
+        assert_eq!(newer.read_header(), Err(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_frame_at() -> Result<()> {
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut frame = Frame::with_len(304);
+        for index in [37, 0, 12, 12, 5] {
+            xtc.read_frame_at(index, &mut frame)?;
+            assert_eq!(frame.step, index + 1);
+            let coords = frame.coords.clone();
+            trr.read_frame_at(index, &mut frame)?;
+            assert_eq!(frame.step, index + 1);
+            for (x, y) in coords.iter().zip(&frame.coords) {
+                for k in 0..3 {
+                    assert_approx_eq!(x[k], y[k], 1e-4);
+                }
+            }
+        }
+        // Reading carries on after the frame
+        assert_eq!(xtc.read_owned()?.map(|f| f.step), Some(7));
+
+        assert_eq!(
+            xtc.read_frame_at(38, &mut frame),
+            Err(Error::FrameOutOfRange {
+                index: 38,
+                num_frames: 38
+            })
+        );
+        assert!(xtc.read_frame_at(0, &mut Frame::with_len(3)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_index() -> Result<()> {
+        let index = XTCTrajectory::open_read("tests/1l2y.xtc")?.build_index()?;
+        let saved = TrajectoryIndex::new(index.entries().to_vec());
+        let mut xtc = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        xtc.set_index(saved);
+        assert_eq!(xtc.len(), Some(38));
+        assert_eq!(xtc.read_nth(20)?.step, 21);
+
+        // The index given is used without checking it against the file
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let index = trr.build_index()?;
+        let first_two = index.entries()[..2].to_vec();
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        trr.set_index(TrajectoryIndex::new(first_two));
+        assert_eq!(trr.num_frames()?, 2);
+        assert_eq!(trr.read_nth(1)?.step, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejected_write_keeps_index() -> Result<()> {
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let index = XTCTrajectory::open_read("tests/1l2y.xtc")?.build_index()?;
+        let mut xtc = XTCTrajectory::open_write(tempfile.path())?;
+        xtc.set_index(index);
+        let mut frame = Frame::with_len(10);
+        frame.coords[3][1] = 1e7;
+        assert!(xtc.write(&frame).is_err());
+        assert_eq!(xtc.len(), Some(38));
+        assert!(!xtc.handle.dirty);
+
+        // A write that succeeds discards the index
+        frame.coords[3][1] = 0.0;
+        xtc.write(&frame)?;
+        assert_eq!(xtc.len(), None);
+        assert!(xtc.handle.dirty);
+
+        let index = TRRTrajectory::open_read("tests/1l2y.trr")?.build_index()?;
+        let mut trr = TRRTrajectory::open_write(tempfile.path())?;
+        trr.set_index(index);
+        trr.set_blocks(TrrBlocks::X | TrrBlocks::V);
+        let result = trr.write(&frame);
+        assert_eq!(result, Err(Error::MissingData { name: "velocities" }));
+        assert_eq!(trr.len(), Some(38));
+        assert!(!trr.handle.dirty);
+        Ok(())
+    }
 }
@@ -0,0 +1,47 @@
+//! Parallel folds over indexed trajectories
+use crate::*;
+use rayon::prelude::*;
+
+/// Fold over all frames in `index`, reading each chunk of frames through its
+/// own file handle, created by `open_at` and positioned at the given offset
+///
+/// The frames are split into one contiguous chunk per thread. Each chunk is
+/// folded with `reduce` starting from `init()`, and the chunk results are
+/// then combined in file order with `combine`.
+pub(crate) fn map_reduce<Tr, T, A>(
+    index: &TrajectoryIndex,
+    num_atoms: usize,
+    open_at: impl Fn(u64) -> Result<Tr> + Sync,
+    map: impl Fn(&Frame) -> T + Sync,
+    reduce: impl Fn(A, T) -> A + Sync,
+    combine: impl Fn(A, A) -> A,
+    init: impl Fn() -> A + Sync,
+) -> Result<A>
+where
+    Tr: Trajectory,
+    A: Send,
+{
+    let num_chunks = rayon::current_num_threads().clamp(1, index.len().max(1));
+    let chunk_len = index.len().div_ceil(num_chunks);
+    let chunks: Vec<&[IndexEntry]> = if index.is_empty() {
+        Vec::new()
+    } else {
+        index.entries().chunks(chunk_len).collect()
+    };
+
+    let results: Result<Vec<A>> = chunks
+        .into_par_iter()
+        .map(|chunk| {
+            let mut traj = open_at(chunk[0].offset)?;
+            let mut frame = Frame::with_len(num_atoms);
+            let mut acc = init();
+            for _ in chunk {
+                traj.read(&mut frame)?;
+                acc = reduce(acc, map(&frame));
+            }
+            Ok(acc)
+        })
+        .collect();
+
+    Ok(results?.into_iter().fold(init(), combine))
+}
@@ -0,0 +1,150 @@
+//! Recycling of frame buffers across many reads
+use crate::*;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+/// A pool of `Frame` buffers that are reused instead of reallocated
+///
+/// Reading many trajectories one after another allocates a new frame, with
+/// its coordinate buffer, for each of them. A pool keeps the buffers of
+/// frames that are no longer in use: `acquire` hands out a `PooledFrame`,
+/// which can be passed to `Trajectory::read` like any `&mut Frame`, and
+/// dropping it returns its buffers to the pool for the next `acquire`.
+///
+/// Clones of a pool share the same buffers. The pool is protected by a mutex,
+/// so it can be shared between threads, for example one pool for all workers
+/// of a server; the lock is only held while a frame is taken or returned.
+#[derive(Debug, Clone, Default)]
+pub struct FramePool {
+    idle: Arc<Mutex<Vec<Frame>>>,
+}
+
+impl FramePool {
+    /// Create an empty pool
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// A frame of `num_atoms` atoms, reusing an idle buffer if there is one
+    ///
+    /// The frame is cleared as if created with `Frame::with_len`: all
+    /// coordinates are zero, the step, time, box and lambda are reset, and
+    /// there are no velocities, forces or other per-atom values. The buffer
+    /// with the largest capacity is reused, so that it rarely has to grow.
+    pub fn acquire(&self, num_atoms: usize) -> PooledFrame {
+        let frame = {
+            let mut idle = self.lock();
+            let largest = (0..idle.len()).max_by_key(|&i| idle[i].coords.capacity());
+            largest.map(|i| idle.swap_remove(i))
+        };
+        let frame = match frame {
+            Some(mut frame) => {
+                let mut coords = std::mem::take(&mut frame.coords);
+                coords.clear();
+                coords.resize(num_atoms, [0.0; 3]);
+                Frame {
+                    coords,
+                    ..Default::default()
+                }
+            }
+            None => Frame::with_len(num_atoms),
+        };
+        PooledFrame {
+            frame: Some(frame),
+            pool: self.clone(),
+        }
+    }
+
+    /// Number of buffers waiting in the pool to be reused
+    pub fn num_idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Drop all idle buffers, freeing their memory
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Frame>> {
+        // A panic elsewhere cannot leave a list of frames inconsistent
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A frame borrowed from a `FramePool`, returned to it when dropped
+///
+/// This dereferences to a `Frame`, so it can be used wherever a frame is
+/// expected.
+#[derive(Debug)]
+pub struct PooledFrame {
+    /// Always Some, until taken by `into_inner` or `drop`
+    frame: Option<Frame>,
+    pool: FramePool,
+}
+
+impl PooledFrame {
+    /// Take the frame out of the pool for good
+    ///
+    /// Its buffers are then freed as usual when it is dropped.
+    pub fn into_inner(mut self) -> Frame {
+        self.frame.take().expect("Pooled frame was already taken")
+    }
+}
+
+impl Deref for PooledFrame {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        self.frame.as_ref().expect("Pooled frame was already taken")
+    }
+}
+
+impl DerefMut for PooledFrame {
+    fn deref_mut(&mut self) -> &mut Frame {
+        self.frame.as_mut().expect("Pooled frame was already taken")
+    }
+}
+
+impl Drop for PooledFrame {
+    fn drop(&mut self) {
+        if let Some(frame) = self.frame.take() {
+            self.pool.lock().push(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pool_reuse() -> Result<()> {
+        let pool = FramePool::new();
+        let mut traj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut frame = pool.acquire(304);
+        traj.read(&mut frame)?;
+        assert_eq!(frame.step, 1);
+        let buffer = frame.coords.as_ptr();
+        drop(frame);
+        assert_eq!(pool.num_idle(), 1);
+
+        // The same allocation comes back, cleared and resized
+        let frame = pool.clone().acquire(100);
+        assert_eq!(frame.coords.as_ptr(), buffer);
+        assert_eq!(frame.step, 0);
+        assert_eq!(frame.coords, vec![[0.0; 3]; 100]);
+        assert_eq!(pool.num_idle(), 0);
+
+        // Frames in use are not handed out twice
+        let other = pool.acquire(10);
+        assert_ne!(other.coords.as_ptr(), buffer);
+        let frame = frame.into_inner();
+        drop(other);
+        assert_eq!(pool.num_idle(), 1);
+        assert_eq!(frame.len(), 100);
+
+        pool.clear();
+        assert_eq!(pool.num_idle(), 0);
+        Ok(())
+    }
+}
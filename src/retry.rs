@@ -0,0 +1,240 @@
+//! Retrying reads that fail with transient errors
+use crate::*;
+use std::thread;
+use std::time::Duration;
+
+/// Trajectory wrapper that retries reads failing with transient errors
+///
+/// When a read fails with an error for which `Error::is_retriable` is true,
+/// the wrapper seeks back to the start of the frame and tries again, up to
+/// `max_attempts` attempts in total. The delay before the first retry is
+/// `backoff` and doubles with every further retry. Any other error, including
+/// the end of the file, is returned straight away. Writes are passed through
+/// unchanged.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// let trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+/// let trj = ReadRetry::new(trj, 3, Duration::from_millis(100));
+/// assert_eq!(trj.into_iter().count(), 38);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReadRetry<T> {
+    inner: T,
+    max_attempts: usize,
+    backoff: Duration,
+}
+
+impl<T> ReadRetry<T> {
+    /// Wrap `inner`, making at most `max_attempts` attempts at each read
+    pub fn new(inner: T, max_attempts: usize, backoff: Duration) -> Self {
+        ReadRetry {
+            inner,
+            max_attempts: max_attempts.max(1),
+            backoff,
+        }
+    }
+
+    /// Get a reference to the wrapped trajectory
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped trajectory
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Unwrap the wrapped trajectory
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Trajectory + io::Seek> ReadRetry<T> {
+    /// Run `read` on the inner trajectory, rewinding and retrying it on transient errors
+    fn retry(&mut self, read: impl FnMut(&mut T) -> Result<()>) -> Result<()> {
+        let start = self.inner.stream_position().map_err(from_io_error)?;
+        let mut delay = self.backoff;
+        let rewind = |inner: &mut T| {
+            thread::sleep(delay);
+            delay *= 2;
+            inner.seek(SeekFrom::Start(start)).map_err(from_io_error)?;
+            Ok(())
+        };
+        retry_read(
+            &mut self.inner,
+            self.max_attempts,
+            Error::is_retriable,
+            read,
+            rewind,
+        )
+    }
+}
+
+/// Recover the crate error from an error returned through `io::Seek`
+fn from_io_error(e: io::Error) -> Error {
+    match e.into_inner().map(|e| e.downcast::<Error>()) {
+        Some(Ok(e)) => *e,
+        _ => (ErrorCode::ExdrNr, ErrorTask::Seek).into(),
+    }
+}
+
+impl<T: Trajectory + io::Seek> Trajectory for ReadRetry<T> {
+    fn read(&mut self, frame: &mut Frame) -> Result<()> {
+        self.retry(|inner| inner.read(frame))
+    }
+
+    fn read_atom_range(&mut self, frame: &mut Frame, start: usize, end: usize) -> Result<()> {
+        self.retry(|inner| inner.read_atom_range(frame, start, end))
+    }
+
+    forward_trajectory!(|this| this.inner, except reads);
+}
+
+impl<T: Trajectory + io::Seek> IntoIterator for ReadRetry<T> {
+    type Item = Result<std::rc::Rc<Frame>>;
+    type IntoIter = TrajectoryIterator<ReadRetry<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        iterator::into_iter_inner(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trajectory of frames numbered by position, that fails with
+    /// `errno` a given number of times before each successful read
+    struct Flaky {
+        position: u64,
+        num_frames: u64,
+        failures_per_frame: usize,
+        failures: usize,
+        errno: i32,
+        attempts: usize,
+    }
+
+    impl Flaky {
+        fn new(failures_per_frame: usize, errno: i32) -> Self {
+            Flaky {
+                position: 0,
+                num_frames: 3,
+                failures_per_frame,
+                failures: 0,
+                errno,
+                attempts: 0,
+            }
+        }
+    }
+
+    impl Trajectory for Flaky {
+        fn read(&mut self, frame: &mut Frame) -> Result<()> {
+            self.attempts += 1;
+            if self.position == self.num_frames {
+                return Err((ErrorCode::ExdrEndOfFile, ErrorTask::Read).into());
+            }
+            // Fail halfway through the frame
+            self.position += 1;
+            if self.failures < self.failures_per_frame {
+                self.failures += 1;
+                return Err(Error::OsError {
+                    code: ErrorCode::ExdrFloat,
+                    task: ErrorTask::Read,
+                    errno: self.errno,
+                });
+            }
+            self.failures = 0;
+            frame.step = self.position as usize;
+            Ok(())
+        }
+
+        fn write(&mut self, _frame: &Frame) -> Result<()> {
+            Err(Error::Unsupported {
+                operation: "Writing",
+            })
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_num_atoms(&mut self) -> Result<usize> {
+            Ok(1)
+        }
+    }
+
+    impl io::Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            if let SeekFrom::Start(pos) = pos {
+                self.position = pos;
+            }
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn test_retry_transient() -> Result<()> {
+        let mut traj = ReadRetry::new(Flaky::new(2, libc::EIO), 3, Duration::from_millis(1));
+        let push = |mut steps: Vec<usize>, step| {
+            steps.push(step);
+            steps
+        };
+        let steps = traj.map_reduce(|frame| frame.step, push, Vec::new())?;
+        // Each frame fails twice before it is read, plus one read at the end of the file
+        assert_eq!(steps, vec![1, 2, 3]);
+        assert_eq!(traj.get_ref().attempts, 3 * 3 + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_gives_up() {
+        let mut traj = ReadRetry::new(Flaky::new(3, libc::EIO), 3, Duration::from_millis(1));
+        let result = traj.read(&mut Frame::with_len(1));
+        assert!(
+            result.as_ref().is_err_and(Error::is_retriable),
+            "{:?}",
+            result
+        );
+        assert_eq!(traj.get_ref().attempts, 3);
+    }
+
+    #[test]
+    fn test_retry_not_retriable() -> Result<()> {
+        let mut traj = ReadRetry::new(Flaky::new(1, libc::ENOSPC), 3, Duration::from_millis(1));
+        let result = traj.read(&mut Frame::with_len(1));
+        assert!(matches!(result, Err(Error::OsError { .. })));
+        assert_eq!(traj.get_ref().attempts, 1);
+
+        // End of file is returned without retrying
+        let mut traj = ReadRetry::new(Flaky::new(0, libc::EIO), 3, Duration::from_millis(1));
+        traj.get_mut().position = 3;
+        let result = traj.read(&mut Frame::with_len(1));
+        assert!(result.is_err_and(|e| e.is_eof()));
+        assert_eq!(traj.get_ref().attempts, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_forwards() -> Result<()> {
+        let trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut trj = ReadRetry::new(trj, 3, Duration::from_millis(1));
+        assert_eq!(trj.num_frames()?, 38);
+        trj.seek_time(10.2)?;
+        assert_eq!(trj.peek_step()?, Some(10));
+
+        // As do boxed and borrowed trajectories
+        fn num_frames(mut trj: impl Trajectory) -> Result<usize> {
+            trj.num_frames()
+        }
+        let mut boxed: Box<dyn Trajectory> = Box::new(trj);
+        assert_eq!(num_frames(&mut boxed)?, 38);
+        assert_eq!(boxed.read_nth(4)?.step, 5);
+        Ok(())
+    }
+}
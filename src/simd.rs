@@ -0,0 +1,139 @@
+//! SIMD implementations of the coordinate transforms on `Frame`
+//!
+//! These functions operate directly on coordinate slices and process four
+//! atoms at a time. They produce results that are bit-identical to
+//! `Frame::translate`, `Frame::scale` and `Frame::rotate`, since every
+//! coordinate goes through the same floating point operations in the same
+//! order (no fused multiply-adds are used). Unlike `Frame::scale`, `scale`
+//! only touches the coordinates it is given, not the box.
+//!
+//! `translate` and `scale` work on the coordinates as a flat array and gain
+//! the most. `rotate` has to gather each component of four atoms into a
+//! vector first, which eats most of the benefit for the array-of-structs
+//! layout `Frame` uses; see `cargo bench --features simd -- transforms`.
+//!
+//! Only available with the `simd` feature.
+use wide::f32x4;
+
+/// Reinterpret coordinates as a flat slice of floats
+fn flatten(coords: &mut [[f32; 3]]) -> &mut [f32] {
+    // SAFETY: [[f32; 3]] has the same layout as [f32] of three times the length
+    unsafe { std::slice::from_raw_parts_mut(coords.as_mut_ptr() as *mut f32, coords.len() * 3) }
+}
+
+fn load(chunk: &[f32]) -> f32x4 {
+    f32x4::from([chunk[0], chunk[1], chunk[2], chunk[3]])
+}
+
+fn store(chunk: &mut [f32], value: f32x4) {
+    chunk.copy_from_slice(&value.to_array());
+}
+
+/// Translate all coordinates by `offset`. See `Frame::translate`
+pub fn translate(coords: &mut [[f32; 3]], offset: [f32; 3]) {
+    let [ox, oy, oz] = offset;
+    // Four atoms are exactly three vectors, with the offset pattern rotating
+    let offsets = [
+        f32x4::from([ox, oy, oz, ox]),
+        f32x4::from([oy, oz, ox, oy]),
+        f32x4::from([oz, ox, oy, oz]),
+    ];
+
+    let mut chunks = flatten(coords).chunks_exact_mut(12);
+    for chunk in &mut chunks {
+        for (quad, offset) in chunk.chunks_exact_mut(4).zip(offsets.iter()) {
+            let value = load(quad) + *offset;
+            store(quad, value);
+        }
+    }
+    for (i, x) in chunks.into_remainder().iter_mut().enumerate() {
+        *x += offset[i % 3];
+    }
+}
+
+/// Scale all coordinates by `factor`. See `Frame::scale`
+pub fn scale(coords: &mut [[f32; 3]], factor: f32) {
+    let factors = f32x4::splat(factor);
+    let mut chunks = flatten(coords).chunks_exact_mut(4);
+    for quad in &mut chunks {
+        let value = load(quad) * factors;
+        store(quad, value);
+    }
+    for x in chunks.into_remainder() {
+        *x *= factor;
+    }
+}
+
+/// Rotate all coordinates by the matrix `rotation`. See `Frame::rotate`
+pub fn rotate(coords: &mut [[f32; 3]], rotation: [[f32; 3]; 3]) {
+    let r = rotation;
+    let mut chunks = coords.chunks_exact_mut(4);
+    for atoms in &mut chunks {
+        let x = f32x4::from([atoms[0][0], atoms[1][0], atoms[2][0], atoms[3][0]]);
+        let y = f32x4::from([atoms[0][1], atoms[1][1], atoms[2][1], atoms[3][1]]);
+        let z = f32x4::from([atoms[0][2], atoms[1][2], atoms[2][2], atoms[3][2]]);
+
+        let mut rotated = [[0.0; 4]; 3];
+        for (out, row) in rotated.iter_mut().zip(r.iter()) {
+            let value =
+                f32x4::splat(row[0]) * x + f32x4::splat(row[1]) * y + f32x4::splat(row[2]) * z;
+            *out = value.to_array();
+        }
+        for (i, atom) in atoms.iter_mut().enumerate() {
+            *atom = [rotated[0][i], rotated[1][i], rotated[2][i]];
+        }
+    }
+    for x in chunks.into_remainder() {
+        *x = [
+            r[0][0] * x[0] + r[0][1] * x[1] + r[0][2] * x[2],
+            r[1][0] * x[0] + r[1][1] * x[1] + r[1][2] * x[2],
+            r[2][0] * x[0] + r[2][1] * x[1] + r[2][2] * x[2],
+        ];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Frame;
+
+    /// A frame with irregular coordinates and an atom count that is not a
+    /// multiple of four, so the remainder paths are exercised too
+    fn test_frame() -> Frame {
+        let mut frame = Frame::with_len(103);
+        for (i, x) in frame.coords.iter_mut().enumerate() {
+            let i = i as f32;
+            *x = [i * 0.37 - 5.0, (i * 1.3).sin() * 7.0, 1.0 / (i + 0.5)];
+        }
+        frame
+    }
+
+    #[test]
+    fn test_translate_identical() {
+        let mut expected = test_frame();
+        let mut frame = expected.clone();
+        expected.translate([0.1, -2.5, 3.3]);
+        translate(&mut frame.coords, [0.1, -2.5, 3.3]);
+        assert_eq!(frame.coords, expected.coords);
+    }
+
+    #[test]
+    fn test_scale_identical() {
+        let mut expected = test_frame();
+        let mut frame = expected.clone();
+        expected.scale(0.1);
+        scale(&mut frame.coords, 0.1);
+        assert_eq!(frame.coords, expected.coords);
+    }
+
+    #[test]
+    fn test_rotate_identical() {
+        let (s, c) = 0.3_f32.sin_cos();
+        let rotation = [[c, -s, 0.0], [s * 0.5, c * 0.5, 0.8], [0.6, 0.0, c]];
+        let mut expected = test_frame();
+        let mut frame = expected.clone();
+        expected.rotate(rotation);
+        rotate(&mut frame.coords, rotation);
+        assert_eq!(frame.coords, expected.coords);
+    }
+}
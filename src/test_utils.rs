@@ -0,0 +1,210 @@
+//! Helpers for testing code that reads and writes frames
+use crate::*;
+
+/// Panic with a description of the first difference between two frames
+///
+/// Step and atom and residue ids must match exactly. Time, lambda, the box
+/// and all other per-atom values (coordinates, velocities, forces, occupancies
+/// and B-factors) may differ by up to `tol`; use 0 to compare them exactly.
+/// Optional per-atom values must be present in both frames or in neither.
+/// The panic message names the field, the atom and the component that
+/// differ, and by how much, so a failing round-trip test shows what went
+/// wrong without further digging.
+///
+/// This is available with the `test-utils` feature.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// let mut trj = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+/// let frame = trj.read_owned()?.expect("The file has frames");
+/// let mut shifted = frame.clone();
+/// shifted.coords[10][2] += 1e-4;
+/// assert_frames_eq(&frame, &shifted, 1e-3);
+/// # Ok(())
+/// # }
+/// ```
+#[track_caller]
+pub fn assert_frames_eq(a: &Frame, b: &Frame, tol: f32) {
+    if let Some(difference) = frame_difference(a, b, tol) {
+        panic!("Frames differ: {}", difference);
+    }
+}
+
+/// Description of the first difference between two frames beyond `tol`, or
+/// None if they are equal, for `assert_frames_eq`
+fn frame_difference(a: &Frame, b: &Frame, tol: f32) -> Option<String> {
+    // Written so that NaN never compares as close
+    let close = |x: f32, y: f32| x == y || (x - y).abs() <= tol;
+    let scalar = |field: &str, x: f32, y: f32| {
+        if close(x, y) {
+            None
+        } else {
+            Some(format!(
+                "{} is {} and {} (difference {})",
+                field,
+                x,
+                y,
+                y - x
+            ))
+        }
+    };
+
+    if a.step != b.step {
+        return Some(format!("step is {} and {}", a.step, b.step));
+    }
+    if let Some(difference) = scalar("time", a.time, b.time) {
+        return Some(difference);
+    }
+    if let Some(difference) = scalar("lambda", a.lambda, b.lambda) {
+        return Some(difference);
+    }
+    for i in 0..3 {
+        for j in 0..3 {
+            let field = format!("box_vector[{}][{}]", i, j);
+            let difference = scalar(&field, a.box_vector[i][j], b.box_vector[i][j]);
+            if difference.is_some() {
+                return difference;
+            }
+        }
+    }
+
+    let vectors = [
+        ("coords", Some(&a.coords), Some(&b.coords)),
+        ("velocities", a.velocities.as_ref(), b.velocities.as_ref()),
+        ("forces", a.forces.as_ref(), b.forces.as_ref()),
+    ];
+    for (field, x, y) in vectors.iter() {
+        let (x, y) = match (x, y) {
+            (None, None) => continue,
+            (Some(x), Some(y)) => (x, y),
+            (x, _) => return Some(presence(field, x.is_some())),
+        };
+        if x.len() != y.len() {
+            return Some(format!("{} has {} and {} atoms", field, x.len(), y.len()));
+        }
+        for (atom, (x, y)) in x.iter().zip(y.iter()).enumerate() {
+            for (k, axis) in ["x", "y", "z"].iter().enumerate() {
+                let field = format!("{}[{}].{}", field, atom, axis);
+                let difference = scalar(&field, x[k], y[k]);
+                if difference.is_some() {
+                    return difference;
+                }
+            }
+        }
+    }
+
+    let scalars = [
+        (
+            "occupancies",
+            a.occupancies.as_ref(),
+            b.occupancies.as_ref(),
+        ),
+        ("b_factors", a.b_factors.as_ref(), b.b_factors.as_ref()),
+    ];
+    for (field, x, y) in scalars.iter() {
+        let (x, y) = match (x, y) {
+            (None, None) => continue,
+            (Some(x), Some(y)) => (x, y),
+            (x, _) => return Some(presence(field, x.is_some())),
+        };
+        if x.len() != y.len() {
+            return Some(format!("{} has {} and {} atoms", field, x.len(), y.len()));
+        }
+        for (atom, (&x, &y)) in x.iter().zip(y.iter()).enumerate() {
+            let difference = scalar(&format!("{}[{}]", field, atom), x, y);
+            if difference.is_some() {
+                return difference;
+            }
+        }
+    }
+
+    let ids = [
+        ("atom_ids", a.atom_ids.as_ref(), b.atom_ids.as_ref()),
+        (
+            "residue_ids",
+            a.residue_ids.as_ref(),
+            b.residue_ids.as_ref(),
+        ),
+    ];
+    for (field, x, y) in ids.iter() {
+        let (x, y) = match (x, y) {
+            (None, None) => continue,
+            (Some(x), Some(y)) => (x, y),
+            (x, _) => return Some(presence(field, x.is_some())),
+        };
+        if x.len() != y.len() {
+            return Some(format!("{} has {} and {} atoms", field, x.len(), y.len()));
+        }
+        let atom = x.iter().zip(y.iter()).position(|(x, y)| x != y);
+        if let Some(atom) = atom {
+            return Some(format!(
+                "{}[{}] is {} and {}",
+                field, atom, x[atom], y[atom]
+            ));
+        }
+    }
+    None
+}
+
+fn presence(field: &str, in_first: bool) -> String {
+    let (first, second) = if in_first { ("", "not ") } else { ("not ", "") };
+    format!(
+        "{} are {}in the first frame but {}in the second",
+        field, first, second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assert_frames_eq() {
+        let mut a = Frame::with_len(3);
+        a.step = 4;
+        a.coords[2] = [1.0, 2.0, 3.0];
+        a.velocities = Some(vec![[0.5; 3]; 3]);
+        assert_frames_eq(&a, &a.clone(), 0.0);
+
+        let mut b = a.clone();
+        b.coords[2][1] = 2.25;
+        assert_frames_eq(&a, &b, 0.5);
+        assert_eq!(
+            frame_difference(&a, &b, 0.1),
+            Some("coords[2].y is 2 and 2.25 (difference 0.25)".to_string())
+        );
+        let message = std::panic::catch_unwind(|| assert_frames_eq(&a, &b, 0.1))
+            .expect_err("Perturbed frames should not compare equal");
+        let message = message
+            .downcast_ref::<String>()
+            .expect("The panic message should be a string");
+        assert_eq!(
+            message,
+            "Frames differ: coords[2].y is 2 and 2.25 (difference 0.25)"
+        );
+
+        b.coords[2][1] = 2.0;
+        b.velocities = None;
+        assert_eq!(
+            frame_difference(&a, &b, 0.1),
+            Some("velocities are in the first frame but not in the second".to_string())
+        );
+        b.velocities = a.velocities.clone();
+        b.step = 5;
+        assert_eq!(
+            frame_difference(&a, &b, 1.0),
+            Some("step is 4 and 5".to_string())
+        );
+        b.step = 4;
+        b.time = f32::NAN;
+        assert!(frame_difference(&a, &b, f32::INFINITY).is_some());
+        b.time = 0.0;
+        b.resize(2);
+        assert_eq!(
+            frame_difference(&a, &b, 0.0),
+            Some("coords has 3 and 2 atoms".to_string())
+        );
+    }
+}
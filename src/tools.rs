@@ -0,0 +1,984 @@
+//! Trajectory-level processing that reads from one trajectory and writes to another
+use crate::*;
+
+/// Read the next frame of `traj`, returning `Ok(false)` at the end of the file
+fn read_next(traj: &mut impl Trajectory, frame: &mut Frame) -> Result<bool> {
+    match traj.read(frame) {
+        Ok(()) => Ok(true),
+        Err(e) if e.is_eof() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Resample `src` to frames spaced exactly `dt` apart and write them to `dst`
+///
+/// Output frames start at the time of the first source frame and are linearly
+/// interpolated (see `Frame::interpolate`) between the two source frames
+/// surrounding them. No frames are extrapolated past the last source frame.
+/// Source frames are expected to be ordered by time; frames that do not
+/// advance the time are ignored. Returns the number of frames written.
+pub fn resample(src: &mut impl Trajectory, dst: &mut impl Trajectory, dt: f32) -> Result<usize> {
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err(Error::InvalidArgument {
+            name: "dt",
+            value: dt.to_string(),
+        });
+    }
+
+    let mut prev = Frame::with_len(src.get_num_atoms()?);
+    if !read_next(src, &mut prev)? {
+        return Ok(0);
+    }
+    let mut next = prev.clone();
+    let start = prev.time;
+    let mut written = 0;
+
+    loop {
+        let target = start + written as f32 * dt;
+        if target <= next.time {
+            let t = if next.time > prev.time {
+                (target - prev.time) / (next.time - prev.time)
+            } else {
+                1.0
+            };
+            dst.write(&Frame::interpolate(&prev, &next, t.clamp(0.0, 1.0))?)?;
+            written += 1;
+            continue;
+        }
+
+        // Advance to the next source frame that moves forward in time
+        std::mem::swap(&mut prev, &mut next);
+        loop {
+            if !read_next(src, &mut next)? {
+                return Ok(written);
+            }
+            if next.time > prev.time {
+                break;
+            }
+        }
+    }
+}
+
+/// Write about `target_frames` frames of `src`, evenly spread over the whole
+/// trajectory, to `dst`
+///
+/// This thins a trajectory that is too large to visualize or analyze in full.
+/// The frames are counted first with `Trajectory::num_frames`, and frame
+/// `i * num_frames / target_frames` is copied for each `i` below
+/// `target_frames`, which always includes the first frame and spaces the
+/// others by a stride of `num_frames / target_frames` on average. Frames in
+/// between are skipped by seeking (`Trajectory::read_nth`), without decoding
+/// them. A trajectory with no more than `target_frames` frames is copied
+/// whole. Returns the number of frames written, or `Error::InvalidArgument`
+/// if `target_frames` is zero.
+pub fn decimate_to(
+    src: &mut impl Trajectory,
+    dst: &mut impl Trajectory,
+    target_frames: usize,
+) -> Result<usize> {
+    if target_frames == 0 {
+        return Err(Error::InvalidArgument {
+            name: "target_frames",
+            value: target_frames.to_string(),
+        });
+    }
+    let num_frames = src.num_frames()?;
+    let picked = target_frames.min(num_frames);
+    for i in 0..picked {
+        // In u128, so that the product cannot overflow
+        let n = (i as u128 * num_frames as u128 / picked as u128) as usize;
+        dst.write(&src.read_nth(n)?)?;
+    }
+    Ok(picked)
+}
+
+/// Remove center of mass motion from `src`, writing the result to `dst`
+///
+/// Every frame is translated so that its (mass-weighted, if `masses` is
+/// given) center of mass lies at the origin. See `Frame::center_of_mass`.
+/// Returns the number of frames written.
+pub fn remove_com_motion(
+    src: &mut impl Trajectory,
+    dst: &mut impl Trajectory,
+    masses: Option<&[f32]>,
+) -> Result<usize> {
+    let mut frame = Frame::with_len(src.get_num_atoms()?);
+    let mut written = 0;
+    while read_next(src, &mut frame)? {
+        let com = frame.center_of_mass(masses)?;
+        frame.translate([-com[0], -com[1], -com[2]]);
+        dst.write(&frame)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Copy `src` to `dst`, replacing the step and time of every frame
+///
+/// Frame `i` gets step `i` and time `start_time + i * dt`; everything else,
+/// including the coordinates, is copied unchanged. This salvages trajectories
+/// whose writer left the step and time zero or otherwise broken. Returns the
+/// number of frames whose step or time was changed.
+pub fn repair_timestamps(
+    src: &mut impl Trajectory,
+    dst: &mut impl Trajectory,
+    dt: f32,
+    start_time: f32,
+) -> Result<usize> {
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err(Error::InvalidArgument {
+            name: "dt",
+            value: dt.to_string(),
+        });
+    }
+    if !start_time.is_finite() {
+        return Err(Error::InvalidArgument {
+            name: "start_time",
+            value: start_time.to_string(),
+        });
+    }
+
+    let mut frame = Frame::with_len(src.get_num_atoms()?);
+    let mut step = 0;
+    let mut fixed = 0;
+    while read_next(src, &mut frame)? {
+        let time = (f64::from(start_time) + step as f64 * f64::from(dt)) as f32;
+        if frame.step != step || frame.time != time {
+            frame.step = step;
+            frame.time = time;
+            fixed += 1;
+        }
+        dst.write(&frame)?;
+        step += 1;
+    }
+    Ok(fixed)
+}
+
+/// Merge two trajectories of the same system into `dst`, ordered by time
+///
+/// Both inputs must be ordered by time themselves. Whenever both contain a
+/// frame with the same time, only the frame from `a` is written. Returns the
+/// number of frames written.
+pub fn merge_by_time(
+    a: &mut impl Trajectory,
+    b: &mut impl Trajectory,
+    dst: &mut impl Trajectory,
+) -> Result<usize> {
+    let num_atoms = a.get_num_atoms()?;
+    let num_atoms_b = b.get_num_atoms()?;
+    if num_atoms != num_atoms_b {
+        return Err(Error::WrongSizeFrame {
+            expected: num_atoms,
+            found: num_atoms_b,
+        });
+    }
+
+    let mut frame_a = Frame::with_len(num_atoms);
+    let mut frame_b = Frame::with_len(num_atoms);
+    let mut has_a = read_next(a, &mut frame_a)?;
+    let mut has_b = read_next(b, &mut frame_b)?;
+    let mut written = 0;
+
+    while has_a || has_b {
+        if has_a && (!has_b || frame_a.time <= frame_b.time) {
+            if has_b && frame_a.time == frame_b.time {
+                has_b = read_next(b, &mut frame_b)?;
+            }
+            dst.write(&frame_a)?;
+            has_a = read_next(a, &mut frame_a)?;
+        } else {
+            dst.write(&frame_b)?;
+            has_b = read_next(b, &mut frame_b)?;
+        }
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Sizes of the input and output of `compress_trr_to_xtc`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionReport {
+    /// Number of frames converted
+    pub frames: usize,
+    /// Size of the TRR input in bytes
+    pub input_bytes: u64,
+    /// Size of the XTC output in bytes
+    pub output_bytes: u64,
+}
+
+impl CompressionReport {
+    /// Size of the output relative to the input, e.g. 0.25 for a fourfold reduction
+    pub fn ratio(&self) -> f64 {
+        self.output_bytes as f64 / self.input_bytes as f64
+    }
+}
+
+/// Convert the TRR trajectory at `src` to an XTC trajectory at `dst`
+///
+/// Coordinates are compressed at `precision` (1000.0 keeps 3 decimal places,
+/// i.e. 0.001 nm); velocities, forces and lambda are dropped, as XTC cannot
+/// store them. Every frame of `src` must contain coordinates.
+pub fn compress_trr_to_xtc(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    precision: f32,
+) -> Result<CompressionReport> {
+    if !(precision > 0.0 && precision.is_finite()) {
+        return Err(Error::InvalidArgument {
+            name: "precision",
+            value: precision.to_string(),
+        });
+    }
+
+    let mut trr = TRRTrajectory::open_read(src)?;
+    let mut xtc = XTCTrajectory::open_write(dst)?;
+    xtc.set_precision(precision);
+
+    let num_atoms = trr.get_num_atoms()?;
+    let mut frame = Frame::with_len(num_atoms);
+    let mut frames = 0;
+    while read_next(&mut trr, &mut frame)? {
+        if frame.coords.is_empty() {
+            return Err(Error::MissingData {
+                name: "coordinates",
+            });
+        }
+        xtc.write(&frame)?;
+        frames += 1;
+    }
+    xtc.flush()?;
+
+    Ok(CompressionReport {
+        frames,
+        input_bytes: trr.try_tell()?,
+        output_bytes: xtc.try_tell()?,
+    })
+}
+
+/// Convert the trajectory at `src`, in `src_format`, to a new file `dst` in
+/// `dst_format`
+///
+/// The formats are given explicitly rather than detected, so the files may
+/// have any extension; see `Format::from_path` and `Format::detect` to find
+/// them. XTC output keeps the precision of an XTC `src`, and TRR output
+/// keeps every block each frame has. Data XTC cannot store is dropped, and
+/// every frame must contain coordinates when writing XTC. Returns the number
+/// of frames written.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let dst = dir.path().join("out.trr");
+/// let dst_format: Format = "trr".parse()?;
+/// let frames = convert_trajectory("tests/1l2y.xtc", Format::Xtc, &dst, dst_format)?;
+/// assert_eq!(frames, 38);
+/// # Ok(())
+/// # }
+/// ```
+pub fn convert_trajectory(
+    src: impl AsRef<Path>,
+    src_format: Format,
+    dst: impl AsRef<Path>,
+    dst_format: Format,
+) -> Result<usize> {
+    let mut src = src_format.open(src, FileMode::Read)?;
+    let dst = dst.as_ref();
+    match dst_format {
+        Format::Xtc => {
+            let mut xtc = XTCTrajectory::open_write(dst)?;
+            let written = convert_into(&mut src, &mut xtc, |_, frame| {
+                if frame.coords.is_empty() {
+                    return Err(Error::MissingData {
+                        name: "coordinates",
+                    });
+                }
+                Ok(())
+            })?;
+            xtc.close()?;
+            Ok(written)
+        }
+        Format::Trr => {
+            let mut trr = TRRTrajectory::open_write(dst)?;
+            let written = convert_into(&mut src, &mut trr, |trr, frame| {
+                trr.set_blocks(blocks_of(frame));
+                Ok(())
+            })?;
+            trr.close()?;
+            Ok(written)
+        }
+    }
+}
+
+/// Concatenate the trajectories `parts`, in order, into a new file `dst`
+///
+/// Formats are detected from the file extensions, and all parts must have the
+/// format of `dst`. Unless `allow_discontinuities` is set, each part must
+/// continue in time from the one before it: its first frame must come after
+/// the last frame of the previous part, but by no more than one and a half
+/// times the spacing of the previous part's last two frames. Otherwise this
+/// fails with `Error::Discontinuity`, leaving the frames written so far in
+/// `dst`. XTC frames are written with the precision of the first part, and
+/// TRR frames keep all the blocks they have. Returns the number of frames
+/// written.
+pub fn concat_trajectories(
+    parts: &[PathBuf],
+    dst: impl AsRef<Path>,
+    allow_discontinuities: bool,
+) -> Result<usize> {
+    let dst = dst.as_ref();
+    let format = Format::from_path(dst)?;
+    for part in parts {
+        let found = Format::from_path(part)?;
+        if found != format {
+            return Err(Error::FormatMismatch {
+                path: part.clone(),
+                expected: format,
+                found,
+            });
+        }
+    }
+
+    match format {
+        Format::Xtc => {
+            let mut xtc = XTCTrajectory::open_write(dst)?;
+            let written = concat_into(parts, &mut xtc, allow_discontinuities, |_, _| {})?;
+            xtc.close()?;
+            Ok(written)
+        }
+        Format::Trr => {
+            let mut trr = TRRTrajectory::open_write(dst)?;
+            let written = concat_into(parts, &mut trr, allow_discontinuities, |trr, frame| {
+                trr.set_blocks(blocks_of(frame))
+            })?;
+            trr.close()?;
+            Ok(written)
+        }
+    }
+}
+
+/// Split `src` into `num_parts` files of consecutive frames
+///
+/// The frames are counted first (see `Trajectory::num_frames`) and divided as
+/// evenly as possible, with earlier parts getting one frame more if they do
+/// not divide exactly. Each part is read from its first frame on, found with
+/// the frame index built while counting (see `Trajectory::read_frame_at`). If there are fewer frames than parts, the last parts
+/// are empty. Part `i`, counting from zero, is written to `out_pattern` with
+/// its single `%d` placeholder replaced by `i`; for zero padding, use a width
+/// as in `out_%03d.xtc`. The parts have the format of `src`, detected from the
+/// file extensions, and keep its XTC precision or the TRR blocks of each
+/// frame. Returns the paths of the parts in order.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let pattern = dir.path().join("part_%02d.xtc");
+/// # let pattern = pattern.to_str().unwrap();
+/// let parts = split_trajectory("tests/1l2y.xtc", pattern, 3)?;
+/// assert!(parts[2].ends_with("part_02.xtc"));
+/// # Ok(())
+/// # }
+/// ```
+pub fn split_trajectory(
+    src: impl AsRef<Path>,
+    out_pattern: &str,
+    num_parts: usize,
+) -> Result<Vec<PathBuf>> {
+    let src = src.as_ref();
+    if num_parts == 0 {
+        return Err(Error::InvalidArgument {
+            name: "num_parts",
+            value: num_parts.to_string(),
+        });
+    }
+    let paths = (0..num_parts)
+        .map(|i| expand_pattern(out_pattern, i).map(PathBuf::from))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Error::InvalidArgument {
+            name: "out_pattern",
+            value: out_pattern.to_string(),
+        })?;
+    let format = Format::from_path(src)?;
+    let found = Format::from_path(&paths[0])?;
+    if found != format {
+        return Err(Error::FormatMismatch {
+            path: paths[0].clone(),
+            expected: format,
+            found,
+        });
+    }
+
+    let mut src = format.open(src, FileMode::Read)?;
+    let num_frames = src.num_frames()?;
+    let sizes: Vec<usize> = (0..num_parts)
+        .map(|i| num_frames / num_parts + usize::from(i < num_frames % num_parts))
+        .collect();
+    match format {
+        Format::Xtc => split_into(
+            &mut src,
+            &paths,
+            &sizes,
+            |path| XTCTrajectory::open_write(path),
+            |_, _| {},
+        ),
+        Format::Trr => split_into(
+            &mut src,
+            &paths,
+            &sizes,
+            |path| TRRTrajectory::open_write(path),
+            |trr, frame| trr.set_blocks(blocks_of(frame)),
+        ),
+    }?;
+    Ok(paths)
+}
+
+/// Replace the single `%d` or `%0Nd` placeholder in `pattern` with `i`
+fn expand_pattern(pattern: &str, i: usize) -> Option<String> {
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d')?;
+    let width = &rest[..end];
+    if !width.chars().all(|c| c.is_ascii_digit()) || rest[end + 1..].contains('%') {
+        return None;
+    }
+    let number = match width.strip_prefix('0') {
+        Some(width) => format!("{:0width$}", i, width = width.parse().unwrap_or(0)),
+        None => format!("{:width$}", i, width = width.parse().unwrap_or(0)),
+    };
+    Some(format!(
+        "{}{}{}",
+        &pattern[..start],
+        number,
+        &rest[end + 1..]
+    ))
+}
+
+/// Write `sizes[i]` frames of `src`, from the first frame of part `i` on, to
+/// `paths[i]` for every part, calling `prepare` before each write
+fn split_into<T: Trajectory>(
+    src: &mut Box<dyn Trajectory>,
+    paths: &[PathBuf],
+    sizes: &[usize],
+    open: impl Fn(&Path) -> Result<T>,
+    prepare: impl Fn(&mut T, &Frame),
+) -> Result<()> {
+    let mut frame = Frame::with_len(src.get_num_atoms()?);
+    let mut start = 0;
+    for (path, &size) in paths.iter().zip(sizes) {
+        let mut dst = open(path)?;
+        dst.copy_settings_from(src)?;
+        for k in start..start + size {
+            // Each part starts with a seek through the frame index
+            if k == start {
+                src.read_frame_at(k, &mut frame)?;
+            } else {
+                src.read(&mut frame)?;
+            }
+            prepare(&mut dst, &frame);
+            dst.write(&frame)?;
+        }
+        dst.flush()?;
+        start += size;
+    }
+    Ok(())
+}
+
+/// Write all frames of `src` to `dst`, calling `prepare` before each write
+fn convert_into<T: Trajectory>(
+    src: &mut Box<dyn Trajectory>,
+    dst: &mut T,
+    prepare: impl Fn(&mut T, &Frame) -> Result<()>,
+) -> Result<usize> {
+    dst.copy_settings_from(src)?;
+    let mut frame = Frame::with_len(src.get_num_atoms()?);
+    let mut written = 0;
+    while read_next(src, &mut frame)? {
+        prepare(dst, &frame)?;
+        dst.write(&frame)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// The TRR blocks holding data in `frame`
+fn blocks_of(frame: &Frame) -> TrrBlocks {
+    let mut blocks = TrrBlocks::LAMBDA;
+    blocks.set(TrrBlocks::X, !frame.coords.is_empty());
+    blocks.set(TrrBlocks::V, frame.velocities.is_some());
+    blocks.set(TrrBlocks::F, frame.forces.is_some());
+    blocks.set(TrrBlocks::BOX, frame.box_vector != [[0.0; 3]; 3]);
+    blocks
+}
+
+/// Write all frames of `parts` to `dst`, calling `prepare` before each write
+fn concat_into<T: Trajectory>(
+    parts: &[PathBuf],
+    dst: &mut T,
+    allow_discontinuities: bool,
+    prepare: impl Fn(&mut T, &Frame),
+) -> Result<usize> {
+    let mut written = 0;
+    // Time of the last frame written, and its spacing from the one before
+    let mut last: Option<(f32, Option<f32>)> = None;
+    for (i, part) in parts.iter().enumerate() {
+        let format = Format::from_path(part)?;
+        let mut src = format.open(part, FileMode::Read)?;
+        if i == 0 {
+            dst.copy_settings_from(&mut src)?;
+        }
+        let mut frame = Frame::with_len(src.get_num_atoms()?);
+        let mut first = true;
+        while read_next(&mut src, &mut frame)? {
+            if let (true, false, Some((previous_time, spacing))) =
+                (first, allow_discontinuities, last)
+            {
+                let step = frame.time - previous_time;
+                if step <= 0.0 || spacing.is_some_and(|spacing| step > 1.5 * spacing) {
+                    return Err(Error::Discontinuity {
+                        path: part.clone(),
+                        previous_time,
+                        time: frame.time,
+                    });
+                }
+            }
+            first = false;
+            last = Some((
+                frame.time,
+                last.map(|(previous_time, _)| frame.time - previous_time),
+            ));
+
+            prepare(dst, &frame);
+            dst.write(&frame)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    /// Write one single-atom frame per time to a TRR file, with x = time
+    fn write_times(path: &Path, times: &[f32]) -> Result<()> {
+        let mut traj = TRRTrajectory::open_write(path)?;
+        for (step, &time) in times.iter().enumerate() {
+            let frame = Frame {
+                step,
+                time,
+                box_vector: [[0.0; 3]; 3],
+                coords: vec![[time, 0.0, 0.0]],
+                ..Default::default()
+            };
+            traj.write(&frame)?;
+        }
+        traj.flush()
+    }
+
+    #[test]
+    fn test_repair_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+        let src_file = NamedTempFile::new()?;
+        let dst_file = NamedTempFile::new()?;
+        let mut src = XTCTrajectory::open_write(src_file.path())?;
+        for i in 0..5 {
+            let frame = Frame {
+                coords: vec![[i as f32, 0.0, 0.0]],
+                ..Default::default()
+            };
+            src.write(&frame)?;
+        }
+        src.close()?;
+
+        let mut src = XTCTrajectory::open_read(src_file.path())?;
+        let mut dst = XTCTrajectory::open_write(dst_file.path())?;
+        // The first frame already has step 0 and time 0
+        assert_eq!(repair_timestamps(&mut src, &mut dst, 0.5, 0.0)?, 4);
+        dst.close()?;
+
+        let dst = XTCTrajectory::open_read(dst_file.path())?;
+        let frames: Result<Vec<_>> = dst.into_iter().map(|f| f.map(|f| (*f).clone())).collect();
+        let frames = frames?;
+        assert_eq!(frames.len(), 5);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.step, i);
+            assert_eq!(frame.time, 0.5 * i as f32);
+            assert_eq!(frame.coords, vec![[i as f32, 0.0, 0.0]]);
+        }
+
+        // Shifting the start time changes every frame
+        let mut src = XTCTrajectory::open_read(dst_file.path())?;
+        let mut dst = XTCTrajectory::open_write(src_file.path())?;
+        assert_eq!(repair_timestamps(&mut src, &mut dst, 0.5, 10.0)?, 5);
+
+        let result = repair_timestamps(&mut src, &mut dst, 0.0, 0.0);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidArgument { name: "dt", .. })
+        ));
+        let result = repair_timestamps(&mut src, &mut dst, 1.0, f32::NAN);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidArgument {
+                name: "start_time",
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimate_to() -> Result<(), Box<dyn std::error::Error>> {
+        let dst_file = NamedTempFile::new()?;
+        let mut src = XTCTrajectory::open_read("tests/1l2y.xtc")?;
+        let mut dst = XTCTrajectory::open_write(dst_file.path())?;
+        assert_eq!(decimate_to(&mut src, &mut dst, 10)?, 10);
+        dst.close()?;
+
+        let dst = XTCTrajectory::open_read(dst_file.path())?;
+        let steps: Result<Vec<_>> = dst.into_iter().map(|f| f.map(|f| f.step)).collect();
+        assert_eq!(steps?, vec![1, 4, 8, 12, 16, 20, 23, 27, 31, 35]);
+
+        // Fewer frames than requested are all copied
+        let mut src = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+        assert_eq!(decimate_to(&mut src, &mut dst, 500)?, 38);
+        dst.close()?;
+        assert_eq!(TRRTrajectory::open_read(dst_file.path())?.num_frames()?, 38);
+
+        let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+        assert!(matches!(
+            decimate_to(&mut src, &mut dst, 0),
+            Err(Error::InvalidArgument {
+                name: "target_frames",
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_trajectory() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let pattern = dir.path().join("out_%03d.xtc");
+        let parts = split_trajectory("tests/1l2y.xtc", pattern.to_str().unwrap(), 4)?;
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[3], dir.path().join("out_003.xtc"));
+
+        let mut steps = Vec::new();
+        let mut sizes = Vec::new();
+        for part in &parts {
+            let mut xtc = XTCTrajectory::open_read(part)?;
+            sizes.push(xtc.num_frames()?);
+            for frame in xtc {
+                steps.push(frame?.step);
+            }
+        }
+        assert_eq!(sizes, vec![10, 10, 9, 9]);
+        assert_eq!(steps, (1..=38).collect::<Vec<_>>());
+
+        // The precision of the source is kept
+        let mut xtc = XTCTrajectory::open_read(&parts[2])?;
+        xtc.read(&mut Frame::with_len(304))?;
+        assert_eq!(xtc.read_precision(), Some(10000.0));
+
+        // TRR frames keep their blocks
+        let src = dir.path().join("src.trr");
+        write_part(&src, &[0.0, 1.0, 2.0])?;
+        let pattern = dir.path().join("out%d.trr");
+        let parts = split_trajectory(&src, pattern.to_str().unwrap(), 2)?;
+        let frames: Result<Vec<_>> = TRRTrajectory::open_read(&parts[1])?.into_iter().collect();
+        let frames = frames?;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, 2.0);
+        assert_eq!(frames[0].velocities, Some(vec![[1.0, 2.0, 3.0]; 2]));
+
+        for bad in ["out.xtc", "out_%d_%d.xtc", "out_%x.xtc"].iter() {
+            let result = split_trajectory("tests/1l2y.xtc", bad, 2);
+            assert!(matches!(
+                result,
+                Err(Error::InvalidArgument {
+                    name: "out_pattern",
+                    ..
+                })
+            ));
+        }
+        let result = split_trajectory("tests/1l2y.xtc", "out_%d.trr", 2);
+        assert!(matches!(result, Err(Error::FormatMismatch { .. })));
+        let result = split_trajectory("tests/1l2y.xtc", "out_%d.xtc", 0);
+        assert!(matches!(result, Err(Error::InvalidArgument { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_by_time() -> Result<(), Box<dyn std::error::Error>> {
+        let a_file = NamedTempFile::new()?;
+        let b_file = NamedTempFile::new()?;
+        let dst_file = NamedTempFile::new()?;
+        write_times(a_file.path(), &[0.0, 1.0, 2.0, 3.0])?;
+        write_times(b_file.path(), &[2.5, 3.0, 4.0])?;
+
+        let mut a = TRRTrajectory::open_read(a_file.path())?;
+        let mut b = TRRTrajectory::open_read(b_file.path())?;
+        let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+        assert_eq!(merge_by_time(&mut a, &mut b, &mut dst)?, 6);
+        dst.flush()?;
+
+        let dst = TRRTrajectory::open_read(dst_file.path())?;
+        let times: Result<Vec<f32>> = dst.into_iter().map(|f| f.map(|f| f.time)).collect();
+        assert_eq!(times?, vec![0.0, 1.0, 2.0, 2.5, 3.0, 4.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_by_time_wrong_size() -> Result<(), Box<dyn std::error::Error>> {
+        let b_file = NamedTempFile::new()?;
+        let dst_file = NamedTempFile::new()?;
+        let mut a = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        write_times(b_file.path(), &[0.0])?;
+
+        let mut b = TRRTrajectory::open_read(b_file.path())?;
+        let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+        let result = merge_by_time(&mut a, &mut b, &mut dst);
+        assert!(matches!(
+            result,
+            Err(Error::WrongSizeFrame {
+                expected: 304,
+                found: 1
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_com_motion() -> Result<(), Box<dyn std::error::Error>> {
+        let src_file = NamedTempFile::new()?;
+        let dst_file = NamedTempFile::new()?;
+        let mut src = TRRTrajectory::open_write(src_file.path())?;
+        for step in 0..5 {
+            let shift = step as f32;
+            let frame = Frame {
+                step,
+                time: shift,
+                box_vector: [[0.0; 3]; 3],
+                coords: vec![[shift, 1.0, 0.0], [2.0 * shift, 0.0, 3.0]],
+                ..Default::default()
+            };
+            src.write(&frame)?;
+        }
+        src.flush()?;
+
+        let masses = [1.0, 3.0];
+        for masses in [None, Some(&masses[..])] {
+            let mut src = TRRTrajectory::open_read(src_file.path())?;
+            let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+            assert_eq!(remove_com_motion(&mut src, &mut dst, masses)?, 5);
+            dst.flush()?;
+
+            let dst = TRRTrajectory::open_read(dst_file.path())?;
+            for frame in dst.into_iter() {
+                let com = frame?.center_of_mass(masses)?;
+                for x in com.iter() {
+                    assert_approx_eq!(x, 0.0);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_trr_to_xtc() -> Result<()> {
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        let report = compress_trr_to_xtc("tests/1l2y.trr", tempfile.path(), 100.0)?;
+        assert_eq!(report.frames, 38);
+        assert_eq!(
+            report.input_bytes,
+            std::fs::metadata("tests/1l2y.trr").unwrap().len()
+        );
+        assert!(report.ratio() < 0.25, "{:?}", report);
+
+        let mut trr = TRRTrajectory::open_read("tests/1l2y.trr")?;
+        let mut xtc = XTCTrajectory::open_read(tempfile.path())?;
+        let mut expected = Frame::with_len(304);
+        let mut found = Frame::with_len(304);
+        while read_next(&mut trr, &mut expected)? {
+            xtc.read(&mut found)?;
+            assert_eq!(found.step, expected.step);
+            for (x, y) in found.coords.iter().zip(&expected.coords) {
+                for k in 0..3 {
+                    assert!((x[k] - y[k]).abs() <= 0.5 / 100.0 + 1e-5);
+                }
+            }
+        }
+
+        let result = compress_trr_to_xtc("tests/1l2y.trr", tempfile.path(), 0.0);
+        assert!(matches!(result, Err(Error::InvalidArgument { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_irregular() -> Result<(), Box<dyn std::error::Error>> {
+        let src_file = NamedTempFile::new()?;
+        let dst_file = NamedTempFile::new()?;
+        write_times(src_file.path(), &[0.0, 1.0, 3.0, 4.0])?;
+
+        let mut src = TRRTrajectory::open_read(src_file.path())?;
+        let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+        let written = resample(&mut src, &mut dst, 0.5)?;
+        dst.flush()?;
+        assert_eq!(written, 9);
+
+        let dst = TRRTrajectory::open_read(dst_file.path())?;
+        let frames: Result<Vec<_>> = dst.into_iter().collect();
+        let frames = frames?;
+        assert_eq!(frames.len(), 9);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_approx_eq!(frame.time, i as f32 * 0.5);
+            assert_approx_eq!(frame[0][0], frame.time);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_no_extrapolation() -> Result<(), Box<dyn std::error::Error>> {
+        let src_file = NamedTempFile::new()?;
+        let dst_file = NamedTempFile::new()?;
+        write_times(src_file.path(), &[0.0, 1.0, 2.5])?;
+
+        let mut src = TRRTrajectory::open_read(src_file.path())?;
+        let mut dst = TRRTrajectory::open_write(dst_file.path())?;
+        assert_eq!(resample(&mut src, &mut dst, 1.0)?, 3);
+
+        let mut src = TRRTrajectory::open_read(src_file.path())?;
+        assert!(matches!(
+            resample(&mut src, &mut dst, 0.0),
+            Err(Error::InvalidArgument { name: "dt", .. })
+        ));
+        Ok(())
+    }
+
+    /// Write one frame per time to `path`, in the format of its extension,
+    /// with velocities for TRR
+    fn write_part(path: &Path, times: &[f32]) -> Result<()> {
+        let mut traj: Box<dyn Trajectory> = match Format::from_path(path)? {
+            Format::Xtc => Box::new(XTCTrajectory::open_write(path)?),
+            Format::Trr => {
+                let mut trr = TRRTrajectory::open_write(path)?;
+                trr.set_blocks(TrrBlocks::X | TrrBlocks::V);
+                Box::new(trr)
+            }
+        };
+        for (step, &time) in times.iter().enumerate() {
+            let frame = Frame {
+                step,
+                time,
+                coords: vec![[time, 0.0, 0.0]; 2],
+                velocities: Some(vec![[1.0, 2.0, 3.0]; 2]),
+                ..Default::default()
+            };
+            traj.write(&frame)?;
+        }
+        traj.flush()
+    }
+
+    #[test]
+    fn test_concat_trajectories() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let part = |name: &str| dir.path().join(name);
+        write_part(&part("a.xtc"), &[0.0, 1.0, 2.0])?;
+        write_part(&part("b.xtc"), &[3.0, 4.0])?;
+        write_part(&part("overlap.xtc"), &[1.5, 2.5])?;
+        write_part(&part("gap.xtc"), &[5.0])?;
+        write_part(&part("a.trr"), &[0.0, 1.0])?;
+        write_part(&part("b.trr"), &[2.0])?;
+
+        let parts = [part("a.xtc"), part("b.xtc")];
+        assert_eq!(concat_trajectories(&parts, part("out.xtc"), false)?, 5);
+        let out = XTCTrajectory::open_read(part("out.xtc"))?;
+        let times: Result<Vec<f32>> = out.into_iter().map(|f| Ok(f?.time)).collect();
+        assert_eq!(times?, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+
+        // XTC frames keep the precision of the input
+        let parts = [PathBuf::from("tests/1l2y.xtc")];
+        assert_eq!(concat_trajectories(&parts, part("out.xtc"), false)?, 38);
+        let mut out = XTCTrajectory::open_read(part("out.xtc"))?;
+        out.read_owned()?;
+        assert_eq!(out.read_precision(), Some(10000.0));
+
+        // TRR frames keep their velocities
+        let parts = [part("a.trr"), part("b.trr")];
+        assert_eq!(concat_trajectories(&parts, part("out.trr"), false)?, 3);
+        let out = TRRTrajectory::open_read(part("out.trr"))?;
+        for frame in out {
+            assert_eq!(frame?.velocities, Some(vec![[1.0, 2.0, 3.0]; 2]));
+        }
+
+        for (bad, num_frames) in [("overlap.xtc", 5), ("gap.xtc", 4)] {
+            let parts = [part("a.xtc"), part(bad)];
+            let err = concat_trajectories(&parts, part("out.xtc"), false).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::Discontinuity { previous_time, .. } if previous_time == 2.0
+            ));
+            let written = concat_trajectories(&parts, part("out.xtc"), true)?;
+            assert_eq!(written, num_frames);
+        }
+
+        let parts = [part("a.xtc"), part("b.trr")];
+        assert!(matches!(
+            concat_trajectories(&parts, part("out.xtc"), false),
+            Err(Error::FormatMismatch {
+                expected: Format::Xtc,
+                found: Format::Trr,
+                ..
+            })
+        ));
+        assert!(matches!(
+            concat_trajectories(&parts, part("out.dcd"), false),
+            Err(Error::UnknownFormat { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_trajectory() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name);
+        write_part(&path("in.trr"), &[0.0, 1.0, 2.0])?;
+
+        // Formats come from the enum, not the extensions
+        let formats: Vec<Format> = ["xtc", "TRR"]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<_>>()?;
+        assert_eq!(formats, vec![Format::Xtc, Format::Trr]);
+        for &format in &formats {
+            let dst = path(&format!("out_{}.dat", format));
+            assert_eq!(
+                convert_trajectory(path("in.trr"), Format::Trr, &dst, format)?,
+                3
+            );
+            assert_eq!(Format::detect(&dst)?, format);
+
+            let mut out = format.open(&dst, FileMode::Read)?;
+            let mut frame = Frame::with_len(2);
+            out.read(&mut frame)?;
+            assert_eq!(frame.coords, vec![[0.0, 0.0, 0.0]; 2]);
+            let velocities = (format == Format::Trr).then(|| vec![[1.0, 2.0, 3.0]; 2]);
+            assert_eq!(frame.velocities, velocities);
+        }
+
+        // XTC output keeps the precision of XTC input
+        convert_trajectory("tests/1l2y.xtc", Format::Xtc, path("a.xtc"), Format::Xtc)?;
+        let mut xtc = XTCTrajectory::open_read(path("a.xtc"))?;
+        assert_eq!(xtc.write_settings()?.precision, Some(10000.0));
+
+        // A wrong source format is not silently accepted
+        let result = convert_trajectory("tests/1l2y.xtc", Format::Trr, path("d.xtc"), Format::Xtc);
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Composable transformations of frames, applied while iterating
+use crate::*;
+
+/// A transformation applied to every frame of a trajectory
+///
+/// Pass a transform to `TrajectoryIterator::transform` to apply it to each
+/// frame before it is yielded; transforms can be chained by calling
+/// `transform` again on the result, and are applied in the order they were
+/// added. Besides the transforms in this module and `CenterMode`, any
+/// closure taking `&mut Frame` and returning `Result<()>` is a transform.
+pub trait FrameTransform {
+    /// Transform the frame in place
+    fn apply(&self, frame: &mut Frame) -> Result<()>;
+}
+
+impl<F> FrameTransform for F
+where
+    F: Fn(&mut Frame) -> Result<()>,
+{
+    fn apply(&self, frame: &mut Frame) -> Result<()> {
+        self(frame)
+    }
+}
+
+/// Center the frame, see `Frame::center`
+impl FrameTransform for CenterMode {
+    fn apply(&self, frame: &mut Frame) -> Result<()> {
+        frame.center(*self)
+    }
+}
+
+/// Wrap all atoms into the primary unit cell of the box
+///
+/// As for the other periodic operations, the box is assumed to be
+/// lower-triangular, and dimensions with a box length of zero are left as
+/// they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wrap;
+
+impl FrameTransform for Wrap {
+    fn apply(&self, frame: &mut Frame) -> Result<()> {
+        for i in 0..frame.len() {
+            frame.coords[i] = frame.wrap_position(frame.coords[i]);
+        }
+        Ok(())
+    }
+}
+
+/// Wrap all atoms into the cell centered on a point, see `Frame::wrap_around`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WrapAround(pub [f32; 3]);
+
+impl FrameTransform for WrapAround {
+    fn apply(&self, frame: &mut Frame) -> Result<()> {
+        frame.wrap_around(self.0);
+        Ok(())
+    }
+}
+
+/// Scale the coordinates and the box by a factor, see `Frame::scale`
+///
+/// `Scale(10.0)` converts from the nanometers of GROMACS to Ångström.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale(pub f32);
+
+impl FrameTransform for Scale {
+    fn apply(&self, frame: &mut Frame) -> Result<()> {
+        frame.scale(self.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transforms() -> Result<()> {
+        let mut frame = Frame::with_len(2);
+        frame.box_vector = [[2.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]];
+        frame[0] = [5.0, -0.5, 1.0];
+        frame[1] = [1.0, 1.5, 1.0];
+
+        Wrap.apply(&mut frame)?;
+        assert_eq!(frame.coords, vec![[1.0, 1.5, 1.0], [1.0, 1.5, 1.0]]);
+        WrapAround([0.0; 3]).apply(&mut frame)?;
+        assert_eq!(frame.coords, vec![[-1.0, -0.5, -1.0]; 2]);
+        Scale(10.0).apply(&mut frame)?;
+        assert_eq!(frame[0], [-10.0, -5.0, -10.0]);
+        assert_eq!(frame.box_vector[2][2], 20.0);
+        CenterMode::Atom(1).apply(&mut frame)?;
+        assert_eq!(frame[0], [0.0; 3]);
+
+        let shift = |frame: &mut Frame| {
+            frame.translate([1.0, 0.0, 0.0]);
+            Ok(())
+        };
+        shift.apply(&mut frame)?;
+        assert_eq!(frame[1], [1.0, 0.0, 0.0]);
+        Ok(())
+    }
+}
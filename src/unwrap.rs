@@ -0,0 +1,131 @@
+//! Unwrapping of coordinates across periodic boundaries over a trajectory
+use crate::*;
+
+/// Stateful helper that unwraps coordinates over consecutive frames
+///
+/// Feed the frames of a trajectory to `unwrap` in order. For every atom, the
+/// unwrapper keeps an image flag counting how many times the atom crossed
+/// each periodic boundary, and shifts its coordinates by that many box vectors,
+/// so that atoms move continuously instead of jumping across the box. This is
+/// what mean squared displacement and diffusion analyses need.
+///
+/// A crossing is detected when an atom moves by more than half a box length
+/// between two frames, so the frames must be close enough in time that no atom
+/// really moves that far. Like the rest of the crate, the box vectors are
+/// assumed to form a lower-triangular matrix. The first frame is left as it is.
+#[derive(Debug, Clone, Default)]
+pub struct Unwrapper {
+    /// Wrapped coordinates of the previous frame
+    prev: Option<Vec<[f32; 3]>>,
+    /// Number of boundary crossings along each box vector, per atom
+    images: Vec<[i32; 3]>,
+}
+
+impl Unwrapper {
+    /// Create an unwrapper that has not seen any frames
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Unwrap the coordinates of the next frame in place
+    ///
+    /// Returns `Error::WrongSizeFrame` if the number of atoms differs from the
+    /// previous frames.
+    pub fn unwrap(&mut self, frame: &mut Frame) -> Result<()> {
+        let prev = match &mut self.prev {
+            Some(prev) => prev,
+            None => {
+                self.prev = Some(frame.coords.clone());
+                self.images = vec![[0; 3]; frame.len()];
+                return Ok(());
+            }
+        };
+        if prev.len() != frame.len() {
+            return Err((&*frame, prev.len()).into());
+        }
+
+        let box_vector = frame.box_vector;
+        for ((x, x_prev), images) in frame
+            .coords
+            .iter_mut()
+            .zip(prev.iter_mut())
+            .zip(&mut self.images)
+        {
+            let mut d = [x[0] - x_prev[0], x[1] - x_prev[1], x[2] - x_prev[2]];
+            *x_prev = *x;
+            for m in (0..3).rev() {
+                let length = box_vector[m][m];
+                if length > 0.0 {
+                    let shift = (d[m] / length).round();
+                    if shift != 0.0 {
+                        for (di, bi) in d.iter_mut().zip(&box_vector[m]) {
+                            *di -= shift * bi;
+                        }
+                        images[m] -= shift as i32;
+                    }
+                }
+            }
+            for (m, &n) in images.iter().enumerate() {
+                if n != 0 {
+                    for (xi, bi) in x.iter_mut().zip(&box_vector[m]) {
+                        *xi += n as f32 * bi;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of boundary crossings along each box vector for every atom
+    pub fn images(&self) -> &[[i32; 3]] {
+        &self.images
+    }
+
+    /// Forget all frames seen so far
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap() -> Result<()> {
+        // One atom moving forward in x and one moving backward in y, by 1 per frame
+        let wrapped = [
+            [[8.0, 1.0, 5.0], [5.0, 1.0, 5.0]],
+            [[9.0, 1.0, 5.0], [5.0, 0.0, 5.0]],
+            [[0.0, 1.0, 5.0], [5.0, 9.0, 5.0]],
+            [[1.0, 1.0, 5.0], [5.0, 8.0, 5.0]],
+        ];
+        let mut unwrapper = Unwrapper::new();
+        let mut unwrapped = Vec::new();
+        for coords in wrapped.iter() {
+            let mut frame = Frame {
+                box_vector: [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]],
+                coords: coords.to_vec(),
+                ..Default::default()
+            };
+            unwrapper.unwrap(&mut frame)?;
+            unwrapped.push(frame.coords);
+        }
+
+        for (i, coords) in unwrapped.iter().enumerate() {
+            assert_approx_eq!(coords[0][0], 8.0 + i as f32);
+            assert_approx_eq!(coords[1][1], 1.0 - i as f32);
+            assert_approx_eq!(coords[0][2], 5.0);
+        }
+        assert_eq!(unwrapper.images(), &[[1, 0, 0], [0, -1, 0]]);
+
+        let mut frame = Frame::with_len(3);
+        assert!(matches!(
+            unwrapper.unwrap(&mut frame),
+            Err(Error::WrongSizeFrame { .. })
+        ));
+        unwrapper.reset();
+        unwrapper.unwrap(&mut frame)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Writing trajectories on a background thread
+use crate::*;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Write frames sent over a channel to a new trajectory on a background thread
+///
+/// The file is created by the background thread, which owns the trajectory
+/// exclusively, as trajectory handles cannot be shared between threads. Each
+/// frame sent is written in turn. Once every sender has been dropped, the
+/// file is flushed and the thread returns the number of frames written.
+///
+/// If opening or writing fails, the thread stops and returns the error, and
+/// further sends fail because the receiver is gone. The error is then
+/// available by joining the handle.
+///
+/// ```rust
+/// use xdrfile::*;
+///
+/// # fn main() -> Result<()> {
+/// # let tempfile = tempfile::NamedTempFile::new().unwrap();
+/// # let path = tempfile.path();
+/// let (frames, writer) = spawn_writer(path, Format::Xtc);
+/// for step in 0..10 {
+///     let frame = Frame {
+///         step,
+///         ..Frame::with_len(20)
+///     };
+///     frames.send(frame).expect("Writer thread stopped");
+/// }
+/// drop(frames);
+/// let num_written = writer.join().expect("Writer thread panicked")?;
+/// assert_eq!(num_written, 10);
+/// # Ok(())
+/// # }
+/// ```
+pub fn spawn_writer(
+    path: impl AsRef<Path>,
+    format: Format,
+) -> (Sender<Frame>, JoinHandle<Result<usize>>) {
+    let path = path.as_ref().to_owned();
+    let (sender, receiver) = mpsc::channel::<Frame>();
+    let handle = thread::spawn(move || {
+        let mut traj = format.open(&path, FileMode::Write)?;
+        let mut num_written = 0;
+        for frame in receiver {
+            traj.write(&frame)?;
+            num_written += 1;
+        }
+        traj.flush()?;
+        Ok(num_written)
+    });
+    (sender, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_spawn_writer() -> Result<()> {
+        let tempfile = NamedTempFile::new().expect("Could not create temporary file");
+        for format in [Format::Xtc, Format::Trr] {
+            let (frames, writer) = spawn_writer(tempfile.path(), format);
+            // Frames may come from several producers
+            let producers: Vec<_> = (0..2)
+                .map(|half| {
+                    let frames = frames.clone();
+                    thread::spawn(move || {
+                        for step in (half * 5)..(half * 5 + 5) {
+                            let frame = Frame {
+                                step,
+                                ..Frame::with_len(12)
+                            };
+                            frames.send(frame).expect("Writer thread stopped");
+                        }
+                    })
+                })
+                .collect();
+            for producer in producers {
+                producer.join().expect("Producer panicked");
+            }
+            drop(frames);
+            assert_eq!(writer.join().expect("Writer thread panicked")?, 10);
+
+            let traj = format.open(tempfile.path(), FileMode::Read)?;
+            let steps: Result<Vec<usize>> = traj.into_frames().map(|f| Ok(f?.step)).collect();
+            let mut steps = steps?;
+            steps.sort_unstable();
+            assert_eq!(steps, (0..10).collect::<Vec<_>>());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_writer_error() {
+        let (frames, writer) = spawn_writer("/nonexistent/dir/traj.xtc", Format::Xtc);
+        let err = writer.join().expect("Writer thread panicked").unwrap_err();
+        assert!(matches!(err, Error::CouldNotOpen { .. }));
+        assert!(frames.send(Frame::with_len(1)).is_err());
+    }
+}